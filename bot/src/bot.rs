@@ -0,0 +1,228 @@
+use crate::stats::Stats;
+
+use common::{
+	net::{
+		client::{Auth, InputFrame, PlayerAction, PlayerMiningStatus, ServerBound},
+		packet::Packet,
+		server::{Auth as ServerAuth, ClientBound, WorldUpdate},
+		transport::{ReadHalf, Stream},
+		Face, NetError, VPosition,
+	},
+	physics,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{atomic::Ordering, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+/// How long a bot keeps walking in one direction, in seconds
+const WALK_TIME: (f32, f32) = (1.0, 5.0);
+/// Time between voxel edits
+const EDIT_INTERVAL: Duration = Duration::from_secs(3);
+/// Most sent input frames remembered for measuring latency
+const MAX_UNACKNOWLEDGED: usize = 600;
+/// How long a bot mines a voxel before telling the server it is done
+const MINING_TIME: Duration = Duration::from_secs(1);
+/// Seconds between chat messages from a bot
+const CHAT_TIME: (f32, f32) = (10.0, 30.0);
+/// What bots say, a few are commands any player can run
+const CHAT_LINES: &[&str] = &["hello", "anyone around?", "nice spot", "brb", "/seed", "/help"];
+
+/// What the reading half of a bot has learnt from the server
+#[derive(Default)]
+struct Shared {
+	/// Input frames that haven't been acknowledged yet and when they were sent
+	sent: VecDeque<(u32, Instant)>,
+	pos: (f64, f64, f64),
+	on_ground: bool,
+	disconnected: bool,
+}
+
+/// A simulated player that logs in and then wanders around and edits the world until it is disconnected
+pub async fn run(id: usize, address: SocketAddr, stats: Arc<Stats>) {
+	let stream = match Stream::connect(address).await {
+		Ok(s) => s,
+		Err(e) => {
+			log::warn!("bot {} failed to connect: {:?}", id, e);
+			stats.disconnects.fetch_add(1, Ordering::Relaxed);
+			return;
+		}
+	};
+	let (reader, mut writer) = stream.into_split();
+
+	// Writing happens on its own task so that a slow server never holds up the bot's inputs
+	let (outgoing, mut outgoing_rx) = unbounded_channel::<ServerBound>();
+	{
+		let stats = stats.clone();
+		tokio::spawn(async move {
+			while let Some(message) = outgoing_rx.recv().await {
+				let packet = Packet::serialize(&message);
+				stats
+					.bytes_out
+					.fetch_add(packet.wire_len() as u64, Ordering::Relaxed);
+				stats.packets_out.fetch_add(1, Ordering::Relaxed);
+				if writer.send_packet(packet).await.is_err() {
+					break;
+				}
+			}
+		});
+	}
+
+	let shared = Arc::new(Mutex::new(Shared::default()));
+	tokio::spawn(read_loop(
+		id,
+		reader,
+		outgoing.clone(),
+		shared.clone(),
+		stats.clone(),
+	));
+
+	let _ = outgoing.send(ServerBound::Auth(Auth::login_request(&format!("bot{}", id))));
+	stats.connected.fetch_add(1, Ordering::Relaxed);
+	act(id, &outgoing, &shared).await;
+	stats.connected.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Sends input frames at the physics rate, turning at random, now and then breaking or placing a voxel and
+/// chatting
+async fn act(id: usize, outgoing: &UnboundedSender<ServerBound>, shared: &Mutex<Shared>) {
+	let mut rng = StdRng::seed_from_u64(id as u64);
+	let mut interval = tokio::time::interval(Duration::from_secs_f64(physics::STEP));
+	let mut frame = InputFrame {
+		forward: true,
+		..InputFrame::default()
+	};
+	let mut turn_at = Instant::now();
+	let mut edit_at = Instant::now() + EDIT_INTERVAL;
+	let mut mining: Option<(VPosition, Instant)> = None;
+	let mut chat_at = Instant::now() + Duration::from_secs_f32(rng.gen_range(CHAT_TIME.0, CHAT_TIME.1));
+
+	loop {
+		interval.tick().await;
+		let now = Instant::now();
+		let (pos, on_ground) = {
+			let mut shared = shared.lock().unwrap();
+			if shared.disconnected {
+				return;
+			}
+			if shared.sent.len() == MAX_UNACKNOWLEDGED {
+				shared.sent.pop_front();
+			}
+			shared.sent.push_back((frame.seq, now));
+			(shared.pos, shared.on_ground)
+		};
+
+		if now >= turn_at {
+			frame.yaw = rng.gen_range(0.0, 360.0);
+			frame.sprint = rng.gen_bool(0.3);
+			turn_at = now + Duration::from_secs_f32(rng.gen_range(WALK_TIME.0, WALK_TIME.1));
+		}
+		if now >= chat_at {
+			chat_at = now + Duration::from_secs_f32(rng.gen_range(CHAT_TIME.0, CHAT_TIME.1));
+			let message = CHAT_LINES[rng.gen_range(0, CHAT_LINES.len())].to_owned();
+			let _ = outgoing.send(ServerBound::Chat { message });
+		}
+		frame.jump = on_ground && rng.gen_bool(0.02);
+		if outgoing
+			.send(ServerBound::PlayerAction(PlayerAction::PlayerInput(frame)))
+			.is_err()
+		{
+			return;
+		}
+		frame.seq += 1;
+
+		// Break the voxel under our feet, or place one beside it
+		let below = VPosition::new(
+			pos.0.floor() as i32,
+			pos.1.floor() as i32 - 1,
+			pos.2.floor() as i32,
+		);
+		if let Some((voxel, started)) = mining {
+			if now.duration_since(started) >= MINING_TIME {
+				let _ = outgoing.send(ServerBound::PlayerAction(PlayerAction::PlayerMining {
+					status: PlayerMiningStatus::Completed,
+					voxel,
+					face: Face::Top,
+				}));
+				mining = None;
+			}
+		} else if now >= edit_at {
+			edit_at = now + EDIT_INTERVAL;
+			let action = if rng.gen_bool(0.5) {
+				mining = Some((below, now));
+				PlayerAction::PlayerMining {
+					status: PlayerMiningStatus::Started,
+					voxel: below,
+					face: Face::Top,
+				}
+			} else {
+				let faces = [Face::North, Face::South, Face::East, Face::West];
+				PlayerAction::PlaceVoxel {
+					pos: below,
+					face: faces[rng.gen_range(0, faces.len())],
+				}
+			};
+			let _ = outgoing.send(ServerBound::PlayerAction(action));
+		}
+	}
+}
+
+async fn read_loop(
+	id: usize,
+	mut reader: ReadHalf,
+	outgoing: UnboundedSender<ServerBound>,
+	shared: Arc<Mutex<Shared>>,
+	stats: Arc<Stats>,
+) {
+	let mut own_id = None;
+	let reason = loop {
+		let packet = match reader.recv_packet().await {
+			Ok(p) => p,
+			Err(e) => break format!("{:?}", e),
+		};
+		stats
+			.bytes_in
+			.fetch_add(packet.wire_len() as u64, Ordering::Relaxed);
+		stats.packets_in.fetch_add(1, Ordering::Relaxed);
+
+		match packet.deserialize::<ClientBound>() {
+			Ok(ClientBound::Update(WorldUpdate::PlayerState {
+				seq, pos, on_ground, ..
+			})) => {
+				let mut shared = shared.lock().unwrap();
+				shared.pos = pos;
+				shared.on_ground = on_ground;
+				while let Some((sent_seq, sent_at)) = shared.sent.front().copied() {
+					if sent_seq > seq {
+						break;
+					}
+					shared.sent.pop_front();
+					if sent_seq == seq {
+						stats.add_latency(sent_at.elapsed());
+					}
+				}
+			}
+			Ok(ClientBound::Auth(ServerAuth::LoginSuccess { id, .. })) => own_id = Some(id),
+			Ok(ClientBound::Update(WorldUpdate::EntityTeleport { entity, pos }))
+				if Some(entity) == own_id =>
+			{
+				let pos = pos.to_vec3();
+				shared.lock().unwrap().pos = (pos.x, pos.y, pos.z);
+			}
+			Ok(ClientBound::KeepAlive { id }) => {
+				let _ = outgoing.send(ServerBound::KeepAlive { id });
+			}
+			Ok(ClientBound::Auth(ServerAuth::Disconnect { reason })) => break reason.to_string(),
+			Ok(_) => {}
+			Err(NetError::Deserialize(e)) => break format!("unreadable message: {}", e),
+			Err(e) => break format!("{:?}", e),
+		}
+	};
+
+	log::warn!("bot {} disconnected: {}", id, reason);
+	stats.disconnects.fetch_add(1, Ordering::Relaxed);
+	shared.lock().unwrap().disconnected = true;
+}
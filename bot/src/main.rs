@@ -0,0 +1,85 @@
+mod bot;
+mod stats;
+
+use std::net::SocketAddr;
+use std::sync::{atomic::Ordering, Arc};
+use std::time::Duration;
+
+use stats::Stats;
+
+/// Server the bots join when no address is given
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+/// Time between bots joining, so that the server isn't hit by every login at once
+const JOIN_INTERVAL: Duration = Duration::from_millis(100);
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+fn main() {
+	// Establish our logger
+	simple_logger::SimpleLogger::new()
+		.with_level(log::LevelFilter::Info)
+		.init()
+		.ok();
+
+	let mut args = std::env::args().skip(1);
+	let address: SocketAddr = match args.next().as_deref().unwrap_or(DEFAULT_ADDRESS).parse() {
+		Ok(a) => a,
+		Err(e) => {
+			log::error!("invalid server address: {}", e);
+			log::error!("usage: takh-bot [address] [bots]");
+			std::process::exit(1);
+		}
+	};
+	let count: usize = match args.next().map(|c| c.parse()).unwrap_or(Ok(10)) {
+		Ok(c) => c,
+		Err(e) => {
+			log::error!("invalid number of bots: {}", e);
+			log::error!("usage: takh-bot [address] [bots]");
+			std::process::exit(1);
+		}
+	};
+
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.enable_all()
+		.build()
+		.expect("Failed to build Tokio runtime");
+
+	log::info!("starting {} bots against {}", count, address);
+	let stats = Arc::new(Stats::default());
+	{
+		let stats = stats.clone();
+		runtime.spawn(async move {
+			for id in 0..count {
+				tokio::spawn(bot::run(id, address, stats.clone()));
+				tokio::time::sleep(JOIN_INTERVAL).await;
+			}
+		});
+	}
+
+	let seconds = REPORT_INTERVAL.as_secs_f64();
+	loop {
+		std::thread::sleep(REPORT_INTERVAL);
+		let report = stats.take_report();
+		let latency = match report.latency_avg {
+			Some(avg) => format!("{:.1}ms avg, {:.1}ms max", ms(avg), ms(report.latency_max)),
+			None => "no samples".to_owned(),
+		};
+		log::info!(
+			"{} connected, {} disconnected | latency {} | in {:.1} KiB/s ({:.0} packets/s) | out {:.1} KiB/s ({:.0} packets/s)",
+			report.connected,
+			report.disconnects,
+			latency,
+			report.bytes_in as f64 / 1024.0 / seconds,
+			report.packets_in as f64 / seconds,
+			report.bytes_out as f64 / 1024.0 / seconds,
+			report.packets_out as f64 / seconds,
+		);
+		if report.disconnects as usize >= count && stats.connected.load(Ordering::Relaxed) == 0 {
+			log::info!("every bot has disconnected");
+			break;
+		}
+	}
+}
+
+fn ms(d: Duration) -> f64 {
+	d.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,55 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Counters shared by every bot, read and reset by the reporter
+#[derive(Default)]
+pub struct Stats {
+	pub connected: AtomicU64,
+	pub disconnects: AtomicU64,
+	pub bytes_in: AtomicU64,
+	pub bytes_out: AtomicU64,
+	pub packets_in: AtomicU64,
+	pub packets_out: AtomicU64,
+	/// Sum and count of round trip times in microseconds, from sending an input frame to the server
+	/// acknowledging it
+	latency_total: AtomicU64,
+	latency_samples: AtomicU64,
+	latency_max: AtomicU64,
+}
+
+/// What happened over one reporting period
+pub struct Report {
+	pub connected: u64,
+	pub disconnects: u64,
+	pub bytes_in: u64,
+	pub bytes_out: u64,
+	pub packets_in: u64,
+	pub packets_out: u64,
+	pub latency_avg: Option<Duration>,
+	pub latency_max: Duration,
+}
+
+impl Stats {
+	pub fn add_latency(&self, latency: Duration) {
+		let micros = latency.as_micros() as u64;
+		self.latency_total.fetch_add(micros, Ordering::Relaxed);
+		self.latency_samples.fetch_add(1, Ordering::Relaxed);
+		self.latency_max.fetch_max(micros, Ordering::Relaxed);
+	}
+
+	/// Collects the counters for a report, resetting the ones that are per period
+	pub fn take_report(&self) -> Report {
+		let total = self.latency_total.swap(0, Ordering::Relaxed);
+		let samples = self.latency_samples.swap(0, Ordering::Relaxed);
+		Report {
+			connected: self.connected.load(Ordering::Relaxed),
+			disconnects: self.disconnects.load(Ordering::Relaxed),
+			bytes_in: self.bytes_in.swap(0, Ordering::Relaxed),
+			bytes_out: self.bytes_out.swap(0, Ordering::Relaxed),
+			packets_in: self.packets_in.swap(0, Ordering::Relaxed),
+			packets_out: self.packets_out.swap(0, Ordering::Relaxed),
+			latency_avg: total.checked_div(samples).map(Duration::from_micros),
+			latency_max: Duration::from_micros(self.latency_max.swap(0, Ordering::Relaxed)),
+		}
+	}
+}
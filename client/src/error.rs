@@ -1,4 +1,5 @@
 use crate::render::RenderError;
+use common::net::NetError;
 use image::error::ImageError;
 
 #[derive(Debug)]
@@ -6,6 +7,7 @@ pub enum Error {
 	BackendError(Box<dyn std::fmt::Debug>),
 	RenderError(RenderError),
 	AssetError(Box<dyn std::fmt::Debug>),
+	NetworkError(NetError),
 }
 
 impl From<RenderError> for Error {
@@ -19,3 +21,9 @@ impl From<ImageError> for Error {
 		Error::AssetError(Box::new(e))
 	}
 }
+
+impl From<NetError> for Error {
+	fn from(e: NetError) -> Error {
+		Error::NetworkError(e)
+	}
+}
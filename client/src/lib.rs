@@ -1,5 +1,7 @@
 pub mod error;
+pub mod net;
 pub mod render;
+pub mod resources;
 pub mod scene;
 pub mod settings;
 pub mod state;
@@ -10,6 +12,7 @@ pub use crate::error::Error;
 use std::sync::Arc;
 
 use crate::{
+	resources::Resources,
 	scene::GameScene,
 	settings::Settings,
 	state::{PlayState, PlayStateNext},
@@ -20,6 +23,8 @@ use tokio::runtime::Runtime;
 
 pub struct GlobalState {
 	pub settings: Settings,
+	/// Game data with the resource packs in the settings laid over it
+	pub resources: Resources,
 	pub window: Window,
 	pub runtime: Arc<Runtime>,
 }
@@ -82,13 +87,15 @@ fn handle_main_events_cleared(
 			}
 			PlayStateNext::Pop => {
 				states.pop();
-				states.last_mut().map(|new| new.enter(global_state));
+				if let Some(new) = states.last_mut() {
+					new.enter(global_state)
+				}
 			}
 			PlayStateNext::Push(mut new) => {
 				new.enter(global_state);
 				states.push(new);
 			}
-			PlayStateNext::Quit => while let Some(_) = states.pop() {},
+			PlayStateNext::Quit => while states.pop().is_some() {},
 		}
 	}
 
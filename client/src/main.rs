@@ -1,6 +1,6 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use takh_client::{settings::Settings, window::Window, GlobalState};
+use takh_client::{resources::Resources, settings::Settings, window::Window, GlobalState};
 
 fn main() {
 	// Establish our logger
@@ -11,18 +11,23 @@ fn main() {
 
 	// Load settings
 	let settings = Settings::load();
+	let resources = Resources::load(&settings.resource_packs);
 	// Create our window and opengl context
-	let (window, event_loop) = match Window::new(&settings) {
+	let (mut window, event_loop) = match Window::new(&settings) {
 		Ok(o) => o,
 		Err(e) => {
 			log::error!("failed to create window: {:?}", e);
 			std::process::exit(1);
 		}
 	};
+	window
+		.renderer_mut()
+		.framebuffer
+		.load_passes(&resources, &settings.graphics);
 	// Create our Async runtime
 	let runtime = std::sync::Arc::new(
 		tokio::runtime::Builder::new_multi_thread()
-			.enable_io()
+			.enable_all()
 			.thread_name_fn(|| {
 				static ATOMIC_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
 				let id = ATOMIC_THREAD_ID.fetch_add(1, Ordering::SeqCst);
@@ -34,6 +39,7 @@ fn main() {
 	// Create our global state struct and run the event loop
 	let global_state = GlobalState {
 		settings,
+		resources,
 		window,
 		runtime,
 	};
@@ -0,0 +1,252 @@
+use crate::{
+	scene::interpolation::{push_snapshot, Interpolated, ServerClock},
+	Error,
+};
+
+use common::{
+	components::{Orientation, Player as PlayerComp, Position},
+	net::{
+		client::{Auth, ServerBound},
+		server::{Auth as ServerAuth, ClientBound, ServerStatus, WorldUpdate},
+		transport::Stream,
+		EntityID, NetError, Rotation, PROTOCOL_VERSION,
+	},
+};
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	mpsc::{self, TryRecvError},
+	Arc,
+};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use specs::{Builder, Entity, World, WorldExt};
+use takh_server::{settings::Settings as ServerSettings, Server};
+use tokio::{runtime::Runtime, sync::mpsc::UnboundedSender};
+use vek::Vec3;
+
+/// A connection to a server, messages are read and written on the async runtime and handed over through
+/// channels so the game loop never blocks on the network
+pub struct Connection {
+	incoming: mpsc::Receiver<Result<ClientBound, NetError>>,
+	outgoing: UnboundedSender<ServerBound>,
+}
+
+impl Connection {
+	/// Connects to a server over TCP and sends our login request. The server is asked about itself first so
+	/// that one on another protocol version is given up on before logging in. The connection is closed if
+	/// nothing is heard from the server for `timeout`.
+	pub fn connect(
+		runtime: &Runtime,
+		address: SocketAddr,
+		username: &str,
+		timeout: Duration,
+	) -> Result<Connection, Error> {
+		let (status, ping) = query_status(runtime, address, timeout)?;
+		if status.protocol_version != PROTOCOL_VERSION {
+			return Err(NetError::WrongProtocol(status.protocol_version).into());
+		}
+		log::info!(
+			"Joining {}: {}, {}/{} players online, {} ms away",
+			address,
+			status.motd,
+			status.players,
+			status.max_players,
+			ping.as_millis()
+		);
+
+		let stream = runtime.block_on(async {
+			match tokio::time::timeout(timeout, Stream::connect(address)).await {
+				Ok(stream) => stream,
+				Err(_) => Err(NetError::TimedOut),
+			}
+		})?;
+		Ok(Self::new(runtime, stream, username, timeout))
+	}
+
+	/// Logs in to the server at the other end of `stream`
+	pub fn new(runtime: &Runtime, stream: Stream, username: &str, timeout: Duration) -> Connection {
+		let (mut reader, mut writer) = stream.into_split();
+
+		let (incoming_tx, incoming) = mpsc::channel();
+		runtime.spawn(async move {
+			loop {
+				// The server sends keep-alives, so a quiet connection is a dead one
+				let message = match tokio::time::timeout(timeout, reader.recv::<ClientBound>()).await {
+					Ok(message) => message,
+					Err(_) => Err(NetError::TimedOut),
+				};
+				let closed = message.is_err();
+				if incoming_tx.send(message).is_err() || closed {
+					break;
+				}
+			}
+		});
+
+		let (outgoing, mut outgoing_rx) = tokio::sync::mpsc::unbounded_channel::<ServerBound>();
+		runtime.spawn(async move {
+			while let Some(message) = outgoing_rx.recv().await {
+				if let Err(e) = writer.send(&message).await {
+					log::warn!("failed to send message to server: {:?}", e);
+					break;
+				}
+			}
+		});
+
+		let connection = Connection { incoming, outgoing };
+		connection.send(ServerBound::Auth(Auth::login_request(username)));
+		connection
+	}
+
+	/// Queue a message to be sent to the server
+	pub fn send(&self, message: ServerBound) {
+		let _ = self.outgoing.send(message);
+	}
+
+	/// Returns the next message from the server if one has arrived
+	pub fn try_recv(&self) -> Result<Option<ClientBound>, NetError> {
+		match self.incoming.try_recv() {
+			Ok(Ok(message)) => Ok(Some(message)),
+			Ok(Err(e)) => Err(e),
+			Err(TryRecvError::Empty) => Ok(None),
+			Err(TryRecvError::Disconnected) => Err(NetError::ConnectionClosed),
+		}
+	}
+}
+
+/// Asks the server at `address` about itself without logging in, returning its status and the round trip
+/// time of a ping
+pub fn query_status(
+	runtime: &Runtime,
+	address: SocketAddr,
+	timeout: Duration,
+) -> Result<(ServerStatus, Duration), Error> {
+	let query = async {
+		let (mut reader, mut writer) = Stream::connect(address).await?.into_split();
+		writer.send(&ServerBound::Auth(Auth::StatusRequest)).await?;
+		let status = match reader.recv::<ClientBound>().await? {
+			ClientBound::Auth(ServerAuth::Status(status)) => status,
+			_ => return Err(NetError::ConnectionClosed),
+		};
+
+		let sent = Instant::now();
+		writer.send(&ServerBound::Auth(Auth::Ping { id: 0 })).await?;
+		match reader.recv::<ClientBound>().await? {
+			ClientBound::Auth(ServerAuth::Pong { id: 0 }) => Ok((status, sent.elapsed())),
+			_ => Err(NetError::ConnectionClosed),
+		}
+	};
+	runtime.block_on(async {
+		match tokio::time::timeout(timeout, query).await {
+			Ok(result) => result.map_err(Error::from),
+			Err(_) => Err(NetError::TimedOut.into()),
+		}
+	})
+}
+
+/// A server running on a thread of our own for singleplayer, it is stopped when this is dropped
+pub struct IntegratedServer {
+	running: Arc<AtomicBool>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl IntegratedServer {
+	/// Starts the server and returns a stream connected to it
+	pub fn start(runtime: &Arc<Runtime>) -> Result<(IntegratedServer, Stream), Error> {
+		let (server, connector) = Server::integrated(
+			ServerSettings::load_from(&ServerSettings::singleplayer_path()),
+			runtime.clone(),
+		);
+		let running = Arc::new(AtomicBool::new(true));
+		let thread = {
+			let running = running.clone();
+			std::thread::Builder::new()
+				.name("integrated-server".to_owned())
+				.spawn(move || server.run(running))
+				.map_err(NetError::from)?
+		};
+		let stream = connector.connect()?;
+		Ok((
+			IntegratedServer {
+				running,
+				thread: Some(thread),
+			},
+			stream,
+		))
+	}
+}
+
+impl Drop for IntegratedServer {
+	fn drop(&mut self) {
+		self.running.store(false, Ordering::Relaxed);
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+/// Keeps our ECS in step with the entities the server replicates to us
+#[derive(Default)]
+pub struct EntitySync {
+	entities: HashMap<EntityID, Entity>,
+	/// Time on the server's timeline of the updates being applied, from the last `WorldUpdate::Tick`
+	time: f64,
+}
+
+impl EntitySync {
+	/// Applies an entity update from the server, updates that aren't about entities are ignored
+	pub fn apply(&mut self, ecs: &mut World, update: &WorldUpdate) {
+		match update {
+			WorldUpdate::Tick { tick } => {
+				ecs.write_resource::<ServerClock>().received(*tick);
+				self.time = ServerClock::tick_time(*tick);
+			}
+			WorldUpdate::SpawnPlayer { entity, pos, rot } => {
+				if let Some(old) = self.entities.remove(entity) {
+					let _ = ecs.delete_entity(old);
+				}
+				let local = ecs
+					.create_entity()
+					.with(Position(pos.to_vec3()))
+					.with(Orientation(rotation(rot)))
+					.with(Interpolated::new(pos.to_vec3(), rotation(rot), self.time))
+					.with(PlayerComp)
+					.build();
+				self.entities.insert(*entity, local);
+			}
+			WorldUpdate::EntityTransform { entity, pos, rot } => {
+				if let Some(local) = self.entities.get(entity) {
+					if let Some(p) = ecs.write_storage::<Position>().get_mut(*local) {
+						p.0 += WorldUpdate::transform_delta(*pos);
+					}
+					if let Some(o) = ecs.write_storage::<Orientation>().get_mut(*local) {
+						o.0 = rotation(rot);
+					}
+					push_snapshot(ecs, *local, self.time, false);
+				}
+			}
+			WorldUpdate::EntityTeleport { entity, pos } => {
+				if let Some(local) = self.entities.get(entity) {
+					if let Some(p) = ecs.write_storage::<Position>().get_mut(*local) {
+						p.0 = pos.to_vec3();
+					}
+					push_snapshot(ecs, *local, self.time, true);
+				}
+			}
+			WorldUpdate::DespawnEntity { entity } => {
+				if let Some(local) = self.entities.remove(entity) {
+					let _ = ecs.delete_entity(local);
+				}
+			}
+			_ => {}
+		}
+	}
+}
+
+fn rotation(rot: &Rotation) -> Vec3<f64> {
+	let (yaw, pitch) = rot.to_degrees();
+	Vec3::new(yaw, pitch, 0.0)
+}
@@ -7,14 +7,14 @@ use std::path::Path;
 
 fn main() {
 	let out_dir = env::var("OUT_DIR").unwrap();
-	let mut file = File::create(&Path::new(&out_dir).join("bindings.rs")).unwrap();
+	let mut file = File::create(Path::new(&out_dir).join("bindings.rs")).unwrap();
 
 	Registry::new(
 		Api::Gl,
 		(4, 5),
 		Profile::Core,
 		Fallbacks::All,
-		["GL_ARB_direct_state_access"],
+		["GL_ARB_direct_state_access", "GL_ARB_texture_filter_anisotropic"],
 	)
 	.write_bindings(GlobalGenerator, &mut file)
 	.unwrap();
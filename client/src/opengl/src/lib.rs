@@ -1,3 +1,4 @@
+#[allow(clippy::all)]
 mod bindings {
 	include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
@@ -1,133 +1,174 @@
 use crate::render::{
 	mesh::{data, Mesh, Vertex},
-	shader::Program,
+	preprocess::Defines,
+	shader::{Program, Shader, ShaderKind},
 };
+use crate::{resources::Resources, settings::GraphicsSettings, Error};
 
-pub struct Framebuffer {
+use std::rc::Rc;
+
+/// Somewhere to draw to whose colour and depth can be read back as textures
+struct RenderTarget {
 	fbo: gl::types::GLuint,
-	rbo: gl::types::GLuint,
-	texture_buf: gl::types::GLuint,
-	quad: Mesh<PostprocessVertex>,
-	shader: Program,
+	colour: gl::types::GLuint,
+	depth: Option<gl::types::GLuint>,
 }
 
-static BASIC_VS: &'static str = include_str!("../../../res/client/shaders/postprocess/basic.vs");
-static BASIC_FS: &'static str = include_str!("../../../res/client/shaders/postprocess/basic.fs");
-
-impl Framebuffer {
-	pub fn new(w: u32, h: u32) -> Framebuffer {
-		let quad = Mesh::new(&SCREEN_QUAD);
-		let shader = Program::from_vert_and_frag(BASIC_VS, BASIC_FS).unwrap();
-
+impl RenderTarget {
+	fn new(w: u32, h: u32, with_depth: bool) -> RenderTarget {
+		let (w, h) = (w.max(1) as i32, h.max(1) as i32);
 		let mut fbo = 0;
+		let mut colour = 0;
 		unsafe {
-			gl::GenFramebuffers(1, &mut fbo);
-			gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+			gl::CreateFramebuffers(1, &mut fbo);
+			gl::CreateTextures(gl::TEXTURE_2D, 1, &mut colour);
+			// Half floats so that colours brighter than white survive until they are tonemapped
+			gl::TextureStorage2D(colour, 1, gl::RGBA16F, w, h);
+			gl::TextureParameteri(colour, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+			gl::TextureParameteri(colour, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+			gl::TextureParameteri(colour, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+			gl::TextureParameteri(colour, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+			gl::NamedFramebufferTexture(fbo, gl::COLOR_ATTACHMENT0, colour, 0);
 		}
-		let mut texture_buf = 0;
+		let depth = with_depth.then(|| {
+			let mut depth = 0;
+			unsafe {
+				gl::CreateTextures(gl::TEXTURE_2D, 1, &mut depth);
+				gl::TextureStorage2D(depth, 1, gl::DEPTH24_STENCIL8, w, h);
+				gl::TextureParameteri(depth, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+				gl::TextureParameteri(depth, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+				gl::NamedFramebufferTexture(fbo, gl::DEPTH_STENCIL_ATTACHMENT, depth, 0);
+			}
+			depth
+		});
 		unsafe {
-			gl::GenTextures(1, &mut texture_buf);
-			gl::BindTexture(gl::TEXTURE_2D, texture_buf);
-			gl::TexImage2D(
-				gl::TEXTURE_2D,
-				0,
-				gl::RGB8 as i32,
-				w as i32,
-				h as i32,
-				0,
-				gl::RGB,
-				gl::UNSIGNED_BYTE,
-				std::ptr::null(),
-			);
-			gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-			gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-			gl::FramebufferTexture2D(
-				gl::FRAMEBUFFER,
-				gl::COLOR_ATTACHMENT0,
-				gl::TEXTURE_2D,
-				texture_buf,
-				0,
-			)
+			if gl::CheckNamedFramebufferStatus(fbo, gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+				log::error!("Framebuffer is not complete");
+			}
 		}
-		let mut rbo = 0;
+		RenderTarget { fbo, colour, depth }
+	}
+}
+
+impl std::ops::Drop for RenderTarget {
+	fn drop(&mut self) {
 		unsafe {
-			gl::GenRenderbuffers(1, &mut rbo);
-			gl::BindRenderbuffer(gl::RENDERBUFFER, rbo);
-			gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, w as i32, h as i32);
-			gl::FramebufferRenderbuffer(
-				gl::FRAMEBUFFER,
-				gl::DEPTH_STENCIL_ATTACHMENT,
-				gl::RENDERBUFFER,
-				rbo,
-			);
-			if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
-				log::error!("Framebuffer is not complete");
+			if let Some(depth) = &self.depth {
+				gl::DeleteTextures(1, depth);
 			}
+			gl::DeleteTextures(1, &self.colour);
+			gl::DeleteFramebuffers(1, &self.fbo);
 		}
+	}
+}
 
+/// Where the world is drawn before the post-processing passes are applied to it on the way to the screen.
+/// Each pass is a fragment shader reading the last one's output from `screen_tex` and the depth of the
+/// world from `depth_tex`.
+pub struct Framebuffer {
+	/// The world is drawn here
+	scene: RenderTarget,
+	/// Passes take turns drawing into one of these from the other, the last draws to the screen
+	ping_pong: [RenderTarget; 2],
+	quad: Mesh<PostprocessVertex>,
+	passes: Vec<Rc<Program>>,
+	/// Copies the world to the screen when there are no passes
+	copy: Rc<Program>,
+}
+
+static BASIC_VS: &str = include_str!("../../../res/client/shaders/postprocess/basic.vs");
+static BASIC_FS: &str = include_str!("../../../res/client/shaders/postprocess/basic.fs");
+
+impl Framebuffer {
+	pub fn new(w: u32, h: u32) -> Framebuffer {
 		Framebuffer {
-			fbo,
-			rbo,
-			texture_buf,
-			quad,
-			shader,
+			scene: RenderTarget::new(w, h, true),
+			ping_pong: [RenderTarget::new(w, h, false), RenderTarget::new(w, h, false)],
+			quad: Mesh::new(&SCREEN_QUAD),
+			passes: Vec::new(),
+			copy: Rc::new(Program::from_vert_and_frag(BASIC_VS, BASIC_FS).unwrap()),
 		}
 	}
 
-	/// Set shader program to use as our postprocessing effect, a value of `None` will
-	/// reset to default shader program
-	pub fn shader_program(&mut self, program: Option<Program>) {
-		if program.is_some() {
-			self.shader = program.unwrap();
-			self.shader.bind();
-			self.shader.set_uniform_int("screen_tex", 0);
-		}
-		self.shader = Program::from_vert_and_frag(BASIC_VS, BASIC_FS).unwrap();
+	/// Loads the post-processing passes in the settings. Passes that fail to load are left out.
+	pub fn load_passes(&mut self, resources: &Resources, graphics: &GraphicsSettings) {
+		// Debug formatting keeps the decimal point, so GLSL sees a float
+		let defines = Defines::new().with("GAMMA", format!("{:?}", graphics.gamma));
+		let passes = graphics
+			.postprocess
+			.iter()
+			.filter_map(|pass| {
+				let fragment = format!("client/shaders/postprocess/{}.fs", pass.shader_name());
+				let program = || -> Result<Program, Error> {
+					Ok(Program::from_shaders(&[
+						Shader::from_resource(
+							resources,
+							"client/shaders/postprocess/basic.vs",
+							ShaderKind::Vertex,
+							&defines,
+						)?,
+						Shader::from_resource(resources, &fragment, ShaderKind::Fragement, &defines)?,
+					])?)
+				};
+				program()
+					.map_err(|e| log::error!("Leaving out post-processing pass {:?}: {:?}", pass, e))
+					.ok()
+			})
+			.map(Rc::new)
+			.collect();
+		self.set_passes(passes);
+	}
+
+	/// Sets the passes the world goes through on the way to the screen, in order. With none it is copied
+	/// as it is.
+	pub fn set_passes(&mut self, passes: Vec<Rc<Program>>) {
+		self.passes = passes;
+	}
+
+	pub fn passes(&self) -> &[Rc<Program>] {
+		&self.passes
 	}
 
 	pub fn resize(&mut self, w: u32, h: u32) {
-		unsafe {
-			gl::TexImage2D(
-				gl::TEXTURE_2D,
-				0,
-				gl::RGB8 as i32,
-				w as i32,
-				h as i32,
-				0,
-				gl::RGB,
-				gl::UNSIGNED_BYTE,
-				std::ptr::null(),
-			);
-			gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, w as i32, h as i32);
-		}
+		// Texture storage can't change size, so the targets are made again
+		self.scene = RenderTarget::new(w, h, true);
+		self.ping_pong = [RenderTarget::new(w, h, false), RenderTarget::new(w, h, false)];
 	}
 
 	/// Bind our framebuffer for off screen drawing of screen
 	pub fn bind(&self) {
 		unsafe {
-			gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+			gl::BindFramebuffer(gl::FRAMEBUFFER, self.scene.fbo);
 			gl::Enable(gl::DEPTH_TEST);
 			gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 		}
 	}
 
-	/// Draw our texture and render that to our main frame buffer
+	/// Runs the world through each pass and draws the result to our main frame buffer
 	pub fn draw(&self) {
+		let passes = if self.passes.is_empty() {
+			std::slice::from_ref(&self.copy)
+		} else {
+			&self.passes[..]
+		};
 		unsafe {
-			gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
 			gl::Disable(gl::DEPTH_TEST);
+			gl::BindTextureUnit(1, self.scene.depth.unwrap_or(0));
 		}
-		self.shader.bind();
-		self.quad.render();
-	}
-}
-
-impl std::ops::Drop for Framebuffer {
-	fn drop(&mut self) {
-		unsafe {
-			gl::DeleteRenderbuffers(1, &self.rbo);
-			gl::DeleteTextures(1, &self.texture_buf);
-			gl::DeleteFramebuffers(1, &self.fbo);
+		let mut input = self.scene.colour;
+		for (i, pass) in passes.iter().enumerate() {
+			let output = &self.ping_pong[i % 2];
+			let last = i + 1 == passes.len();
+			unsafe {
+				gl::BindFramebuffer(gl::FRAMEBUFFER, if last { 0 } else { output.fbo });
+				gl::BindTextureUnit(0, input);
+			}
+			pass.bind();
+			// Set every frame as reloading a pass forgets them
+			pass.set_uniform_int("screen_tex", 0);
+			pass.set_uniform_int("depth_tex", 1);
+			self.quad.render();
+			input = output.colour;
 		}
 	}
 }
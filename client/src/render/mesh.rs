@@ -19,7 +19,7 @@ impl<V: Vertex> Mesh<V> {
 			gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
 			gl::BufferData(
 				gl::ARRAY_BUFFER,
-				(data.len() * std::mem::size_of::<V>()) as gl::types::GLsizeiptr,
+				std::mem::size_of_val(data) as gl::types::GLsizeiptr,
 				data.as_ptr() as *const gl::types::GLvoid,
 				gl::STATIC_DRAW,
 			);
@@ -62,6 +62,12 @@ pub struct MeshBuilder<V: Vertex> {
 	vertices: Vec<V>,
 }
 
+impl<V: Vertex + Copy> Default for MeshBuilder<V> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl<V: Vertex + Copy> MeshBuilder<V> {
 	pub fn new() -> MeshBuilder<V> {
 		MeshBuilder { vertices: vec![] }
@@ -85,13 +91,22 @@ impl<V: Vertex + Copy> MeshBuilder<V> {
 		self.vertices.push(*v2);
 	}
 
+	/// The number of vertices pushed so far
+	pub fn len(&self) -> usize {
+		self.vertices.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.vertices.is_empty()
+	}
+
 	pub fn build(self) -> Mesh<V> {
 		Mesh::new(&self.vertices)
 	}
 }
 
 // TODO: proc macro this
-#[allow(dead_code)]
+#[allow(dead_code, clippy::missing_safety_doc)]
 pub mod data {
 	impl u8_2 {
 		pub const fn new(d0: u8, d1: u8) -> Self {
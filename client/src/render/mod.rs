@@ -1,8 +1,10 @@
 mod error;
 pub mod framebuffer;
 pub mod mesh;
+pub mod preprocess;
 pub mod shader;
 pub mod texture;
+pub mod watcher;
 
 pub use error::RenderError;
 
@@ -15,7 +17,7 @@ pub struct Renderer {
 
 impl Renderer {
 	pub fn new(context: glutin::RawContext<glutin::PossiblyCurrent>, win_size: [u32; 2]) -> Renderer {
-		let _ = gl::load_with(|s| context.get_proc_address(s));
+		gl::load_with(|s| context.get_proc_address(s));
 		if cfg!(debug_assertions) {
 			unsafe {
 				gl::Enable(gl::DEBUG_OUTPUT);
@@ -0,0 +1,259 @@
+use crate::{render::RenderError, Error};
+
+use std::fmt::Display;
+
+/// `#define`s given to a shader by the game, such as quality settings
+#[derive(Clone, Default, Debug)]
+pub struct Defines(Vec<(String, String)>);
+
+impl Defines {
+	pub fn new() -> Defines {
+		Defines::default()
+	}
+
+	pub fn with(mut self, name: &str, value: impl Display) -> Defines {
+		self.0.push((name.to_owned(), value.to_string()));
+		self
+	}
+}
+
+/// Shader source with its includes pasted in and defines added, along with where each line came from
+pub struct Preprocessed {
+	pub source: String,
+	/// Names of the files read, the shader's own first
+	pub files: Vec<String>,
+	/// For each line of `source` the file it came from and its line number there, `None` for lines added
+	/// by the preprocessor
+	lines: Vec<Option<(usize, u32)>>,
+}
+
+impl Preprocessed {
+	/// Preprocesses the shader `name`, getting the contents of it and the files it includes from `load`.
+	/// `#include "file"` is relative to the file it is in and each file is only included once.
+	pub fn new(
+		name: &str,
+		defines: &Defines,
+		mut load: impl FnMut(&str) -> Result<String, Error>,
+	) -> Result<Preprocessed, Error> {
+		let mut preprocessed = Preprocessed {
+			source: String::new(),
+			files: Vec::new(),
+			lines: Vec::new(),
+		};
+		preprocessed.include(name, defines, &mut load, &mut Vec::new())?;
+		Ok(preprocessed)
+	}
+
+	/// `stack` is the files being included, to catch files that include themselves
+	fn include(
+		&mut self,
+		name: &str,
+		defines: &Defines,
+		load: &mut impl FnMut(&str) -> Result<String, Error>,
+		stack: &mut Vec<String>,
+	) -> Result<(), Error> {
+		if stack.iter().any(|file| file == name) {
+			return Err(RenderError::Shader(format!("{} includes itself", name)).into());
+		}
+		if self.files.iter().any(|file| file == name) {
+			return Ok(());
+		}
+		let text = load(name)?;
+		let file = self.files.len();
+		self.files.push(name.to_owned());
+		stack.push(name.to_owned());
+
+		for (number, line) in (1..).zip(text.lines()) {
+			let directive = line.trim_start();
+			if let Some(include) = directive.strip_prefix("#include") {
+				let include = include
+					.trim()
+					.strip_prefix('"')
+					.and_then(|include| include.strip_suffix('"'))
+					.ok_or_else(|| {
+						RenderError::Shader(format!("{}:{}: expected #include \"file\"", name, number))
+					})?;
+				self.include(&relative_to(name, include), defines, load, stack)?;
+			} else {
+				self.push_line(line, Some((file, number)));
+				// Defines go straight after `#version` in the shader itself, as nothing may come before it
+				if directive.starts_with("#version") && stack.len() == 1 {
+					for (define, value) in &defines.0 {
+						self.push_line(&format!("#define {} {}", define, value), None);
+					}
+				}
+			}
+		}
+		stack.pop();
+		Ok(())
+	}
+
+	fn push_line(&mut self, line: &str, origin: Option<(usize, u32)>) {
+		self.source.push_str(line);
+		self.source.push('\n');
+		self.lines.push(origin);
+	}
+
+	/// Rewrites the line numbers in a compile log to the files and lines they came from. Understands the
+	/// `0:12(5)`, `0(12)` and `ERROR: 0:12` forms drivers use.
+	pub fn map_log(&self, log: &str) -> String {
+		log.lines()
+			.map(|line| match line_reference(line) {
+				Some((start, end, number)) => {
+					let origin = number
+						.checked_sub(1)
+						.and_then(|index| self.lines.get(index as usize).copied().flatten());
+					match origin {
+						Some((file, number)) => {
+							format!(
+								"{}{}:{}{}",
+								&line[..start],
+								self.files[file],
+								number,
+								&line[end..]
+							)
+						}
+						None => line.to_owned(),
+					}
+				}
+				None => line.to_owned(),
+			})
+			.collect::<Vec<_>>()
+			.join("\n")
+	}
+}
+
+/// Finds the `<source>:<line>` or `<source>(<line>)` at the start of a line of a compile log, giving
+/// where it starts and ends along with the line number
+fn line_reference(line: &str) -> Option<(usize, usize, u32)> {
+	let start = line.find(|c: char| c.is_ascii_digit())?;
+	// Anything before the reference is a word like `ERROR: `
+	let prefix = &line[..start];
+	if !prefix.is_empty()
+		&& !prefix
+			.strip_suffix(": ")
+			.is_some_and(|word| word.chars().all(|c| c.is_ascii_alphabetic()))
+	{
+		return None;
+	}
+	let rest = &line[start..];
+	let source_end = rest.find(|c: char| !c.is_ascii_digit())?;
+	let (open, close) = match rest[source_end..].chars().next()? {
+		':' => (':', None),
+		'(' => ('(', Some(')')),
+		_ => return None,
+	};
+	let after_open = source_end + open.len_utf8();
+	let digits = rest[after_open..]
+		.find(|c: char| !c.is_ascii_digit())
+		.unwrap_or(rest.len() - after_open);
+	let number = rest[after_open..after_open + digits].parse().ok()?;
+	let mut end = after_open + digits;
+	if let Some(close) = close {
+		if !rest[end..].starts_with(close) {
+			return None;
+		}
+		end += close.len_utf8();
+	}
+	Some((start, start + end, number))
+}
+
+/// The name of the file `include` refers to from inside of `name`, both with `/` between directories
+fn relative_to(name: &str, include: &str) -> String {
+	let mut parts: Vec<&str> = name.split('/').collect();
+	parts.pop();
+	for part in include.split('/') {
+		match part {
+			"" | "." => {}
+			".." => {
+				parts.pop();
+			}
+			part => parts.push(part),
+		}
+	}
+	parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Preprocesses `name` out of `files`, given as file names and their contents
+	fn preprocess(name: &str, defines: &Defines, files: &[(&str, &str)]) -> Result<Preprocessed, Error> {
+		Preprocessed::new(name, defines, |file| {
+			files
+				.iter()
+				.find(|(name, _)| *name == file)
+				.map(|(_, text)| text.to_string())
+				.ok_or_else(|| RenderError::Shader(format!("{}: not found", file)).into())
+		})
+	}
+
+	/// A shader including a file from a directory next to it, with two defines
+	fn shader() -> Preprocessed {
+		let defines = Defines::new().with("FOG", 1).with("SHADOWS", 0);
+		preprocess(
+			"shaders/main.fs",
+			&defines,
+			&[
+				(
+					"shaders/main.fs",
+					"#version 450\n#include \"lib/fog.glsl\"\nvoid main() {\n\tbroken\n}",
+				),
+				("shaders/lib/fog.glsl", "float fog() {\n\tbad\n}"),
+			],
+		)
+		.unwrap()
+	}
+
+	#[test]
+	fn puts_defines_right_after_version() {
+		let shader = shader();
+		let lines: Vec<&str> = shader.source.lines().collect();
+		assert_eq!(
+			lines[..4],
+			[
+				"#version 450",
+				"#define FOG 1",
+				"#define SHADOWS 0",
+				"float fog() {"
+			]
+		);
+		assert_eq!(shader.files, ["shaders/main.fs", "shaders/lib/fog.glsl"]);
+	}
+
+	#[test]
+	fn maps_log_lines_after_an_include_back_to_their_file() {
+		let shader = shader();
+		// Line 8 of the source is line 4 of the shader, after the version, 2 defines and 3 included lines
+		assert_eq!(
+			shader.map_log("0:8(5): error: syntax error"),
+			"shaders/main.fs:4(5): error: syntax error"
+		);
+		assert_eq!(
+			shader.map_log("0(8) : error C0000: syntax error"),
+			"shaders/main.fs:4 : error C0000: syntax error"
+		);
+		assert_eq!(
+			shader.map_log("ERROR: 0:5: 'bad' : undeclared identifier"),
+			"ERROR: shaders/lib/fog.glsl:2: 'bad' : undeclared identifier"
+		);
+		// Lines the preprocessor added have nowhere to go back to
+		assert_eq!(shader.map_log("0:2(1): warning"), "0:2(1): warning");
+	}
+
+	#[test]
+	fn rejects_files_including_themselves() {
+		let result = preprocess(
+			"loop.glsl",
+			&Defines::new(),
+			&[("loop.glsl", "#version 450\n#include \"loop.glsl\"")],
+		);
+		match result {
+			Err(Error::RenderError(RenderError::Shader(message))) => {
+				assert_eq!(message, "loop.glsl includes itself")
+			}
+			_ => panic!("the self include was let through"),
+		}
+	}
+}
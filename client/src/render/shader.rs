@@ -1,8 +1,17 @@
-use crate::render::RenderError;
+use crate::{
+	render::{
+		preprocess::{Defines, Preprocessed},
+		RenderError,
+	},
+	resources::Resources,
+	Error,
+};
 
+use std::cell::{Cell, RefCell};
 use std::ffi::CString;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+#[derive(Clone, Copy, Debug)]
 pub enum ShaderKind {
 	Vertex,
 	Fragement,
@@ -22,6 +31,32 @@ impl ShaderKind {
 
 pub struct Shader {
 	id: gl::types::GLuint,
+	/// Where the shader was loaded from, if it wasn't given as a string
+	source: Option<ShaderSource>,
+}
+
+/// How a shader was loaded, kept so that it can be loaded again
+#[derive(Clone)]
+struct ShaderSource {
+	origin: Origin,
+	kind: ShaderKind,
+	/// Files on disk that went into the shader, includes and all
+	files: Vec<PathBuf>,
+}
+
+#[derive(Clone)]
+enum Origin {
+	File(PathBuf),
+	Resource { name: String, defines: Defines },
+}
+
+impl ShaderSource {
+	fn load(&self, resources: &Resources) -> Result<Shader, Error> {
+		match &self.origin {
+			Origin::File(path) => Shader::from_file(path, self.kind),
+			Origin::Resource { name, defines } => Shader::from_resource(resources, name, self.kind, defines),
+		}
+	}
 }
 
 impl Shader {
@@ -53,18 +88,64 @@ impl Shader {
 					std::ptr::null_mut(),
 					error.as_ptr() as *mut gl::types::GLchar,
 				);
+				gl::DeleteShader(id);
 				return Err(RenderError::Shader(error.into_string().unwrap()));
 			}
 		}
-		Ok(Shader { id })
+		Ok(Shader { id, source: None })
 	}
 
-	pub fn from_file<P: AsRef<Path>>(path: P, kind: ShaderKind) -> Result<Shader, RenderError> {
-		let source = std::fs::read_to_string(path.as_ref());
-		match source {
-			Ok(s) => Self::from_str(&s, kind),
-			Err(e) => Err(RenderError::Shader(format!("io error: {:?}", e))), // stringly typed error ew
-		}
+	/// Loads a shader from a file, includes are found relative to it
+	pub fn from_file<P: AsRef<Path>>(path: P, kind: ShaderKind) -> Result<Shader, Error> {
+		let path = path.as_ref();
+		let (mut shader, files) =
+			Self::preprocess_and_compile(&path.to_string_lossy(), kind, &Defines::new(), |file| {
+				std::fs::read_to_string(file)
+					.map_err(|e| RenderError::Shader(format!("{}: {}", file, e)).into())
+			})?;
+		shader.source = Some(ShaderSource {
+			origin: Origin::File(path.to_owned()),
+			kind,
+			files: files.into_iter().map(PathBuf::from).collect(),
+		});
+		Ok(shader)
+	}
+
+	/// Loads the shader `name` from the game data with `defines` added to it, includes are found in the
+	/// game data too
+	pub fn from_resource(
+		resources: &Resources,
+		name: &str,
+		kind: ShaderKind,
+		defines: &Defines,
+	) -> Result<Shader, Error> {
+		let (mut shader, files) =
+			Self::preprocess_and_compile(name, kind, defines, |file| resources.read_to_string(file))?;
+		shader.source = Some(ShaderSource {
+			origin: Origin::Resource {
+				name: name.to_owned(),
+				defines: defines.clone(),
+			},
+			kind,
+			// Files in zipped resource packs can't be watched for changes
+			files: files.iter().filter_map(|file| resources.path(file)).collect(),
+		});
+		Ok(shader)
+	}
+
+	/// Compiles the shader `name` once its includes and defines are in place, also giving the names of
+	/// every file that went into it
+	fn preprocess_and_compile(
+		name: &str,
+		kind: ShaderKind,
+		defines: &Defines,
+		load: impl FnMut(&str) -> Result<String, Error>,
+	) -> Result<(Shader, Vec<String>), Error> {
+		let preprocessed = Preprocessed::new(name, defines, load)?;
+		let shader = Self::from_str(&preprocessed.source, kind).map_err(|RenderError::Shader(log)| {
+			RenderError::Shader(format!("{}: {}", name, preprocessed.map_log(&log)))
+		})?;
+		Ok((shader, preprocessed.files))
 	}
 
 	pub fn id(&self) -> gl::types::GLuint {
@@ -81,11 +162,25 @@ impl core::ops::Drop for Shader {
 }
 
 pub struct Program {
-	id: gl::types::GLuint,
+	/// Changes when the program is reloaded
+	id: Cell<gl::types::GLuint>,
+	/// Where each of the program's shaders came from, empty unless none were given as strings
+	sources: RefCell<Vec<ShaderSource>>,
 }
 
 impl Program {
 	pub fn from_shaders(shaders: &[Shader]) -> Result<Program, RenderError> {
+		let sources = shaders
+			.iter()
+			.map(|shader| shader.source.clone())
+			.collect::<Option<_>>();
+		Ok(Program {
+			id: Cell::new(Self::link(shaders)?),
+			sources: RefCell::new(sources.unwrap_or_default()),
+		})
+	}
+
+	fn link(shaders: &[Shader]) -> Result<gl::types::GLuint, RenderError> {
 		let id = unsafe { gl::CreateProgram() };
 		unsafe {
 			for shader in shaders {
@@ -115,38 +210,94 @@ impl Program {
 					std::ptr::null_mut(),
 					error.as_ptr() as *mut gl::types::GLchar,
 				);
+				gl::DeleteProgram(id);
 				return Err(RenderError::Shader(error.into_string().unwrap()));
 			}
 		}
-		Ok(Program { id })
+		Ok(id)
 	}
+
 	pub fn from_vert_and_frag(vert: &str, frag: &str) -> Result<Program, RenderError> {
 		let vert = Shader::from_str(vert, ShaderKind::Vertex)?;
 		let frag = Shader::from_str(frag, ShaderKind::Fragement)?;
 		Self::from_shaders(&[vert, frag])
 	}
 
+	/// Files on disk the program was built from, includes and all
+	pub fn files(&self) -> Vec<PathBuf> {
+		self.sources
+			.borrow()
+			.iter()
+			.flat_map(|source| source.files.iter().cloned())
+			.collect()
+	}
+
+	/// Builds the program again from where its shaders were loaded from. If that fails the program is left
+	/// as it was, and uniforms have to be set again when it doesn't.
+	pub fn reload(&self, resources: &Resources) -> Result<(), Error> {
+		let shaders = self
+			.sources
+			.borrow()
+			.iter()
+			.map(|source| source.load(resources))
+			.collect::<Result<Vec<_>, _>>()?;
+		let id = Self::link(&shaders)?;
+		unsafe {
+			gl::DeleteProgram(self.id.replace(id));
+		}
+		// Includes may have been added or taken away
+		*self.sources.borrow_mut() = shaders
+			.iter()
+			.filter_map(|shader| shader.source.clone())
+			.collect();
+		Ok(())
+	}
+
 	pub fn bind(&self) {
-		unsafe { gl::UseProgram(self.id) }
+		unsafe { gl::UseProgram(self.id.get()) }
 	}
 
 	pub fn set_uniform_int(&self, name: &str, val: i32) {
 		let cstr = CString::new(name).unwrap();
 		unsafe {
 			gl::ProgramUniform1i(
-				self.id,
-				gl::GetUniformLocation(self.id, cstr.as_ptr() as *const gl::types::GLchar),
+				self.id.get(),
+				gl::GetUniformLocation(self.id.get(), cstr.as_ptr() as *const gl::types::GLchar),
+				val,
+			);
+		}
+	}
+
+	pub fn set_uniform_float(&self, name: &str, val: f32) {
+		let cstr = CString::new(name).unwrap();
+		unsafe {
+			gl::ProgramUniform1f(
+				self.id.get(),
+				gl::GetUniformLocation(self.id.get(), cstr.as_ptr() as *const gl::types::GLchar),
 				val,
 			);
 		}
 	}
 
+	pub fn set_uniform_vec3(&self, name: &str, val: vek::Vec3<f32>) {
+		let cstr = CString::new(name).unwrap();
+		unsafe {
+			gl::ProgramUniform3f(
+				self.id.get(),
+				gl::GetUniformLocation(self.id.get(), cstr.as_ptr() as *const gl::types::GLchar),
+				val.x,
+				val.y,
+				val.z,
+			);
+		}
+	}
+
 	pub fn set_uniform_mat4(&self, name: &str, mat: vek::Mat4<f32>) {
 		let cstr = CString::new(name).unwrap();
 		unsafe {
 			gl::ProgramUniformMatrix4fv(
-				self.id,
-				gl::GetUniformLocation(self.id, cstr.as_ptr() as *const gl::types::GLchar),
+				self.id.get(),
+				gl::GetUniformLocation(self.id.get(), cstr.as_ptr() as *const gl::types::GLchar),
 				1,
 				gl::FALSE,
 				mat.as_col_ptr(),
@@ -158,7 +309,7 @@ impl Program {
 impl core::ops::Drop for Program {
 	fn drop(&mut self) {
 		unsafe {
-			gl::DeleteProgram(self.id);
+			gl::DeleteProgram(self.id.get());
 		}
 	}
 }
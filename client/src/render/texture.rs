@@ -1,47 +1,149 @@
-use crate::Error;
+use crate::{resources::Resources, settings::TextureFilter, Error};
 
-use std::path::Path;
+use common::world::voxel::{TextureId, Transparency};
 
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub enum AtlasError {
+	/// The directory had no textures in it
+	Empty(String),
+	/// Textures have to be square
+	NotSquare(String),
+	/// Every texture has to be the same size as the first
+	SizeMismatch {
+		file: String,
+		expected: u32,
+		found: (u32, u32),
+	},
+	/// A voxel asked for a texture that isn't in the atlas
+	UnknownTexture(String),
+}
+
+impl From<AtlasError> for Error {
+	fn from(e: AtlasError) -> Error {
+		Error::AssetError(Box::new(e))
+	}
+}
+
+/// Square textures of the same size stacked into a texture array, found by the name of the file they
+/// were loaded from
 pub struct TextureAtlas {
 	id: gl::types::GLuint,
 	pub size: u16,
+	names: HashMap<String, u16>,
 }
 
 impl TextureAtlas {
-	pub fn new<P: AsRef<Path>>(file: P, depth: i32) -> Result<TextureAtlas, Error> {
-		let img = image::open(file.as_ref())?.into_rgb8();
+	/// Stacks every PNG in the resource directory `dir` into one texture array, in order of their file
+	/// names. Each is named after its file without the extension. Mip levels are made for how see-through
+	/// the voxels using each texture are in `transparency`, textures missing from it are taken as opaque.
+	pub fn from_dir(
+		resources: &Resources,
+		dir: &str,
+		transparency: &HashMap<String, Transparency>,
+		filter: TextureFilter,
+		anisotropy: u32,
+	) -> Result<TextureAtlas, Error> {
+		let names: Vec<String> = resources
+			.list(dir)
+			.into_iter()
+			.filter_map(|file| file.strip_suffix(".png").map(str::to_owned))
+			.collect();
 
-		let width = img.width() as i32;
-		let height = img.height() as i32 / depth;
+		let mut images = Vec::with_capacity(names.len());
+		let mut size = None;
+		for name in &names {
+			let file = format!("{}/{}.png", dir, name);
+			let img = image::load_from_memory(&resources.read(&file)?)?.into_rgba8();
+			if img.width() != img.height() {
+				return Err(AtlasError::NotSquare(file.clone()).into());
+			}
+			let expected = *size.get_or_insert(img.width());
+			if img.width() != expected {
+				return Err(AtlasError::SizeMismatch {
+					file: file.clone(),
+					expected,
+					found: img.dimensions(),
+				}
+				.into());
+			}
+			images.push(img);
+		}
+		let size = size.ok_or_else(|| AtlasError::Empty(dir.to_owned()))? as i32;
+		log::debug!("Loaded {} voxel textures from {}", images.len(), dir);
 
+		// Every level down to a single texel
+		let levels = 32 - (size as u32).leading_zeros();
 		let mut id = 0;
 		unsafe {
 			gl::CreateTextures(gl::TEXTURE_2D_ARRAY, 1, &mut id);
-			gl::TextureParameteri(id, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-			gl::TextureParameteri(id, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-
-			gl::TextureStorage3D(id, 1, gl::RGB8, width, height, depth as i32);
-			for i in 0..depth {
-				let ptr = img.as_ptr().offset((width * height * 3 * i as i32) as isize);
-				gl::TextureSubImage3D(
-					id,
-					0,
-					0,
-					0,
-					i,
-					width,
-					height,
-					1,
-					gl::RGB,
-					gl::UNSIGNED_BYTE,
-					ptr as *const gl::types::GLvoid,
-				);
+			gl::TextureStorage3D(id, levels as i32, gl::RGBA8, size, size, images.len() as i32);
+			for (layer, (img, name)) in images.into_iter().zip(&names).enumerate() {
+				let transparency = transparency.get(name).copied().unwrap_or(Transparency::Opaque);
+				for (level, mip) in mip_chain(img, levels, transparency).iter().enumerate() {
+					gl::TextureSubImage3D(
+						id,
+						level as i32,
+						0,
+						0,
+						layer as i32,
+						mip.width() as i32,
+						mip.height() as i32,
+						1,
+						gl::RGBA,
+						gl::UNSIGNED_BYTE,
+						mip.as_ptr() as *const gl::types::GLvoid,
+					);
+				}
 			}
 		}
 
-		Ok(TextureAtlas {
+		let atlas = TextureAtlas {
 			id,
-			size: img.width() as u16,
+			size: size as u16,
+			names: names
+				.into_iter()
+				.enumerate()
+				.map(|(layer, name)| (name, layer as u16))
+				.collect(),
+		};
+		atlas.set_filter(filter, anisotropy);
+		Ok(atlas)
+	}
+
+	/// Changes how the textures are sampled, `anisotropy` is capped to what the driver supports
+	pub fn set_filter(&self, filter: TextureFilter, anisotropy: u32) {
+		let min_filter = match filter {
+			TextureFilter::Nearest => gl::NEAREST,
+			TextureFilter::Bilinear => gl::LINEAR_MIPMAP_NEAREST,
+			TextureFilter::Trilinear => gl::LINEAR_MIPMAP_LINEAR,
+		};
+		unsafe {
+			gl::TextureParameteri(self.id, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+			gl::TextureParameteri(self.id, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+
+			let mut max = 1.0;
+			gl::GetFloatv(gl::MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+			let anisotropy = (anisotropy as f32).clamp(1.0, max.max(1.0));
+			gl::TextureParameterf(self.id, gl::TEXTURE_MAX_ANISOTROPY, anisotropy);
+		}
+	}
+
+	/// The texture loaded from `<name>.png`
+	pub fn texture(&self, name: &str) -> Result<TextureId, AtlasError> {
+		self.names
+			.get(name)
+			.map(|index| TextureId::new(self.size, *index))
+			.ok_or_else(|| AtlasError::UnknownTexture(name.to_owned()))
+	}
+
+	/// The texture loaded from `<name>.png`, or the first texture in the atlas if there is none by that name
+	pub fn texture_or_first(&self, name: &str) -> TextureId {
+		self.texture(name).unwrap_or_else(|e| {
+			log::warn!("{:?}, using the first texture instead", e);
+			TextureId::new(self.size, 0)
 		})
 	}
 
@@ -59,3 +161,92 @@ impl core::ops::Drop for TextureAtlas {
 		}
 	}
 }
+
+/// The image followed by each mip level below it, `levels` in all, for a voxel as see-through as
+/// `transparency`
+fn mip_chain(img: RgbaImage, levels: u32, transparency: Transparency) -> Vec<RgbaImage> {
+	let cutout = transparency == Transparency::Cutout;
+	let mut chain = vec![img];
+	while (chain.len() as u32) < levels {
+		let next = downsample(chain.last().unwrap(), cutout);
+		chain.push(next);
+	}
+	chain
+}
+
+/// Halves an image by averaging each 2x2 block. Colours are weighted by alpha so that see-through texels
+/// don't darken the edges next to them. With `cutout` set a block stays solid when at least half of it
+/// was, so leaves don't fade away in the distance.
+fn downsample(img: &RgbaImage, cutout: bool) -> RgbaImage {
+	let (width, height) = ((img.width() / 2).max(1), (img.height() / 2).max(1));
+	RgbaImage::from_fn(width, height, |x, y| {
+		let block = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| {
+			*img.get_pixel(
+				(x * 2 + dx).min(img.width() - 1),
+				(y * 2 + dy).min(img.height() - 1),
+			)
+		});
+		let alpha: u32 = block.iter().map(|p| p[3] as u32).sum();
+		let colour = |c: usize| {
+			let weighted: u32 = block.iter().map(|p| p[c] as u32 * p[3] as u32).sum();
+			weighted
+				.checked_div(alpha)
+				.unwrap_or_else(|| block.iter().map(|p| p[c] as u32).sum::<u32>() / 4)
+		};
+		let alpha = if cutout {
+			let solid = block.iter().filter(|p| p[3] >= 128).count();
+			if solid >= 2 {
+				255
+			} else {
+				0
+			}
+		} else {
+			alpha / 4
+		};
+		Rgba([colour(0) as u8, colour(1) as u8, colour(2) as u8, alpha as u8])
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mip_chain_halves_down_to_the_level_count() {
+		let img = RgbaImage::from_pixel(16, 16, Rgba([10, 20, 30, 255]));
+		let sizes: Vec<_> = mip_chain(img, 5, Transparency::Opaque)
+			.iter()
+			.map(RgbaImage::dimensions)
+			.collect();
+		assert_eq!(sizes, vec![(16, 16), (8, 8), (4, 4), (2, 2), (1, 1)]);
+		// Levels past 1x1 stay 1x1
+		let img = RgbaImage::from_pixel(2, 2, Rgba([0; 4]));
+		assert_eq!(mip_chain(img, 3, Transparency::Opaque)[2].dimensions(), (1, 1));
+	}
+
+	#[test]
+	fn see_through_texels_dont_darken_their_neighbours() {
+		let mut img = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 0, 0]));
+		img.put_pixel(0, 0, Rgba([200, 100, 50, 255]));
+		img.put_pixel(1, 0, Rgba([100, 50, 250, 255]));
+		let level = downsample(&img, false);
+		assert_eq!(*level.get_pixel(0, 0), Rgba([150, 75, 150, 127]));
+
+		// A block with nothing to see keeps its plain average
+		let img = RgbaImage::from_pixel(2, 2, Rgba([40, 80, 120, 0]));
+		assert_eq!(*downsample(&img, false).get_pixel(0, 0), Rgba([40, 80, 120, 0]));
+	}
+
+	#[test]
+	fn cutout_levels_stay_solid_or_clear() {
+		let mut img = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 0, 0]));
+		// Half of the left block is solid, a quarter of the right one
+		img.put_pixel(0, 0, Rgba([0, 255, 0, 255]));
+		img.put_pixel(1, 1, Rgba([0, 255, 0, 200]));
+		img.put_pixel(2, 0, Rgba([0, 255, 0, 255]));
+		let chain = mip_chain(img, 3, Transparency::Cutout);
+		assert_eq!(chain[1].get_pixel(0, 0)[3], 255);
+		assert_eq!(chain[1].get_pixel(1, 0)[3], 0);
+		assert_eq!(chain[2].get_pixel(0, 0)[3], 255);
+	}
+}
@@ -0,0 +1,104 @@
+use crate::{
+	render::{shader::Program, RenderError},
+	resources::Resources,
+	Error,
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+use std::sync::mpsc;
+
+/// Watches the directories shaders were loaded from, rebuilding the programs made from them whenever
+/// one of their files changes
+pub struct ShaderWatcher {
+	watcher: notify::RecommendedWatcher,
+	events: mpsc::Receiver<notify::Result<notify::Event>>,
+	/// Programs that stop being used are dropped from here too
+	programs: Vec<Weak<Program>>,
+	dirs: HashSet<PathBuf>,
+}
+
+impl ShaderWatcher {
+	pub fn new() -> Result<ShaderWatcher, RenderError> {
+		let (sender, events) = mpsc::channel();
+		let watcher = notify::recommended_watcher(sender)
+			.map_err(|e| RenderError::Shader(format!("failed to watch shaders: {}", e)))?;
+		Ok(ShaderWatcher {
+			watcher,
+			events,
+			programs: Vec::new(),
+			dirs: HashSet::new(),
+		})
+	}
+
+	/// Reloads the program when its files change, does nothing for programs that weren't loaded from files
+	pub fn watch(&mut self, program: &Rc<Program>) {
+		let files = program.files();
+		if files.is_empty() {
+			return;
+		}
+		self.watch_dirs(&files);
+		self.programs.push(Rc::downgrade(program));
+	}
+
+	fn watch_dirs(&mut self, files: &[PathBuf]) {
+		// Editors often save by replacing the file, so it is the directory that is watched
+		for dir in files.iter().filter_map(|path| path.parent()) {
+			if self.dirs.contains(dir) {
+				continue;
+			}
+			match self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+				Ok(()) => {
+					self.dirs.insert(dir.to_owned());
+				}
+				Err(e) => log::warn!("Failed to watch {} for shader changes: {}", dir.display(), e),
+			}
+		}
+	}
+
+	/// Rebuilds the programs whose files have changed since the last call. A program that fails to build
+	/// keeps its old version and the error is logged. Returns whether any program was rebuilt.
+	pub fn reload_changed(&mut self, resources: &Resources) -> bool {
+		let changed: HashSet<PathBuf> = self
+			.events
+			.try_iter()
+			.filter_map(|event| match event {
+				Ok(event) => Some(event),
+				Err(e) => {
+					log::warn!("Error watching shaders: {}", e);
+					None
+				}
+			})
+			.filter(|event| matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)))
+			.flat_map(|event| event.paths)
+			.collect();
+		if changed.is_empty() {
+			return false;
+		}
+
+		self.programs.retain(|program| program.strong_count() > 0);
+		let mut reloaded = false;
+		for program in self.programs.clone().iter().filter_map(Weak::upgrade) {
+			let files = program.files();
+			if !files.iter().any(|path| changed.contains(path)) {
+				continue;
+			}
+			let names: Vec<_> = files.iter().map(|path| path.display().to_string()).collect();
+			match program.reload(resources) {
+				Ok(()) => {
+					log::info!("Reloaded shaders {}", names.join(", "));
+					// It may include files from somewhere new
+					self.watch_dirs(&program.files());
+					reloaded = true;
+				}
+				Err(Error::RenderError(RenderError::Shader(log))) => {
+					log::error!("Failed to reload shaders, keeping the old ones: {}", log)
+				}
+				Err(e) => log::error!("Failed to reload shaders, keeping the old ones: {:?}", e),
+			}
+		}
+		reloaded
+	}
+}
@@ -0,0 +1,288 @@
+use crate::Error;
+
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Version of the layout packs are expected to follow, packs made for another are skipped
+pub const PACK_FORMAT: u32 = 1;
+
+/// Describes a resource pack, read from the `pack.ron` at its root
+#[derive(Deserialize, Debug, Clone)]
+pub struct PackManifest {
+	pub name: String,
+	pub description: String,
+	/// The `PACK_FORMAT` the pack was made for
+	pub format: u32,
+}
+
+/// A directory or zip file laid out like `res/`, whose files are used in place of the ones in `res/`
+pub struct ResourcePack {
+	pub manifest: PackManifest,
+	path: PathBuf,
+	source: PackSource,
+}
+
+impl ResourcePack {
+	pub fn open(path: &Path) -> Result<ResourcePack, Error> {
+		let source = if path.is_dir() {
+			PackSource::Dir(path.to_owned())
+		} else {
+			let file = fs::File::open(path).map_err(asset_error)?;
+			PackSource::Zip(RefCell::new(zip::ZipArchive::new(file).map_err(asset_error)?))
+		};
+		let manifest = source
+			.read("pack.ron")
+			.ok_or_else(|| asset_error(format!("{} has no pack.ron", path.display())))?
+			.map_err(asset_error)?;
+		Ok(ResourcePack {
+			manifest: ron::de::from_bytes(&manifest).map_err(asset_error)?,
+			path: path.to_owned(),
+			source,
+		})
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+}
+
+enum PackSource {
+	Dir(PathBuf),
+	Zip(RefCell<zip::ZipArchive<fs::File>>),
+}
+
+impl PackSource {
+	/// Reads a file from the pack, `None` if the pack doesn't have it
+	fn read(&self, name: &str) -> Option<io::Result<Vec<u8>>> {
+		match self {
+			PackSource::Dir(dir) => {
+				let path = dir.join(name);
+				if path.is_file() {
+					Some(fs::read(path))
+				} else {
+					None
+				}
+			}
+			PackSource::Zip(archive) => {
+				let mut archive = archive.borrow_mut();
+				let mut file = archive.by_name(name).ok()?;
+				let mut data = Vec::with_capacity(file.size() as usize);
+				Some(file.read_to_end(&mut data).map(|_| data))
+			}
+		}
+	}
+
+	/// Where the pack's copy of a file is on disk, `None` if the pack doesn't have it and `Some(None)` if
+	/// it isn't on disk
+	fn path(&self, name: &str) -> Option<Option<PathBuf>> {
+		match self {
+			PackSource::Dir(dir) => Some(dir.join(name)).filter(|path| path.is_file()).map(Some),
+			PackSource::Zip(archive) => archive
+				.borrow()
+				.file_names()
+				.any(|file| file == name)
+				.then_some(None),
+		}
+	}
+
+	/// Names of the files directly inside of `dir` in the pack
+	fn list(&self, dir: &str) -> Vec<String> {
+		match self {
+			PackSource::Dir(root) => list_dir(&root.join(dir)),
+			PackSource::Zip(archive) => {
+				let prefix = format!("{}/", dir.trim_end_matches('/'));
+				archive
+					.borrow()
+					.file_names()
+					.filter_map(|name| name.strip_prefix(&prefix))
+					.filter(|name| !name.is_empty() && !name.contains('/'))
+					.map(str::to_owned)
+					.collect()
+			}
+		}
+	}
+}
+
+/// The game data, `res/` with any resource packs laid over it. Files are found by their path relative to
+/// `res/`, such as `client/shaders/chunk.vs`.
+pub struct Resources {
+	base: PathBuf,
+	/// In the order they are laid over `res/`, later packs win
+	packs: Vec<ResourcePack>,
+}
+
+impl Resources {
+	/// Lays the packs at `paths` over `res/` in order. Relative paths are looked for in the config
+	/// directory's `resourcepacks`, and packs that can't be used are skipped.
+	pub fn load(paths: &[PathBuf]) -> Resources {
+		let packs_dir = common::config_root().join("resourcepacks");
+		let packs = paths
+			.iter()
+			.filter_map(|path| {
+				let path = packs_dir.join(path);
+				match ResourcePack::open(&path) {
+					Ok(pack) if pack.manifest.format == PACK_FORMAT => {
+						log::info!(
+							"Using resource pack {}: {}",
+							pack.manifest.name,
+							pack.manifest.description
+						);
+						Some(pack)
+					}
+					Ok(pack) => {
+						log::warn!(
+							"Skipping resource pack {}, it is for format {} but we use {}",
+							pack.manifest.name,
+							pack.manifest.format,
+							PACK_FORMAT
+						);
+						None
+					}
+					Err(e) => {
+						log::warn!("Skipping resource pack {}: {:?}", path.display(), e);
+						None
+					}
+				}
+			})
+			.collect();
+		Resources {
+			base: common::data_root(),
+			packs,
+		}
+	}
+
+	pub fn packs(&self) -> &[ResourcePack] {
+		&self.packs
+	}
+
+	/// Reads a file from the last pack that has it, or from `res/`
+	pub fn read(&self, name: &str) -> Result<Vec<u8>, Error> {
+		self.packs
+			.iter()
+			.rev()
+			.find_map(|pack| pack.source.read(name))
+			.unwrap_or_else(|| fs::read(self.base.join(name)))
+			.map_err(|e| asset_error(format!("{}: {}", name, e)))
+	}
+
+	/// Where on disk the file that `read` would give is, `None` when it is inside of a zip pack or doesn't
+	/// exist
+	pub fn path(&self, name: &str) -> Option<PathBuf> {
+		self.packs
+			.iter()
+			.rev()
+			.find_map(|pack| pack.source.path(name))
+			.unwrap_or_else(|| Some(self.base.join(name)).filter(|path| path.is_file()))
+	}
+
+	pub fn read_to_string(&self, name: &str) -> Result<String, Error> {
+		String::from_utf8(self.read(name)?).map_err(|e| asset_error(format!("{}: {}", name, e)))
+	}
+
+	/// Names of the files directly inside of `dir` in `res/` and in every pack, sorted
+	pub fn list(&self, dir: &str) -> Vec<String> {
+		let mut names: BTreeSet<String> = list_dir(&self.base.join(dir)).into_iter().collect();
+		for pack in &self.packs {
+			names.extend(pack.source.list(dir));
+		}
+		names.into_iter().collect()
+	}
+}
+
+fn list_dir(dir: &Path) -> Vec<String> {
+	fs::read_dir(dir)
+		.into_iter()
+		.flatten()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+		.filter_map(|entry| entry.file_name().into_string().ok())
+		.collect()
+}
+
+fn asset_error<E: std::fmt::Debug + 'static>(e: E) -> Error {
+	Error::AssetError(Box::new(e))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Writes `files` under a fresh directory, giving its path
+	fn dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("takh-test-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		for (file, contents) in files {
+			let path = dir.join(file);
+			fs::create_dir_all(path.parent().unwrap()).unwrap();
+			fs::write(path, contents).unwrap();
+		}
+		dir
+	}
+
+	fn pack(name: &str, files: &[(&str, &str)]) -> ResourcePack {
+		let manifest = format!("(name: \"{}\", description: \"\", format: {})", name, PACK_FORMAT);
+		let mut files = files.to_vec();
+		files.push(("pack.ron", &manifest));
+		ResourcePack::open(&dir(name, &files)).unwrap()
+	}
+
+	/// `res/` and two packs laid over it, kept in directories named after `test`
+	fn resources(test: &str) -> Resources {
+		Resources {
+			base: dir(
+				&format!("{}-base", test),
+				&[
+					("textures/dirt.png", "base dirt"),
+					("textures/stone.png", "base stone"),
+				],
+			),
+			packs: vec![
+				pack(
+					&format!("{}-first", test),
+					&[
+						("textures/dirt.png", "first dirt"),
+						("textures/sand.png", "first sand"),
+					],
+				),
+				pack(
+					&format!("{}-second", test),
+					&[("textures/sand.png", "second sand")],
+				),
+			],
+		}
+	}
+
+	#[test]
+	fn later_packs_are_read_over_earlier_ones() {
+		let resources = resources("resources-read");
+		let read = |name| resources.read_to_string(name).unwrap();
+		assert_eq!(read("textures/stone.png"), "base stone");
+		assert_eq!(read("textures/dirt.png"), "first dirt");
+		assert_eq!(read("textures/sand.png"), "second sand");
+		assert!(resources.read("textures/grass.png").is_err());
+	}
+
+	#[test]
+	fn paths_point_at_the_file_that_is_read() {
+		let resources = resources("resources-path");
+		let path = |name| resources.path(name).map(|path| fs::read_to_string(path).unwrap());
+		assert_eq!(path("textures/stone.png").as_deref(), Some("base stone"));
+		assert_eq!(path("textures/dirt.png").as_deref(), Some("first dirt"));
+		assert_eq!(path("textures/sand.png").as_deref(), Some("second sand"));
+		assert_eq!(path("textures/grass.png"), None);
+	}
+
+	#[test]
+	fn listing_merges_res_and_every_pack() {
+		let resources = resources("resources-list");
+		assert_eq!(
+			resources.list("textures"),
+			vec!["dirt.png", "sand.png", "stone.png"]
+		);
+		assert!(resources.list("sounds").is_empty());
+	}
+}
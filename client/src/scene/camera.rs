@@ -1,6 +1,6 @@
 // use ultraviolet::mat::Mat4;
 // use ultraviolet::vec::Vec3;
-use vek::{Vec3, Mat4};
+use vek::{Mat4, Vec3};
 
 pub struct Camera {
 	fov: f32,
@@ -29,10 +29,19 @@ impl Camera {
 		camera
 	}
 
+	pub fn pos(&self) -> Vec3<f32> {
+		self.pos
+	}
+
 	pub fn set_pos(&mut self, pos: Vec3<f32>) {
 		self.pos = pos;
 	}
 
+	/// Direction the camera looks in, `+x` at a yaw and pitch of `0`, the same way the player walks
+	pub fn forward(&self) -> Vec3<f32> {
+		self.face_dir
+	}
+
 	pub fn set_rot(&mut self, yaw: f32, pitch: f32) {
 		self.yaw = yaw;
 		self.pitch = pitch;
@@ -65,7 +74,7 @@ impl Camera {
 	}
 
 	pub fn view_matrix(&self) -> Mat4<f32> {
-		Mat4::look_at_lh(self.pos, self.pos + self.face_dir, self.up_dir)
+		Mat4::look_at_rh(self.pos, self.pos + self.face_dir, self.up_dir)
 	}
 
 	pub fn proj_matrix(&self) -> Mat4<f32> {
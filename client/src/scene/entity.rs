@@ -0,0 +1,93 @@
+use crate::render::{
+	mesh::{Mesh, MeshBuilder},
+	shader::Program,
+};
+use crate::scene::{interpolation::Interpolated, world::ChunkVertex};
+
+use common::{components::Player, world::voxel::TextureId};
+
+use specs::{Join, World, WorldExt};
+use vek::{Mat4, Vec3};
+
+/// Draws remote players as boxes at their interpolated positions
+pub struct RenderEntities {
+	shader: std::rc::Rc<Program>,
+	mesh: Mesh<ChunkVertex>,
+}
+
+impl RenderEntities {
+	pub fn new(shader: std::rc::Rc<Program>, tex: TextureId) -> Self {
+		Self {
+			shader,
+			mesh: cube_mesh(tex),
+		}
+	}
+
+	pub fn render(&self, ecs: &World) {
+		self.shader.bind();
+		let players = ecs.read_storage::<Player>();
+		for (interp, _) in (&ecs.read_storage::<Interpolated>(), &players).join() {
+			let pos = interp.pos.map(|v| v as f32);
+			let yaw = (interp.ori.x as f32).to_radians();
+			let transform = Mat4::<f32>::translation_3d(pos)
+				* Mat4::rotation_y(-yaw)
+				* Mat4::scaling_3d(Vec3::new(
+					Player::WIDTH as f32,
+					Player::HEIGHT as f32,
+					Player::WIDTH as f32,
+				)) * Mat4::translation_3d(Vec3::new(-0.5, 0.0, -0.5));
+			self.shader.set_uniform_mat4("u_model", transform);
+			self.mesh.render();
+		}
+	}
+}
+
+/// A unit cube with the same face layout as a chunk voxel
+fn cube_mesh(tex: TextureId) -> Mesh<ChunkVertex> {
+	let ts = tex.size;
+	let ti = tex.index;
+	let mut mesh = MeshBuilder::new();
+	// Back face
+	mesh.push_quad(
+		&ChunkVertex::new(1, 1, 0, 0, 0, -1, ts, ts, ti),
+		&ChunkVertex::new(0, 1, 0, 0, 0, -1, 0, ts, ti),
+		&ChunkVertex::new(0, 0, 0, 0, 0, -1, 0, 0, ti),
+		&ChunkVertex::new(1, 0, 0, 0, 0, -1, ts, 0, ti),
+	);
+	// Front face
+	mesh.push_quad(
+		&ChunkVertex::new(1, 0, 1, 0, 0, 1, ts, 0, ti),
+		&ChunkVertex::new(0, 0, 1, 0, 0, 1, 0, 0, ti),
+		&ChunkVertex::new(0, 1, 1, 0, 0, 1, 0, ts, ti),
+		&ChunkVertex::new(1, 1, 1, 0, 0, 1, ts, ts, ti),
+	);
+	// Left face
+	mesh.push_quad(
+		&ChunkVertex::new(0, 1, 0, -1, 0, 0, ts, ts, ti),
+		&ChunkVertex::new(0, 1, 1, -1, 0, 0, 0, ts, ti),
+		&ChunkVertex::new(0, 0, 1, -1, 0, 0, 0, 0, ti),
+		&ChunkVertex::new(0, 0, 0, -1, 0, 0, ts, 0, ti),
+	);
+	// Right face
+	mesh.push_quad(
+		&ChunkVertex::new(1, 0, 0, 1, 0, 0, ts, 0, ti),
+		&ChunkVertex::new(1, 0, 1, 1, 0, 0, 0, 0, ti),
+		&ChunkVertex::new(1, 1, 1, 1, 0, 0, 0, ts, ti),
+		&ChunkVertex::new(1, 1, 0, 1, 0, 0, ts, ts, ti),
+	);
+	// Bottom face
+	mesh.push_quad(
+		&ChunkVertex::new(0, 0, 1, 0, 1, 0, ts, 0, ti),
+		&ChunkVertex::new(1, 0, 1, 0, 1, 0, 0, 0, ti),
+		&ChunkVertex::new(1, 0, 0, 0, 1, 0, 0, ts, ti),
+		&ChunkVertex::new(0, 0, 0, 0, 1, 0, ts, ts, ti),
+	);
+	// Top face
+	mesh.push_quad(
+		&ChunkVertex::new(0, 1, 0, 0, -1, 0, ts, ts, ti),
+		&ChunkVertex::new(1, 1, 0, 0, -1, 0, 0, ts, ti),
+		&ChunkVertex::new(1, 1, 1, 0, -1, 0, 0, 0, ti),
+		&ChunkVertex::new(0, 1, 1, 0, -1, 0, ts, 0, ti),
+	);
+	mesh.build()
+}
@@ -0,0 +1,117 @@
+use vek::{Mat4, Vec3, Vec4};
+
+/// The space a camera can see, as six planes facing inwards. Each plane is `(a, b, c, d)` with a point
+/// `p` in front of it when `a*p.x + b*p.y + c*p.z + d >= 0`.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+	planes: [Vec4<f32>; 6],
+}
+
+impl Frustum {
+	/// Pulls the planes out of a projection times view matrix, they match OpenGL's clipping of
+	/// `-w <= x, y, z <= w`
+	pub fn from_matrix(matrix: Mat4<f32>) -> Frustum {
+		let rows = matrix.into_row_arrays().map(Vec4::from);
+		let planes = [
+			rows[3] + rows[0],
+			rows[3] - rows[0],
+			rows[3] + rows[1],
+			rows[3] - rows[1],
+			rows[3] + rows[2],
+			rows[3] - rows[2],
+		];
+		Frustum { planes }
+	}
+
+	/// Whether any of the box from `min` to `max` could be seen. Boxes close to a corner of the frustum
+	/// can be kept when they are just outside of it, but a box that is inside is never thrown away.
+	pub fn intersects_aabb(&self, min: Vec3<f32>, max: Vec3<f32>) -> bool {
+		self.planes.iter().all(|plane| {
+			// The corner of the box furthest along the plane's normal
+			let corner = Vec3::new(
+				if plane.x >= 0.0 { max.x } else { min.x },
+				if plane.y >= 0.0 { max.y } else { min.y },
+				if plane.z >= 0.0 { max.z } else { min.z },
+			);
+			plane.xyz().dot(corner) + plane.w >= 0.0
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::scene::camera::Camera;
+
+	/// Projection times view matrix of a camera at the origin looking along `+x`
+	fn matrix() -> Mat4<f32> {
+		let mut camera = Camera::new(90.0, 1.0);
+		camera.set_pos(Vec3::zero());
+		camera.set_rot(0.0, 0.0);
+		camera.update();
+		camera.proj_matrix() * camera.view_matrix()
+	}
+
+	/// Whether the matrix puts a point inside OpenGL's clip volume
+	fn clipped_inside(matrix: Mat4<f32>, point: Vec3<f32>) -> bool {
+		let clip = matrix * Vec4::from_point(point);
+		clip.x.abs() <= clip.w && clip.y.abs() <= clip.w && clip.z.abs() <= clip.w
+	}
+
+	fn unit_box(centre: Vec3<f32>) -> (Vec3<f32>, Vec3<f32>) {
+		(centre - 0.5, centre + 0.5)
+	}
+
+	#[test]
+	fn planes_agree_with_clip_space() {
+		let mut camera = Camera::new(70.0, 16.0 / 9.0);
+		camera.set_pos(Vec3::new(3.0, 10.0, -7.0));
+		camera.set_rot(37.0, -20.0);
+		camera.update();
+		let matrix = camera.proj_matrix() * camera.view_matrix();
+		let frustum = Frustum::from_matrix(matrix);
+
+		for x in -20..=20 {
+			for y in -20..=20 {
+				for z in -20..=20 {
+					let point = Vec3::new(x as f32, y as f32, z as f32) * 2.5 + Vec3::new(0.3, 0.7, 0.1);
+					assert_eq!(
+						frustum.intersects_aabb(point, point),
+						clipped_inside(matrix, point),
+						"disagree at {:?}",
+						point
+					);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn keeps_boxes_in_view_and_culls_boxes_behind() {
+		let mut camera = Camera::new(90.0, 1.0);
+		camera.set_rot(0.0, 0.0);
+		camera.update();
+		assert_eq!(camera.forward(), Vec3::unit_x());
+		let frustum = Frustum::from_matrix(matrix());
+		let (min, max) = unit_box(Vec3::unit_x() * 10.0);
+		assert!(frustum.intersects_aabb(min, max));
+		let (min, max) = unit_box(-Vec3::unit_x() * 10.0);
+		assert!(!frustum.intersects_aabb(min, max));
+	}
+
+	#[test]
+	fn keeps_boxes_crossing_a_plane() {
+		let frustum = Frustum::from_matrix(matrix());
+		// Huge boxes around the camera always reach into view
+		assert!(frustum.intersects_aabb(Vec3::broadcast(-50.0), Vec3::broadcast(50.0)));
+		// A thin slab through the origin crosses every side plane
+		assert!(frustum.intersects_aabb(Vec3::new(-50.0, -0.1, -50.0), Vec3::new(50.0, 0.1, 50.0)));
+	}
+
+	#[test]
+	fn culls_boxes_past_the_far_plane() {
+		let frustum = Frustum::from_matrix(matrix());
+		let (min, max) = unit_box(Vec3::unit_x() * 5000.0);
+		assert!(!frustum.intersects_aabb(min, max));
+	}
+}
@@ -0,0 +1,227 @@
+use common::{
+	components::{Orientation, Position},
+	net::server::TICK_RATE,
+};
+
+use specs::{Component, Join, VecStorage, World, WorldExt};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use vek::Vec3;
+
+/// Most snapshots kept for a single entity
+const MAX_SNAPSHOTS: usize = 32;
+/// Seconds past the newest snapshot that we keep guessing an entity's movement for, slowing it down to a
+/// stop over that time
+const MAX_EXTRAPOLATION: f64 = 0.25;
+
+/// Maps the server's ticks onto our own clock. Snapshots are placed by the tick they happened on rather
+/// than by when they arrived, so jitter in the network doesn't bend the timeline.
+pub struct ServerClock {
+	start: Instant,
+	/// Our time minus the server's in seconds, following the ticks that arrived soonest
+	offset: Option<f64>,
+}
+
+impl Default for ServerClock {
+	fn default() -> Self {
+		ServerClock {
+			start: Instant::now(),
+			offset: None,
+		}
+	}
+}
+
+impl ServerClock {
+	/// How far the clock moves towards a tick that arrived later than expected. Small, so that one late
+	/// packet barely matters, but a server that has fallen behind is followed in the end.
+	const LATE_WEIGHT: f64 = 0.01;
+
+	/// Seconds into the server's timeline that `tick` started at
+	pub fn tick_time(tick: u64) -> f64 {
+		tick as f64 / TICK_RATE as f64
+	}
+
+	/// Records that `tick` has just arrived
+	pub fn received(&mut self, tick: u64) {
+		let offset = self.start.elapsed().as_secs_f64() - Self::tick_time(tick);
+		self.offset = Some(match self.offset {
+			Some(old) if offset > old => old + (offset - old) * Self::LATE_WEIGHT,
+			_ => offset,
+		});
+	}
+
+	/// The server's time right now in seconds, once a tick has arrived
+	pub fn now(&self) -> Option<f64> {
+		self.offset
+			.map(|offset| self.start.elapsed().as_secs_f64() - offset)
+	}
+}
+
+#[derive(Clone, Copy)]
+struct Snapshot {
+	/// Seconds into the server's timeline
+	time: f64,
+	pos: Vec3<f64>,
+	ori: Vec3<f64>,
+}
+
+/// A buffer of the states received for a remote entity, and where it should be drawn this frame. Remote
+/// entities are drawn slightly in the past so that there is almost always a snapshot either side of the
+/// time being drawn.
+pub struct Interpolated {
+	snapshots: VecDeque<Snapshot>,
+	/// Position to draw the entity at
+	pub pos: Vec3<f64>,
+	/// Orientation to draw the entity at, `x` is yaw and `y` is pitch
+	pub ori: Vec3<f64>,
+}
+
+impl Component for Interpolated {
+	type Storage = VecStorage<Self>;
+}
+
+impl Interpolated {
+	/// An entity that is at `pos` from `time` on, in seconds of the server's timeline
+	pub fn new(pos: Vec3<f64>, ori: Vec3<f64>, time: f64) -> Interpolated {
+		let mut interp = Interpolated {
+			snapshots: VecDeque::with_capacity(MAX_SNAPSHOTS),
+			pos,
+			ori,
+		};
+		interp.push(pos, ori, time);
+		interp
+	}
+
+	/// Record the state of the entity at `time` on the server's timeline
+	pub fn push(&mut self, pos: Vec3<f64>, ori: Vec3<f64>, time: f64) {
+		if self.snapshots.len() == MAX_SNAPSHOTS {
+			self.snapshots.pop_front();
+		}
+		self.snapshots.push_back(Snapshot { time, pos, ori });
+	}
+
+	/// Works out where the entity was at `time`
+	fn sample(&mut self, time: f64) {
+		// Forget snapshots that are entirely behind the time being drawn
+		while self.snapshots.len() > 2 && self.snapshots[1].time <= time {
+			self.snapshots.pop_front();
+		}
+
+		let (a, b) = match (self.snapshots.front(), self.snapshots.get(1)) {
+			(Some(a), Some(b)) => (*a, *b),
+			(Some(a), None) => {
+				self.pos = a.pos;
+				self.ori = a.ori;
+				return;
+			}
+			_ => return,
+		};
+
+		let span = b.time - a.time;
+		if span <= 0.0 || time <= a.time {
+			self.pos = a.pos;
+			self.ori = a.ori;
+			return;
+		}
+
+		if time > b.time {
+			// Past the newest snapshot means packets are late. Carry on from the last known velocity, slowing
+			// to a stop, so that an entity that really stopped isn't drawn far past where it is.
+			let late = (time - b.time).min(MAX_EXTRAPOLATION);
+			let travelled = late - late * late / (2.0 * MAX_EXTRAPOLATION);
+			self.pos = Vec3::lerp_unclamped(a.pos, b.pos, 1.0 + travelled / span);
+			self.ori = b.ori;
+			return;
+		}
+
+		let t = (time - a.time) / span;
+		self.pos = Vec3::lerp(a.pos, b.pos, t);
+		self.ori = Vec3::new(
+			lerp_angle(a.ori.x, b.ori.x, t),
+			a.ori.y + (b.ori.y - a.ori.y) * t,
+			lerp_angle(a.ori.z, b.ori.z, t),
+		);
+	}
+}
+
+/// Interpolates between two angles in degrees the short way around the circle
+fn lerp_angle(a: f64, b: f64, t: f64) -> f64 {
+	let diff = (b - a + 180.0).rem_euclid(360.0) - 180.0;
+	(a + diff * t).rem_euclid(360.0)
+}
+
+/// Updates where every remote entity should be drawn, `delay` is how far in the past they are drawn
+pub fn interpolate(ecs: &World, delay: Duration) {
+	let time = match ecs.read_resource::<ServerClock>().now() {
+		Some(now) => now - delay.as_secs_f64(),
+		None => return,
+	};
+	for interp in (&mut ecs.write_storage::<Interpolated>()).join() {
+		interp.sample(time);
+	}
+}
+
+/// Records the current `Position` and `Orientation` of an entity as a new snapshot at `time` on the
+/// server's timeline, `teleported` entities jump straight to the new state rather than sliding there
+pub fn push_snapshot(ecs: &World, entity: specs::Entity, time: f64, teleported: bool) {
+	let pos = ecs.read_storage::<Position>().get(entity).copied();
+	let ori = ecs.read_storage::<Orientation>().get(entity).copied();
+	let mut interps = ecs.write_storage::<Interpolated>();
+	if let (Some(pos), Some(ori), Some(interp)) = (pos, ori, interps.get_mut(entity)) {
+		if teleported {
+			*interp = Interpolated::new(pos.0, ori.0, time);
+		} else {
+			interp.push(pos.0, ori.0, time);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// An entity that walked one voxel along `x` during the first tick
+	fn walked() -> Interpolated {
+		let tick = ServerClock::tick_time(1);
+		let mut interp = Interpolated::new(Vec3::zero(), Vec3::zero(), 0.0);
+		interp.push(Vec3::unit_x(), Vec3::new(90.0, 0.0, 0.0), tick);
+		interp
+	}
+
+	#[test]
+	fn interpolates_between_snapshots() {
+		let mut interp = walked();
+		interp.sample(ServerClock::tick_time(1) / 2.0);
+		assert!((interp.pos.x - 0.5).abs() < 1e-9);
+		assert!((interp.ori.x - 45.0).abs() < 1e-9);
+	}
+
+	#[test]
+	fn late_snapshots_slow_to_a_stop() {
+		let tick = ServerClock::tick_time(1);
+		let speed = 1.0 / tick;
+		let mut interp = walked();
+		interp.sample(tick + 10.0);
+		// Half of what carrying on at full speed would have covered
+		let furthest = 1.0 + speed * MAX_EXTRAPOLATION / 2.0;
+		assert!((interp.pos.x - furthest).abs() < 1e-9);
+
+		// When the entity really stopped, it is put back where it is as soon as the server says so
+		interp.push(Vec3::unit_x(), Vec3::new(90.0, 0.0, 0.0), tick * 2.0);
+		interp.sample(tick * 2.0);
+		assert_eq!(interp.pos, Vec3::unit_x());
+	}
+
+	#[test]
+	fn clock_follows_the_soonest_ticks() {
+		let mut clock = ServerClock::default();
+		assert!(clock.now().is_none());
+		clock.received(100);
+		clock.received(101);
+		let expected = ServerClock::tick_time(101);
+		assert!((clock.now().unwrap() - expected).abs() < 0.01);
+		// A tick that arrives half a second late barely moves the clock
+		clock.received(91);
+		assert!((clock.now().unwrap() - expected).abs() < 0.01);
+	}
+}
@@ -1,34 +1,62 @@
 pub mod camera;
+pub mod entity;
+pub mod frustum;
+pub mod interpolation;
 pub mod player;
+pub mod sky;
 pub mod world;
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::time::Duration;
 
+use crate::net::{Connection, IntegratedServer};
 use crate::render::{
+	preprocess::Defines,
 	shader::{Program, Shader, ShaderKind},
 	texture::TextureAtlas,
+	watcher::ShaderWatcher,
 };
-use crate::scene::{camera::Camera, player::Player, world::RenderChunks};
+use crate::scene::{
+	camera::Camera, entity::RenderEntities, frustum::Frustum, player::Player, sky::RenderSky,
+	world::RenderChunks,
+};
+use crate::{resources::Resources, settings::GraphicsSettings};
 use crate::{window, window::GameInput, Error, GlobalState, PlayState, PlayStateNext, Settings};
 
 use common::world::{
-	chunk::{Chunk, Palette},
+	terrain::{Terrain, WorldPalette},
 	voxel,
-	voxel::{TextureId, VoxelTexture},
-	world::generate_chunk,
+	voxel::{TextureId, Transparency, VoxelTexture},
 };
 
-use tokio::runtime::Runtime;
+use serde::Deserialize;
+use specs::WorldExt;
 
-pub struct GameScene {
-	runtime: Arc<Runtime>,
+/// How a voxel looks, an entry of `client/voxels.ron`
+#[derive(Deserialize)]
+struct VoxelLook {
+	/// Name of the texture on every face
+	texture: String,
+	transparency: Transparency,
+}
 
+pub struct GameScene {
 	player: Player,
 	camera: Camera,
 
+	sky: RenderSky,
 	world_mesh: RenderChunks,
+	entities: RenderEntities,
 
 	cursor_grabbed: bool,
+
+	/// Rebuilds shaders as they are edited, only in debug builds
+	shader_watcher: Option<ShaderWatcher>,
+
+	/// The server we are playing on in singleplayer, last so that it is dropped after everything that
+	/// talks to it
+	#[allow(dead_code)]
+	integrated: Option<IntegratedServer>,
 }
 
 impl GameScene {
@@ -39,56 +67,143 @@ impl GameScene {
 			win_size.0 as f32 / win_size.1 as f32,
 		);
 
-		let player = Player::new(global_state.runtime.clone());
+		let (world_mesh, terrain, entity_texture) =
+			Self::create_world(&global_state.resources, &global_state.settings.graphics)?;
+		let mut player = Player::new(terrain, global_state.settings.graphics.render_distance);
+
+		// Without a server to join, singleplayer runs a server of its own
+		let runtime = &global_state.runtime;
+		let username = &global_state.settings.network.username;
+		let timeout = Duration::from_secs(global_state.settings.network.timeout_secs as u64);
+		let (connection, integrated) = match global_state.settings.network.server_address {
+			Some(address) => (Connection::connect(runtime, address, username, timeout)?, None),
+			None => {
+				let (server, stream) = IntegratedServer::start(runtime)?;
+				(Connection::new(runtime, stream, username, timeout), Some(server))
+			}
+		};
+		player.set_connection(connection);
+
+		let entities = RenderEntities::new(world_mesh.shader.clone(), entity_texture);
+		let sky = RenderSky::new(&global_state.resources)?;
 
-		let world_mesh = Self::create_world()?;
+		let shader_watcher = if cfg!(debug_assertions) {
+			ShaderWatcher::new()
+				.map_err(|e| log::warn!("Shaders won't be reloaded: {:?}", e))
+				.ok()
+				.map(|mut watcher| {
+					watcher.watch(&world_mesh.shader);
+					watcher.watch(&sky.shader);
+					for pass in global_state.window.renderer_mut().framebuffer.passes() {
+						watcher.watch(pass);
+					}
+					watcher
+				})
+		} else {
+			None
+		};
 
 		Ok(GameScene {
+			integrated,
 			cursor_grabbed: false,
+			shader_watcher,
 			player,
-			runtime: global_state.runtime.clone(),
+			sky,
 			world_mesh,
+			entities,
 			camera,
 		})
 	}
 
-	fn create_world() -> Result<RenderChunks, Error> {
-		let data_root = common::data_root();
-
+	fn create_world(
+		resources: &Resources,
+		graphics: &GraphicsSettings,
+	) -> Result<(RenderChunks, Terrain, TextureId), Error> {
 		let program = Program::from_shaders(&[
-			Shader::from_file(data_root.join("client/shaders/chunk.vs"), ShaderKind::Vertex)?,
-			Shader::from_file(data_root.join("client/shaders/chunk.fs"), ShaderKind::Fragement)?,
+			Shader::from_resource(
+				resources,
+				"client/shaders/chunk.vs",
+				ShaderKind::Vertex,
+				&Defines::new(),
+			)?,
+			Shader::from_resource(
+				resources,
+				"client/shaders/chunk.fs",
+				ShaderKind::Fragement,
+				&Defines::new(),
+			)?,
 		])?;
 
-		let atlas = TextureAtlas::new(data_root.join("client/textures/voxel_atlas.png"), 1)?;
-		let mut world_mesh = RenderChunks::new(std::rc::Rc::new(program), atlas);
-
-		let tex_id = TextureId::new(world_mesh.atlas.size, 0);
-
-		let mut palette = Palette::new();
-		palette.add_voxel(voxel::Voxel::new_full(VoxelTexture::Single { faces: tex_id }));
-
-		let size: i32 = 2;
-
-		let mut chunks = Vec::with_capacity((size * size) as usize);
-		for x in -size..size {
-			for z in -size..size {
-				for y in -size / 2..size / 2 {
-					chunks.push(generate_chunk(x, y, z, palette.clone()));
-					let chunk = chunks.last().unwrap();
-					world_mesh.add_mesh(
-						world::mesh_builder(chunk).build(),
-						(
-							(chunk.coord.0 * Chunk::WIDTH as i32) as f32,
-							(chunk.coord.1 * Chunk::HEIGHT as i32) as f32,
-							(chunk.coord.2 * Chunk::DEPTH as i32) as f32,
-						),
-					);
-				}
+		let looks: Vec<VoxelLook> = ron::de::from_bytes(&resources.read("client/voxels.ron")?)
+			.map_err(|e| Error::AssetError(Box::new(e)))?;
+		// A texture shared with a cutout voxel needs its holes kept in the distance
+		let mut transparency = HashMap::new();
+		for look in &looks {
+			let entry = transparency
+				.entry(look.texture.clone())
+				.or_insert(look.transparency);
+			if look.transparency == Transparency::Cutout {
+				*entry = Transparency::Cutout;
 			}
 		}
+		let atlas = TextureAtlas::from_dir(
+			resources,
+			"client/textures/voxels",
+			&transparency,
+			graphics.texture_filter,
+			graphics.anisotropy,
+		)?;
+		// Players are drawn with the texture of the first voxel
+		let entity_texture = atlas.texture_or_first(looks.first().map_or("", |look| look.texture.as_str()));
+		let terrain = Terrain::new(Self::voxel_palette(looks, &atlas)?);
+		let world_mesh = RenderChunks::new(std::rc::Rc::new(program), atlas);
 
-		Ok(world_mesh)
+		Ok((world_mesh, terrain, entity_texture))
+	}
+
+	/// The voxels the world is made of as described by `client/voxels.ron`, in the same order as the
+	/// server's palette
+	fn voxel_palette(looks: Vec<VoxelLook>, atlas: &TextureAtlas) -> Result<WorldPalette, Error> {
+		let mut palette = WorldPalette::new();
+		for look in looks {
+			palette.add_voxel(
+				voxel::Voxel::new_full()
+					.with_transparency(look.transparency)
+					.with_texture(VoxelTexture::Single {
+						faces: atlas.texture(&look.texture)?,
+					}),
+			);
+		}
+		Ok(palette)
+	}
+
+	/// Drops the meshes of unloaded chunks and rebuilds up to `max_uploads` meshes of chunks that have
+	/// changed, closest first. The rest wait for later frames. At least one is always rebuilt, or a setting
+	/// of zero would leave the world unmeshed.
+	fn update_meshes(&mut self, max_uploads: usize) {
+		let max_uploads = max_uploads.max(1);
+		let centre = self.player.chunk();
+		let mut dirty: Vec<_> = self.player.take_dirty_chunks().into_iter().collect();
+		dirty.sort_by_key(|c| (c.0 - centre.0).pow(2) + (c.1 - centre.1).pow(2) + (c.2 - centre.2).pow(2));
+
+		let mut deferred = Vec::new();
+		let mut uploads = 0;
+		{
+			let terrain = self.player.ecs().read_resource::<Terrain>();
+			for coord in dirty {
+				match terrain.chunk(coord) {
+					Some(chunk) if uploads < max_uploads => {
+						self.world_mesh
+							.set_mesh(coord, world::mesh_builder(chunk).build());
+						uploads += 1;
+					}
+					Some(_) => deferred.push(coord),
+					// Dropping the mesh frees its buffers
+					None => self.world_mesh.remove_mesh(coord),
+				}
+			}
+		}
+		self.player.defer_dirty_chunks(deferred);
 	}
 }
 
@@ -104,15 +219,23 @@ impl PlayState for GameScene {
 
 		self.player.collect_input(&events);
 		self.player.collect_net();
+		self.update_meshes(global_state.settings.graphics.mesh_uploads_per_frame as usize);
+		let resources = &global_state.resources;
+		if self
+			.shader_watcher
+			.as_mut()
+			.is_some_and(|watcher| watcher.reload_changed(resources))
+		{
+			// A rebuilt program starts without any uniforms set
+			self.world_mesh.shader.set_uniform_int("u_tex", 0);
+		}
 
 		while let Some(event) = events.pop() {
 			match event {
 				crate::window::Event::Close => next_state = PlayStateNext::Quit,
 				crate::window::Event::Resize([_x, _y]) => {}
-				crate::window::Event::MouseMove(x, y) => {
-					if self.cursor_grabbed {
-						mouse_delta = (x as f32, y as f32)
-					}
+				crate::window::Event::MouseMove(x, y) if self.cursor_grabbed => {
+					mouse_delta = (x as f32, y as f32)
 				}
 				crate::window::Event::InputChange(GameInput::Escape, true) => {
 					self.cursor_grabbed = !self.cursor_grabbed;
@@ -122,21 +245,39 @@ impl PlayState for GameScene {
 			}
 		}
 
+		self.player.look(mouse_delta.0, mouse_delta.1);
 		self.player.tick();
-		self.player
-			.update_camera(&mut self.camera, mouse_delta.0, mouse_delta.1);
+		self.player.update_camera(&mut self.camera);
 
 		next_state
 	}
 
-	fn draw(&mut self, _settings: &Settings) {
+	fn draw(&mut self, settings: &Settings) {
+		interpolation::interpolate(
+			self.player.ecs(),
+			Duration::from_millis(settings.network.interpolation_delay as u64),
+		);
+
 		self.world_mesh
 			.shader
 			.set_uniform_mat4("u_camera", self.camera.view_matrix());
 		self.world_mesh
 			.shader
 			.set_uniform_mat4("u_project", self.camera.proj_matrix());
+		self.world_mesh
+			.shader
+			.set_uniform_vec3("u_eye", self.camera.pos());
+		// Fog hides chunks before they come and go at the edge of the render distance
+		self.world_mesh
+			.shader
+			.set_uniform_float("u_fog_distance", self.player.render_distance() as f32);
+
+		self.sky.render(&self.camera);
 
-		self.world_mesh.render();
+		let frustum = Frustum::from_matrix(self.camera.proj_matrix() * self.camera.view_matrix());
+		self.world_mesh.render(&frustum, self.camera.pos());
+		log::trace!("{:?}", self.world_mesh.stats());
+		self.entities.render(self.player.ecs());
+		self.world_mesh.render_translucent();
 	}
 }
@@ -1,50 +1,104 @@
-use std::sync::Arc;
+use std::collections::{HashSet, VecDeque};
+use std::time::Instant;
 
 use crate::{
-	scene::camera::Camera,
+	net::{Connection, EntitySync},
+	scene::{
+		camera::Camera,
+		interpolation::{Interpolated, ServerClock},
+	},
 	window::{Event, GameInput},
 };
 use common::{
-	components::{Orientation, Player as PlayerComp, Position},
+	components::{OnGround, Orientation, Player as PlayerComp, Position, Velocity},
 	ecsres,
+	net::{
+		client::{InputFrame, PlayerAction, ServerBound},
+		server::{Auth, ClientBound, WorldUpdate},
+		world::WorldData,
+		EntityID, VPosition,
+	},
+	physics::{self, Body},
 	state::State,
+	world::terrain::Terrain,
 };
 
-use specs::{Builder, ReadStorage, World, WorldExt, WriteStorage};
-use tokio::runtime::Runtime;
+use specs::{Builder, World, WorldExt};
+use vek::Vec3;
 
+/// Degrees the view turns per unit of mouse movement
+const LOOK_SPEED: f64 = 0.15;
+/// Longest stretch of time simulated in one tick, so a long stall doesn't turn into a burst of inputs
+const MAX_CATCH_UP: f64 = 0.25;
+/// Most inputs kept waiting for the server to acknowledge them, ten seconds at the physics rate
+const MAX_PENDING_INPUTS: usize = 600;
 
 pub struct Player {
-	runtime: Arc<Runtime>,
 	inputs: Vec<Event>,
 	state: State,
+	connection: Option<Connection>,
+	entity_sync: EntitySync,
+	/// Chunks that have been loaded, changed or unloaded since they were last meshed
+	dirty_chunks: HashSet<(i32, i32, i32)>,
+	/// How far around us terrain is kept in voxels, lowered to the server's view distance once it answers
+	render_distance: u32,
+	/// The server's id for our own entity, once logged in
+	server_id: Option<EntityID>,
+	/// Inputs sent to the server that it hasn't acknowledged yet, replayed after every correction
+	pending: VecDeque<InputFrame>,
+	next_seq: u32,
+	/// Time that has passed but not been simulated yet
+	accumulator: f64,
+	last_tick: Instant,
 }
 
 impl Player {
-	pub fn new(runtime: Arc<Runtime>) -> Player {
+	/// Creates the local player in a world made of the chunks already loaded in `terrain`, keeping terrain
+	/// loaded within `render_distance` voxels
+	pub fn new(terrain: Terrain, render_distance: u32) -> Player {
 		let mut state = State::client();
 
 		let ecs = state.ecs_mut();
+		ecs.register::<Interpolated>();
+		ecs.insert(ServerClock::default());
+		ecs.insert(terrain);
 		let player = ecs
 			.create_entity()
 			.with(Position::default())
 			.with(Orientation::default())
-			.with(PlayerComp::default())
+			.with(Velocity::default())
+			.with(OnGround::default())
+			.with(PlayerComp)
 			.build();
 
 		*ecs.write_resource::<ecsres::Player>() = ecsres::Player(Some(player));
 
 		Player {
-			runtime,
 			inputs: vec![],
 			state,
+			connection: None,
+			entity_sync: EntitySync::default(),
+			dirty_chunks: HashSet::new(),
+			render_distance,
+			server_id: None,
+			pending: VecDeque::new(),
+			next_seq: 0,
+			accumulator: 0.0,
+			last_tick: Instant::now(),
 		}
 	}
 
-	pub fn collect_input(&mut self, events: &Vec<Event>) {
+	/// Play on the server at the other end of `connection`
+	pub fn set_connection(&mut self, connection: Connection) {
+		connection.send(ServerBound::ViewDistance {
+			distance: self.render_distance,
+		});
+		self.connection = Some(connection);
+	}
+
+	pub fn collect_input(&mut self, events: &[Event]) {
 		self.inputs.clear();
-		let mut events = events.iter();
-		while let Some(e) = events.next() {
+		for e in events {
 			match e {
 				Event::Input(_) | Event::MouseMove(_, _) => self.inputs.push(*e),
 				_ => {}
@@ -53,75 +107,273 @@ impl Player {
 	}
 
 	pub fn collect_net(&mut self) {
-		// unimplemented!()
+		loop {
+			let message = match &self.connection {
+				Some(connection) => connection.try_recv(),
+				None => return,
+			};
+			match message {
+				Ok(Some(ClientBound::Update(WorldUpdate::PlayerState {
+					seq,
+					pos,
+					vel,
+					on_ground,
+				}))) => self.reconcile(
+					seq,
+					Body {
+						pos: pos.into(),
+						vel: vel.into(),
+						on_ground,
+					},
+				),
+				Ok(Some(ClientBound::Update(WorldUpdate::BlockChange { pos, voxel_id }))) => {
+					let mut terrain = self.state.ecs().write_resource::<Terrain>();
+					terrain.set_voxel(pos, voxel_id);
+					// Chunks the terrain counts as changed need meshing again, we never save them
+					self.dirty_chunks.extend(terrain.take_dirty());
+				}
+				Ok(Some(ClientBound::Update(WorldUpdate::EntityTeleport { entity, pos })))
+					if Some(entity) == self.server_id =>
+				{
+					// The server refused our movement, anything we predicted since is void
+					self.pending.clear();
+					self.set_body(Body {
+						pos: pos.to_vec3(),
+						..Body::default()
+					});
+				}
+				Ok(Some(ClientBound::Update(WorldUpdate::ViewDistance { distance }))) => {
+					// Chunks past what the server sends would only be dropped as we walk away from them
+					self.render_distance = distance;
+				}
+				Ok(Some(ClientBound::Update(WorldUpdate::TimeOfDay { ticks }))) => {
+					self.ecs().write_resource::<ecsres::TimeOfDay>().0 = ticks;
+				}
+				Ok(Some(ClientBound::Update(update))) => {
+					self.entity_sync.apply(self.state.ecs_mut(), &update)
+				}
+				Ok(Some(ClientBound::Data(WorldData::ChunkData { pos, palette, voxels }))) => {
+					if self
+						.ecs()
+						.write_resource::<Terrain>()
+						.insert_chunk_data(pos, &palette, voxels)
+					{
+						self.dirty_chunks.insert((pos.x, pos.y, pos.z));
+					} else {
+						log::warn!("server sent an invalid chunk at {:?}", pos);
+					}
+				}
+				Ok(Some(ClientBound::Data(WorldData::UnloadChunk { pos }))) => {
+					self.ecs()
+						.write_resource::<Terrain>()
+						.remove_chunk((pos.x, pos.y, pos.z));
+					self.dirty_chunks.insert((pos.x, pos.y, pos.z));
+				}
+				Ok(Some(ClientBound::Auth(Auth::LoginSuccess { id, .. }))) => self.server_id = Some(id),
+				Ok(Some(ClientBound::Auth(Auth::Disconnect { reason }))) => {
+					log::warn!("disconnected from server: {}", reason);
+					self.connection = None;
+					break;
+				}
+				// There is nowhere to show chat in game yet, so it goes to the log
+				Ok(Some(ClientBound::Chat { sender, message })) => match sender {
+					Some(sender) => log::info!("<{}> {}", sender, message),
+					None => log::info!("{}", message),
+				},
+				Ok(Some(ClientBound::KeepAlive { id })) => {
+					if let Some(connection) = &self.connection {
+						connection.send(ServerBound::KeepAlive { id });
+					}
+				}
+				Ok(Some(_)) => {}
+				Ok(None) => break,
+				Err(e) => {
+					log::warn!("lost connection to server: {:?}", e);
+					self.connection = None;
+					break;
+				}
+			}
+		}
+	}
+
+	/// How far around us terrain is kept, in voxels
+	pub fn render_distance(&self) -> u32 {
+		self.render_distance
+	}
+
+	/// Returns the chunks whose meshes are out of date
+	pub fn take_dirty_chunks(&mut self) -> HashSet<(i32, i32, i32)> {
+		std::mem::take(&mut self.dirty_chunks)
+	}
+
+	/// Hands back chunks from `Player::take_dirty_chunks` that weren't meshed this time round
+	pub fn defer_dirty_chunks(&mut self, chunks: impl IntoIterator<Item = (i32, i32, i32)>) {
+		self.dirty_chunks.extend(chunks);
+	}
+
+	/// Coordinate of the chunk we are standing in
+	pub fn chunk(&self) -> (i32, i32, i32) {
+		let player = self.ecs_self();
+		let pos = self
+			.ecs()
+			.read_storage::<Position>()
+			.get(player)
+			.map_or(Vec3::zero(), |p| p.0);
+		Terrain::split_pos(VPosition::new(
+			pos.x.floor() as i32,
+			pos.y.floor() as i32,
+			pos.z.floor() as i32,
+		))
+		.0
+	}
+
+	/// Drops chunks that are past the render distance, in case the server is slow to unload them. A chunk
+	/// of leeway stops chunks on the edge from being dropped and sent again as we move back and forth.
+	fn unload_distant_chunks(&mut self) {
+		let centre = self.chunk();
+		let (radius_h, radius_v) = Terrain::chunk_radius(self.render_distance);
+		let mut terrain = self.state.ecs().write_resource::<Terrain>();
+		let distant: Vec<_> = terrain
+			.chunk_coords()
+			.filter(|c| {
+				(c.0 - centre.0).abs() > radius_h + 1
+					|| (c.1 - centre.1).abs() > radius_v + 1
+					|| (c.2 - centre.2).abs() > radius_h + 1
+			})
+			.collect();
+		for coord in distant {
+			terrain.remove_chunk(coord);
+			self.dirty_chunks.insert(coord);
+		}
 	}
 
 	pub fn tick(&mut self) {
-		self.handle_movement();
+		let now = Instant::now();
+		self.accumulator += now.duration_since(self.last_tick).as_secs_f64();
+		self.accumulator = self.accumulator.min(MAX_CATCH_UP);
+		self.last_tick = now;
+
+		while self.accumulator >= physics::STEP {
+			self.accumulator -= physics::STEP;
+			self.step_movement();
+		}
+		self.unload_distant_chunks();
 
 		self.ecs_mut().maintain();
 	}
 
-	fn handle_movement(&mut self) {
-		let mut in_pos = [0.0; 6];
-		let mut in_dir = (0.0, 0.0);
+	/// Turns the player's view by a mouse movement
+	pub fn look(&mut self, dx: f32, dy: f32) {
+		let player = self.ecs_self();
+		if let Some(ori) = self.ecs().write_storage::<Orientation>().get_mut(player) {
+			ori.0.x = (ori.0.x + dx as f64 * LOOK_SPEED).rem_euclid(360.0);
+			// Moving the mouse down looks down
+			ori.0.y = (ori.0.y - dy as f64 * LOOK_SPEED).clamp(-89.5, 89.5);
+		}
+	}
+
+	/// Samples the held inputs into a frame, predicts its result and sends it to the server
+	fn step_movement(&mut self) {
+		let player = self.ecs_self();
+		let ori = self
+			.ecs()
+			.read_storage::<Orientation>()
+			.get(player)
+			.map_or(Vec3::zero(), |o| o.0);
+
+		let mut frame = InputFrame {
+			seq: self.next_seq,
+			yaw: ori.x as f32,
+			pitch: ori.y as f32,
+			..InputFrame::default()
+		};
+		self.next_seq = self.next_seq.wrapping_add(1);
 		for e in &self.inputs {
 			match e {
-				Event::Input(GameInput::MoveForward) => in_pos[0] = 1.0,
-				Event::Input(GameInput::MoveBackwards) => in_pos[1] = -1.0,
-				Event::Input(GameInput::MoveLeft) => in_pos[2] = -1.0,
-				Event::Input(GameInput::MoveRight) => in_pos[3] = 1.0,
-				Event::Input(GameInput::FlyUp) => in_pos[4] = 1.0,
-				Event::Input(GameInput::FlyDown) => in_pos[5] = -1.0,
-				Event::MouseMove(x, y) => in_dir = (*x as f64, *y as f64),
+				Event::Input(GameInput::MoveForward) => frame.forward = true,
+				Event::Input(GameInput::MoveBackwards) => frame.backward = true,
+				Event::Input(GameInput::MoveLeft) => frame.left = true,
+				Event::Input(GameInput::MoveRight) => frame.right = true,
+				Event::Input(GameInput::FlyUp) => frame.jump = true,
+				Event::Input(GameInput::Sprint) => frame.sprint = true,
 				_ => {}
 			}
 		}
 
-		let world = self.ecs();
-		let player = self.ecs_self();
-
-		let mut pos_storage = world.system_data::<WriteStorage<Position>>();
-		let pos = pos_storage.get_mut(player).unwrap();
-		let mut dir_storage = world.system_data::<WriteStorage<Orientation>>();
-		let dir = dir_storage.get_mut(player).unwrap();
-
-		const SPEED: f64 = 0.1;
+		let mut body = self.body();
+		physics::step(&self.ecs().read_resource::<Terrain>(), &mut body, &frame);
+		self.set_body(body);
 
-		// let mut pos_off = DVec3::new(
-		// 	in_pos[0] + in_pos[1],
-		// 	in_pos[4] + in_pos[5],
-		// 	in_pos[2] + in_pos[3],
-		// ) * SPEED;
+		if let Some(connection) = &self.connection {
+			connection.send(ServerBound::PlayerAction(PlayerAction::PlayerInput(frame)));
+			if self.pending.len() == MAX_PENDING_INPUTS {
+				self.pending.pop_front();
+			}
+			self.pending.push_back(frame);
+		}
+	}
 
-		// let yaw = in_dir.0;
-		// let pitch = in_dir.1;
+	/// Moves the player to where the server says it is after input `seq`, then replays the inputs the
+	/// server hasn't seen yet on top
+	fn reconcile(&mut self, seq: u32, server: Body) {
+		while matches!(self.pending.front(), Some(f) if f.seq.wrapping_sub(seq) as i32 <= 0) {
+			self.pending.pop_front();
+		}
 
-		// let face = DVec3::new(
-		// 	yaw.to_radians().cos() * pitch.to_radians().cos(),
-		// 	pitch.to_radians().sin(),
-		// 	yaw.to_radians().sin() * pitch.to_radians().cos(),
-		// );
+		let mut body = server;
+		{
+			let terrain = self.ecs().read_resource::<Terrain>();
+			for frame in &self.pending {
+				physics::step(&terrain, &mut body, frame);
+			}
+		}
+		self.set_body(body);
+	}
 
-		// println!("{:?}", face);
+	fn body(&self) -> Body {
+		let player = self.ecs_self();
+		let world = self.ecs();
+		Body {
+			pos: world
+				.read_storage::<Position>()
+				.get(player)
+				.map_or(Vec3::zero(), |p| p.0),
+			vel: world
+				.read_storage::<Velocity>()
+				.get(player)
+				.map_or(Vec3::zero(), |v| v.0),
+			on_ground: world.read_storage::<OnGround>().get(player).is_some_and(|g| g.0),
+		}
+	}
 
-		// dir.0 = dir.0.cross(face);
-		// pos_off *= dir.0;
-		// pos.0 += pos_off;
+	fn set_body(&mut self, body: Body) {
+		let player = self.ecs_self();
+		let world = self.ecs();
+		if let Some(p) = world.write_storage::<Position>().get_mut(player) {
+			p.0 = body.pos;
+		}
+		if let Some(v) = world.write_storage::<Velocity>().get_mut(player) {
+			v.0 = body.vel;
+		}
+		if let Some(g) = world.write_storage::<OnGround>().get_mut(player) {
+			g.0 = body.on_ground;
+		}
 	}
 
-	pub fn update_camera(&self, camera: &mut Camera, yaw: f32, pitch: f32) {
+	pub fn update_camera(&self, camera: &mut Camera) {
 		let world = self.ecs();
 		let player = self.ecs_self();
 		let pos = world
-			.system_data::<ReadStorage<Position>>()
+			.read_storage::<Position>()
+			.get(player)
+			.map_or(Vec3::zero(), |p| p.0);
+		let ori = world
+			.read_storage::<Orientation>()
 			.get(player)
-			.unwrap()
-			.0;
+			.map_or(Vec3::zero(), |o| o.0);
 
-		let x = [pos.x as f32, pos.y as f32, pos.z as f32].into();
-		camera.set_pos(x);
-		camera.rotate(yaw * 1.5, pitch * 1.5);
+		camera.set_pos((pos + Vec3::new(0.0, PlayerComp::EYE_HEIGHT, 0.0)).map(|v| v as f32));
+		camera.set_rot(ori.x as f32, ori.y as f32);
 
 		camera.update();
 	}
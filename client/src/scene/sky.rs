@@ -0,0 +1,70 @@
+use crate::render::{
+	preprocess::Defines,
+	shader::{Program, Shader, ShaderKind},
+};
+use crate::{resources::Resources, scene::camera::Camera, Error};
+
+use std::rc::Rc;
+use vek::Vec4;
+
+/// The sky behind the world, fading from the colour at the horizon to the one overhead. The fog in the
+/// chunk shader fades into the same colours.
+pub struct RenderSky {
+	pub shader: Rc<Program>,
+	/// Core profile won't draw without one bound, though the sky has no vertices to read
+	vao: gl::types::GLuint,
+}
+
+impl RenderSky {
+	pub fn new(resources: &Resources) -> Result<RenderSky, Error> {
+		let shader = Program::from_shaders(&[
+			Shader::from_resource(
+				resources,
+				"client/shaders/sky.vs",
+				ShaderKind::Vertex,
+				&Defines::new(),
+			)?,
+			Shader::from_resource(
+				resources,
+				"client/shaders/sky.fs",
+				ShaderKind::Fragement,
+				&Defines::new(),
+			)?,
+		])?;
+		let mut vao = 0;
+		unsafe {
+			gl::CreateVertexArrays(1, &mut vao);
+		}
+		Ok(RenderSky {
+			shader: Rc::new(shader),
+			vao,
+		})
+	}
+
+	/// Fills the screen with the sky, to be drawn before anything else
+	pub fn render(&self, camera: &Camera) {
+		// Only which way the camera faces matters, not where it is
+		let mut view = camera.view_matrix();
+		view.cols.w = Vec4::unit_w();
+		self.shader
+			.set_uniform_mat4("u_inverse", (camera.proj_matrix() * view).inverted());
+
+		self.shader.bind();
+		unsafe {
+			gl::Disable(gl::DEPTH_TEST);
+			gl::DepthMask(gl::FALSE);
+			gl::BindVertexArray(self.vao);
+			gl::DrawArrays(gl::TRIANGLES, 0, 3);
+			gl::DepthMask(gl::TRUE);
+			gl::Enable(gl::DEPTH_TEST);
+		}
+	}
+}
+
+impl core::ops::Drop for RenderSky {
+	fn drop(&mut self) {
+		unsafe {
+			gl::DeleteVertexArrays(1, &self.vao);
+		}
+	}
+}
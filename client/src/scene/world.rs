@@ -3,16 +3,66 @@ use crate::render::{
 	shader::Program,
 	texture::TextureAtlas,
 };
+use crate::scene::frustum::Frustum;
 
-use common::world::{chunk::Chunk, voxel::VoxelTexture};
+use common::world::{
+	chunk::Chunk,
+	voxel::{Transparency, Voxel, VoxelTexture},
+};
+
+use std::collections::HashMap;
+
+/// Whether the face of `voxel` that touches `neighbour` can be seen. Faces between two of the same
+/// translucent voxel are left out so a body of water or glass only shows its outside.
+fn shows_face(voxel: &Voxel, neighbour: &Voxel) -> bool {
+	if neighbour.is_air {
+		return true;
+	}
+	match neighbour.transparency {
+		Transparency::Opaque => false,
+		Transparency::Cutout => true,
+		Transparency::Translucent => voxel.transparency != Transparency::Translucent || voxel != neighbour,
+	}
+}
+
+/// The meshes of a chunk before they are uploaded, translucent voxels are kept apart so they can be drawn
+/// after everything else
+pub struct ChunkMeshBuilder {
+	/// Opaque and cutout voxels
+	pub opaque: MeshBuilder<ChunkVertex>,
+	pub translucent: MeshBuilder<ChunkVertex>,
+}
+
+impl ChunkMeshBuilder {
+	pub fn build(self) -> ChunkMesh {
+		ChunkMesh {
+			opaque: self.opaque.build(),
+			translucent: Some(self.translucent)
+				.filter(|m| !m.is_empty())
+				.map(MeshBuilder::build),
+		}
+	}
+}
 
-pub fn mesh_builder(chunk: &Chunk) -> MeshBuilder<ChunkVertex> {
-	let mut mesh = MeshBuilder::new();
+pub struct ChunkMesh {
+	opaque: Mesh<ChunkVertex>,
+	translucent: Option<Mesh<ChunkVertex>>,
+}
+
+#[allow(clippy::identity_op)]
+pub fn mesh_builder(chunk: &Chunk) -> ChunkMeshBuilder {
+	let mut opaque = MeshBuilder::new();
+	let mut translucent = MeshBuilder::new();
 	for y in 0..Chunk::HEIGHT {
 		for z in 0..Chunk::DEPTH {
 			for x in 0..Chunk::WIDTH {
 				if !chunk.is_air(x, y, z) {
-					let tex = match chunk.get_voxel(x, y, z).texture {
+					let voxel = chunk.get_voxel(x, y, z);
+					let mesh = match voxel.transparency {
+						Transparency::Translucent => &mut translucent,
+						Transparency::Opaque | Transparency::Cutout => &mut opaque,
+					};
+					let tex = match voxel.texture {
 						VoxelTexture::Single { faces } => faces,
 						_ => panic!("lazy"),
 					};
@@ -20,7 +70,7 @@ pub fn mesh_builder(chunk: &Chunk) -> MeshBuilder<ChunkVertex> {
 					let ti = tex.index;
 
 					// Back face
-					if z == 0 || chunk.is_air(x, y, z.saturating_sub(1)) {
+					if z == 0 || shows_face(voxel, chunk.get_voxel(x, y, z - 1)) {
 						mesh.push_quad(
 							&ChunkVertex::new(1 + x as u8, 1 + y as u8, 0 + z as u8, 0, 0, -1, ts, ts, ti),
 							&ChunkVertex::new(0 + x as u8, 1 + y as u8, 0 + z as u8, 0, 0, -1, 0, ts, ti),
@@ -29,7 +79,7 @@ pub fn mesh_builder(chunk: &Chunk) -> MeshBuilder<ChunkVertex> {
 						);
 					}
 					// Front face
-					if z == Chunk::DEPTH - 1 || chunk.is_air(x, y, z.saturating_add(1)) {
+					if z == Chunk::DEPTH - 1 || shows_face(voxel, chunk.get_voxel(x, y, z + 1)) {
 						mesh.push_quad(
 							&ChunkVertex::new(1 + x as u8, 0 + y as u8, 1 + z as u8, 0, 0, 1, ts, 0, ti),
 							&ChunkVertex::new(0 + x as u8, 0 + y as u8, 1 + z as u8, 0, 0, 1, 0, 0, ti),
@@ -38,7 +88,7 @@ pub fn mesh_builder(chunk: &Chunk) -> MeshBuilder<ChunkVertex> {
 						);
 					}
 					// Left face
-					if x == 0 || chunk.is_air(x.saturating_sub(1), y, z) {
+					if x == 0 || shows_face(voxel, chunk.get_voxel(x - 1, y, z)) {
 						mesh.push_quad(
 							&ChunkVertex::new(0 + x as u8, 1 + y as u8, 0 + z as u8, -1, 0, 0, ts, ts, ti),
 							&ChunkVertex::new(0 + x as u8, 1 + y as u8, 1 + z as u8, -1, 0, 0, 0, ts, ti),
@@ -47,7 +97,7 @@ pub fn mesh_builder(chunk: &Chunk) -> MeshBuilder<ChunkVertex> {
 						);
 					}
 					// Right face
-					if x == Chunk::WIDTH - 1 || chunk.is_air(x.saturating_add(1), y, z) {
+					if x == Chunk::WIDTH - 1 || shows_face(voxel, chunk.get_voxel(x + 1, y, z)) {
 						mesh.push_quad(
 							&ChunkVertex::new(1 + x as u8, 0 + y as u8, 0 + z as u8, 1, 0, 0, ts, 0, ti),
 							&ChunkVertex::new(1 + x as u8, 0 + y as u8, 1 + z as u8, 1, 0, 0, 0, 0, ti),
@@ -56,7 +106,7 @@ pub fn mesh_builder(chunk: &Chunk) -> MeshBuilder<ChunkVertex> {
 						);
 					}
 					// Bottom face
-					if y == 0 || chunk.is_air(x, y.saturating_sub(1), z) {
+					if y == 0 || shows_face(voxel, chunk.get_voxel(x, y - 1, z)) {
 						mesh.push_quad(
 							&ChunkVertex::new(0 + x as u8, 0 + y as u8, 1 + z as u8, 0, 1, 0, ts, 0, ti),
 							&ChunkVertex::new(1 + x as u8, 0 + y as u8, 1 + z as u8, 0, 1, 0, 0, 0, ti),
@@ -65,7 +115,7 @@ pub fn mesh_builder(chunk: &Chunk) -> MeshBuilder<ChunkVertex> {
 						);
 					}
 					// Top face
-					if y == Chunk::HEIGHT - 1 || chunk.is_air(x, y.saturating_add(1), z) {
+					if y == Chunk::HEIGHT - 1 || shows_face(voxel, chunk.get_voxel(x, y + 1, z)) {
 						mesh.push_quad(
 							&ChunkVertex::new(0 + x as u8, 1 + y as u8, 0 + z as u8, 0, -1, 0, ts, ts, ti),
 							&ChunkVertex::new(1 + x as u8, 1 + y as u8, 0 + z as u8, 0, -1, 0, 0, ts, ti),
@@ -77,43 +127,122 @@ pub fn mesh_builder(chunk: &Chunk) -> MeshBuilder<ChunkVertex> {
 			}
 		}
 	}
-	mesh
+	ChunkMeshBuilder { opaque, translucent }
+}
+
+/// How many chunks the last `RenderChunks::render` drew and how many it skipped as out of view
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChunkStats {
+	pub drawn: usize,
+	pub culled: usize,
 }
 
 pub struct RenderChunks {
 	pub shader: std::rc::Rc<Program>,
 	pub atlas: TextureAtlas,
-	meshes: Vec<((f32, f32, f32), Mesh<ChunkVertex>)>,
+	meshes: HashMap<(i32, i32, i32), ChunkMesh>,
+	/// Chunks drawn by the last opaque pass, nearest first
+	visible: Vec<(i32, i32, i32)>,
+	stats: ChunkStats,
 }
 
 impl RenderChunks {
 	pub fn new(shader: std::rc::Rc<Program>, atlas: TextureAtlas) -> Self {
 		Self {
 			shader,
-			meshes: vec![],
+			meshes: HashMap::new(),
+			visible: Vec::new(),
 			atlas,
+			stats: ChunkStats::default(),
 		}
 	}
 
-	pub fn add_mesh(&mut self, mesh: Mesh<ChunkVertex>, coord: (f32, f32, f32)) {
-		self.meshes.push((coord, mesh));
+	/// Sets the mesh drawn for the chunk at `coord`, replacing any mesh it had before
+	pub fn set_mesh(&mut self, coord: (i32, i32, i32), mesh: ChunkMesh) {
+		self.meshes.insert(coord, mesh);
+	}
+
+	pub fn remove_mesh(&mut self, coord: (i32, i32, i32)) {
+		self.meshes.remove(&coord);
+	}
+
+	pub fn stats(&self) -> ChunkStats {
+		self.stats
+	}
+
+	/// Draws the opaque and cutout parts of the chunks inside `frustum`, nearest to `eye` first so that
+	/// hidden surfaces are rejected before they are shaded
+	pub fn render(&mut self, frustum: &Frustum, eye: vek::Vec3<f32>) {
+		let mut visible: Vec<_> = self
+			.meshes
+			.keys()
+			.filter(|coord| {
+				let min = chunk_origin(**coord);
+				frustum.intersects_aabb(min, min + chunk_size())
+			})
+			.map(|coord| {
+				(
+					(chunk_origin(*coord) + chunk_size() / 2.0).distance_squared(eye),
+					*coord,
+				)
+			})
+			.collect();
+		visible.sort_by(|a, b| a.0.total_cmp(&b.0));
+		self.visible = visible.into_iter().map(|(_, coord)| coord).collect();
+
+		self.shader.bind();
+		self.shader
+			.set_uniform_float("u_alpha_cutoff", OPAQUE_ALPHA_CUTOFF);
+		for coord in &self.visible {
+			self.shader
+				.set_uniform_mat4("u_model", vek::Mat4::translation_3d(chunk_origin(*coord)));
+			self.meshes[coord].opaque.render();
+		}
+		self.stats = ChunkStats {
+			drawn: self.visible.len(),
+			culled: self.meshes.len() - self.visible.len(),
+		};
 	}
 
-	pub fn render(&self) {
+	/// Blends the translucent parts of the chunks drawn by the last `RenderChunks::render` over the scene,
+	/// furthest first. Goes after everything opaque has been drawn.
+	pub fn render_translucent(&self) {
 		self.shader.bind();
-		for (coord, mesh) in &self.meshes {
-			let mut transform = vek::Mat4::identity();
-			transform.translate_3d(vek::Vec3::new(
-				coord.0 as f32,
-				coord.1 as f32,
-				coord.2 as f32,
-			));
-			self.shader.set_uniform_mat4("u_model", transform);
-			mesh.render();
+		self.shader.set_uniform_float("u_alpha_cutoff", 0.0);
+		unsafe {
+			gl::Enable(gl::BLEND);
+			gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+			// Translucent surfaces don't hide each other, and are seen from both sides
+			gl::DepthMask(gl::FALSE);
+			gl::Disable(gl::CULL_FACE);
+		}
+		for coord in self.visible.iter().rev() {
+			if let Some(mesh) = &self.meshes[coord].translucent {
+				self.shader
+					.set_uniform_mat4("u_model", vek::Mat4::translation_3d(chunk_origin(*coord)));
+				mesh.render();
+			}
+		}
+		unsafe {
+			gl::Enable(gl::CULL_FACE);
+			gl::DepthMask(gl::TRUE);
+			gl::Disable(gl::BLEND);
 		}
 	}
 }
 
+/// Texels less opaque than this are cut out of opaque and cutout voxels
+const OPAQUE_ALPHA_CUTOFF: f32 = 0.5;
+
+fn chunk_size() -> vek::Vec3<f32> {
+	vek::Vec3::new(Chunk::WIDTH as f32, Chunk::HEIGHT as f32, Chunk::DEPTH as f32)
+}
+
+/// Corner of a chunk with the lowest coordinates, in world space
+fn chunk_origin(coord: (i32, i32, i32)) -> vek::Vec3<f32> {
+	vek::Vec3::new(coord.0 as f32, coord.1 as f32, coord.2 as f32) * chunk_size()
+}
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C, packed)]
 pub struct ChunkVertex {
@@ -123,6 +252,7 @@ pub struct ChunkVertex {
 }
 
 impl ChunkVertex {
+	#[allow(clippy::too_many_arguments)]
 	pub const fn new(d0: u8, d1: u8, d2: u8, d3: i8, d4: i8, d5: i8, d6: u16, d7: u16, d8: u16) -> Self {
 		Self {
 			pos: data::u8_3::new(d0, d1, d2),
@@ -146,3 +276,55 @@ impl Vertex for ChunkVertex {
 		unsafe { data::u16_3::set_vertex_attrib(stride, location, offset) }
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use common::world::{chunk::Palette, voxel::TextureId};
+
+	fn voxel(index: u16, transparency: Transparency) -> Voxel {
+		Voxel::new_full()
+			.with_transparency(transparency)
+			.with_texture(VoxelTexture::Single {
+				faces: TextureId::new(32, index),
+			})
+	}
+
+	/// Meshes two voxels side by side, giving the number of faces in the opaque and translucent meshes
+	fn faces(left: Voxel, right: Voxel) -> (usize, usize) {
+		let mut palette = Palette::new();
+		palette.add_voxel(left.clone());
+		palette.add_voxel(right.clone());
+		let mut chunk = Chunk::new((0, 0, 0), palette);
+		chunk.set_voxel(1, 1, 1, chunk.palette_id(&left).unwrap());
+		chunk.set_voxel(2, 1, 1, chunk.palette_id(&right).unwrap());
+		let mesh = mesh_builder(&chunk);
+		(mesh.opaque.len() / 6, mesh.translucent.len() / 6)
+	}
+
+	#[test]
+	fn opaque_voxels_hide_the_faces_between_them() {
+		let dirt = voxel(0, Transparency::Opaque);
+		let stone = voxel(1, Transparency::Opaque);
+		assert_eq!(faces(dirt, stone), (10, 0));
+	}
+
+	#[test]
+	fn faces_behind_cutout_voxels_are_kept() {
+		let dirt = voxel(0, Transparency::Opaque);
+		let leaves = voxel(1, Transparency::Cutout);
+		assert_eq!(faces(dirt, leaves.clone()), (11, 0));
+		assert_eq!(faces(leaves.clone(), leaves), (12, 0));
+	}
+
+	#[test]
+	fn faces_between_the_same_translucent_voxel_are_left_out() {
+		let dirt = voxel(0, Transparency::Opaque);
+		let glass = voxel(1, Transparency::Translucent);
+		let water = voxel(2, Transparency::Translucent);
+		// Dirt shows through the glass, but the glass is hidden behind the dirt
+		assert_eq!(faces(dirt, glass.clone()), (6, 5));
+		assert_eq!(faces(glass.clone(), glass.clone()), (0, 10));
+		assert_eq!(faces(glass, water), (0, 12));
+	}
+}
@@ -3,6 +3,7 @@ use crate::window::GameInput;
 use common::config_root;
 use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize, Serializer};
@@ -56,10 +57,63 @@ impl std::default::Default for InputSettings {
 }
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct GraphicsSettings {
 	pub window_size: [u32; 2],
 	pub vsync: bool,
 	pub fov: f32,
+	/// How far around the player terrain is loaded and drawn in voxels, servers may cap it to their own
+	/// view distance
+	pub render_distance: u32,
+	/// Most chunk meshes built and uploaded each frame, the closest chunks go first. At least one always is.
+	pub mesh_uploads_per_frame: u32,
+	/// How voxel textures are smoothed when seen from afar
+	pub texture_filter: TextureFilter,
+	/// Most samples taken along surfaces seen at a glancing angle, `1` turns anisotropic filtering off
+	pub anisotropy: u32,
+	/// Effects applied to the whole screen once the world is drawn, in order
+	pub postprocess: Vec<PostProcess>,
+	/// Gamma the `Gamma` effect corrects for
+	pub gamma: f32,
+}
+
+/// How textures are sampled when they are drawn smaller than they are. Up close they are always left
+/// blocky.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureFilter {
+	/// No smoothing, distant terrain shimmers as it moves
+	Nearest,
+	/// Smooths within the closest mip level
+	Bilinear,
+	/// Also blends between mip levels, so there are no seams where one gives way to the next
+	Trilinear,
+}
+
+/// An effect applied to the whole screen, each is a fragment shader in `client/shaders/postprocess`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum PostProcess {
+	/// Smooths jagged edges
+	Fxaa,
+	/// Brings colours brighter than white back into range
+	Tonemap,
+	Gamma,
+	/// Darkens the corners of the screen
+	Vignette,
+	/// The shader `<name>.fs`, such as one from a resource pack
+	Custom(String),
+}
+
+impl PostProcess {
+	/// Name of the effect's shader without the extension
+	pub fn shader_name(&self) -> &str {
+		match self {
+			PostProcess::Fxaa => "fxaa",
+			PostProcess::Tonemap => "tonemap",
+			PostProcess::Gamma => "gamma",
+			PostProcess::Vignette => "vignette",
+			PostProcess::Custom(name) => name,
+		}
+	}
 }
 
 impl std::default::Default for GraphicsSettings {
@@ -68,25 +122,51 @@ impl std::default::Default for GraphicsSettings {
 			window_size: [1280, 720],
 			vsync: true,
 			fov: 90.0,
+			render_distance: 32,
+			mesh_uploads_per_frame: 4,
+			texture_filter: TextureFilter::Trilinear,
+			anisotropy: 8,
+			postprocess: vec![PostProcess::Fxaa],
+			gamma: 2.2,
 		}
 	}
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct Settings {
-	pub graphics: GraphicsSettings,
-	pub input: InputSettings,
+#[serde(default)]
+pub struct NetworkSettings {
+	/// Server to join on start up, `None` plays singleplayer on a server inside the client
+	pub server_address: Option<SocketAddr>,
+	pub username: String,
+	/// How far in the past other players are drawn in milliseconds, higher values hide more network
+	/// jitter at the cost of seeing others later
+	pub interpolation_delay: u32,
+	/// Seconds without hearing from the server before the connection is given up on
+	pub timeout_secs: u32,
 }
 
-impl std::default::Default for Settings {
+impl std::default::Default for NetworkSettings {
 	fn default() -> Self {
-		Settings {
-			graphics: GraphicsSettings::default(),
-			input: InputSettings::default(),
+		Self {
+			server_address: None,
+			username: "Player".to_owned(),
+			interpolation_delay: 100,
+			timeout_secs: 30,
 		}
 	}
 }
 
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Settings {
+	pub graphics: GraphicsSettings,
+	pub input: InputSettings,
+	pub network: NetworkSettings,
+	/// Directories or zip files laid over the game data in order, files in later packs replace those in
+	/// earlier ones. Relative paths are inside the config directory's `resourcepacks`.
+	pub resource_packs: Vec<PathBuf>,
+}
+
 impl Settings {
 	pub fn load() -> Settings {
 		let path = Self::settings_path();
@@ -135,8 +135,8 @@ impl Window {
 	/// Handles all relevant `event::WindowEvent`'s in our main event loop
 	pub fn handle_window_event(&mut self, event: winit::event::Event<()>, settings: &mut Settings) {
 		use winit::event::WindowEvent;
-		match event {
-			winit::event::Event::WindowEvent { event, .. } => match event {
+		if let winit::event::Event::WindowEvent { event, .. } = event {
+			match event {
 				WindowEvent::CloseRequested => self.events.push(Event::Close),
 				WindowEvent::Resized(s) => {
 					self.renderer.viewport(0, 0, s.width, s.height);
@@ -157,29 +157,27 @@ impl Window {
 					}
 				}
 				_ => {}
-			},
-			_ => {}
+			}
 		}
 	}
 
 	/// Handles all relevant `event::DeviceEvent`'s in our main event loop
 	pub fn handle_device_event(&mut self, event: winit::event::Event<()>) {
 		use winit::event::DeviceEvent;
-		match event {
-			winit::event::Event::DeviceEvent { event, .. } => match event {
-				DeviceEvent::MouseMotion { delta: (x, y) } => self.events.push(Event::MouseMove(x, y)),
-				_ => {}
-			},
-			_ => {}
+		if let winit::event::Event::DeviceEvent {
+			event: DeviceEvent::MouseMotion { delta: (x, y) },
+			..
+		} = event
+		{
+			self.events.push(Event::MouseMove(x, y))
 		}
 	}
 
 	/// Returns a bool for wether a key is pressed or not
 	pub fn is_key_pressed(&self, input: GameInput) -> bool {
 		if let Some(state) = self.keypress_map.get(&input) {
-			match state {
-				winit::event::ElementState::Pressed => return true,
-				_ => {}
+			if state == &winit::event::ElementState::Pressed {
+				return true;
 			}
 		}
 		false
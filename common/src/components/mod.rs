@@ -8,7 +8,16 @@ impl<C: specs::Component + Sync + Send> specs::Component for Last<C> {
 	type Storage = specs::VecStorage<Self>;
 }
 
+/// The id an entity is known by over the network. Unlike specs' entity ids these are never reused, so a
+/// client that missed a despawn can't mistake a new entity for the one it replaced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NetworkId(pub crate::net::EntityID);
+
+impl specs::Component for NetworkId {
+	type Storage = specs::VecStorage<Self>;
+}
+
 pub use {
-	physics::{Gravity, Orientation, Position},
+	physics::{Gravity, OnGround, Orientation, Position, Velocity},
 	player::Player,
 };
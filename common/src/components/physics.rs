@@ -1,20 +1,36 @@
 use specs::prelude::*;
 use vek::Vec3;
 
-#[derive(Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Position(pub Vec3<f64>);
 
 impl Component for Position {
 	type Storage = VecStorage<Self>;
 }
 
-#[derive(Default)]
+/// Rotation of an entity in degrees, `x` is yaw and `y` is pitch
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Orientation(pub Vec3<f64>);
 
 impl Component for Orientation {
 	type Storage = VecStorage<Self>;
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Velocity(pub Vec3<f64>);
+
+impl Component for Velocity {
+	type Storage = VecStorage<Self>;
+}
+
+/// Whether an entity is standing on something, set by the physics step
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OnGround(pub bool);
+
+impl Component for OnGround {
+	type Storage = VecStorage<Self>;
+}
+
 #[derive(Default)]
 pub struct Gravity;
 
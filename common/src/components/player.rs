@@ -3,6 +3,17 @@ use specs::prelude::*;
 #[derive(Default)]
 pub struct Player;
 
+impl Player {
+	/// Width of a player's bounding box along `x` and `z`
+	pub const WIDTH: f64 = 0.6;
+	/// Height of a player's bounding box, their `Position` is at the bottom centre
+	pub const HEIGHT: f64 = 1.8;
+	/// Height of a player's eyes above their `Position`
+	pub const EYE_HEIGHT: f64 = 1.6;
+	/// How far away from their eyes a player can break or place voxels
+	pub const REACH: f64 = 6.0;
+}
+
 impl Component for Player {
 	type Storage = NullStorage<Self>;
 }
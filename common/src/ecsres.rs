@@ -3,5 +3,24 @@ use specs::Entity;
 #[derive(Default, Copy, Clone)]
 pub struct DeltaTime(pub f64);
 
+/// Ticks since the world began, a new day starts at sunrise every `TimeOfDay::DAY_LENGTH` ticks
+#[derive(Default, Copy, Clone)]
+pub struct TimeOfDay(pub u64);
+
+impl TimeOfDay {
+	/// Twenty minutes at the server's twenty ticks a second
+	pub const DAY_LENGTH: u64 = 24000;
+
+	/// Ticks since the start of the current day
+	pub fn time(&self) -> u64 {
+		self.0 % Self::DAY_LENGTH
+	}
+
+	/// Number of days that have passed
+	pub fn day(&self) -> u64 {
+		self.0 / Self::DAY_LENGTH
+	}
+}
+
 #[derive(Default, Clone)]
 pub struct Player(pub Option<Entity>);
@@ -1,6 +1,7 @@
 pub mod components;
 pub mod ecsres;
 pub mod net;
+pub mod physics;
 pub mod state;
 pub mod sys;
 pub mod world;
@@ -19,15 +20,12 @@ pub enum Gamemode {
 ///
 /// Will attempt ../../gamedata if a debug build for sanity in cargo
 pub fn data_root() -> PathBuf {
-	match std::env::var("TAKH_GAMEDATA") {
-		Ok(env) => {
-			if Path::new(&env).exists() {
-				let mut path = PathBuf::new();
-				path.push(&env);
-				return path;
-			}
+	if let Ok(env) = std::env::var("TAKH_GAMEDATA") {
+		if Path::new(&env).exists() {
+			let mut path = PathBuf::new();
+			path.push(&env);
+			return path;
 		}
-		Err(_) => {}
 	}
 	match std::env::current_exe() {
 		Ok(mut exe) => {
@@ -54,15 +52,12 @@ pub fn data_root() -> PathBuf {
 ///
 /// Will attempt ../../config if a debug build for sanity in cargo
 pub fn config_root() -> PathBuf {
-	match std::env::var("TAKH_GAMECONF") {
-		Ok(env) => {
-			if Path::new(&env).exists() {
-				let mut path = PathBuf::new();
-				path.push(&env);
-				return path;
-			}
+	if let Ok(env) = std::env::var("TAKH_GAMECONF") {
+		if Path::new(&env).exists() {
+			let mut path = PathBuf::new();
+			path.push(&env);
+			return path;
 		}
-		Err(_) => {}
 	}
 	match std::env::current_exe() {
 		Ok(mut exe) => {
@@ -1,17 +1,85 @@
-use crate::net::{Face, Position, Rotation, VPosition};
+use crate::net::{Face, VPosition};
 
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ServerBound {
 	Auth(Auth),
 	PlayerAction(PlayerAction),
+	/// Answers the server's `ClientBound::KeepAlive` with the same `id`
+	KeepAlive {
+		id: u64,
+	},
+	/// A line typed into chat, lines starting with `/` are commands
+	Chat {
+		message: String,
+	},
+	/// Asks for ways to finish the last word of a partly typed chat line
+	TabComplete {
+		text: String,
+	},
+	/// How far around the player the client wants terrain in voxels, the server sends no further than its
+	/// own view distance and answers with the distance it uses
+	ViewDistance {
+		distance: u32,
+	},
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Auth {
-	LoginRequest { username: [char; 32] },
+	LoginRequest {
+		/// The client's `net::PROTOCOL_VERSION`, the server refuses clients on another version
+		protocol_version: u32,
+		username: [char; 32],
+	},
+	/// Sent instead of logging in to ask for the server's `ServerStatus`
+	StatusRequest,
+	/// Sent after the status to measure the round trip, the server answers with `Pong` and closes the
+	/// connection
+	Ping { id: u64 },
+}
+
+impl Auth {
+	/// Packs a username into the fixed size field used by `Auth::LoginRequest`, names longer than 32
+	/// characters are truncated
+	pub fn login_request(name: &str) -> Auth {
+		let mut username = ['\0'; 32];
+		for (c, slot) in name.chars().zip(username.iter_mut()) {
+			*slot = c;
+		}
+		Auth::LoginRequest {
+			protocol_version: crate::net::PROTOCOL_VERSION,
+			username,
+		}
+	}
+
+	/// Unpacks a fixed size username, dropping any trailing padding
+	pub fn username(username: &[char; 32]) -> String {
+		username.iter().take_while(|c| **c != '\0').collect()
+	}
+}
+
+/// The player's inputs for a single fixed physics step of `physics::STEP` seconds. The server runs
+/// each frame through the same physics as the client and acknowledges it by `seq`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputFrame {
+	/// Increases by one for every frame a client sends
+	pub seq: u32,
+	pub forward: bool,
+	pub backward: bool,
+	pub left: bool,
+	pub right: bool,
+	pub jump: bool,
+	pub sprint: bool,
+	/// Look direction in degrees
+	pub yaw: f32,
+	pub pitch: f32,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum PlayerAction {
-	/// An absolute position of the player sent to the server
-	PlayerTransform { pos: Position, rot: Rotation },
+	/// One step of movement input from the player
+	PlayerInput(InputFrame),
 	PlayerMining {
 		status: PlayerMiningStatus,
 		voxel: VPosition,
@@ -23,6 +91,7 @@ pub enum PlayerAction {
 	SetHand(u8),
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PlayerMiningStatus {
 	/// Players start to mine
 	Started,
@@ -1,15 +1,44 @@
 pub mod client;
 pub mod packet;
 pub mod server;
+pub mod transport;
 pub mod world;
 
+use serde::{Deserialize, Serialize};
+use vek::Vec3;
+
+/// Changes whenever the messages sent between clients and servers change
+pub const PROTOCOL_VERSION: u32 = 3;
+
+#[derive(Debug)]
 pub enum NetError {
 	ConnectionClosed,
+	/// Nothing arrived from the other end within the read timeout
+	TimedOut,
+	Io(std::io::Error),
 	Deserialize(bincode::Error),
+	/// A packet declared a length larger than `Packet::MAX_SIZE`
+	PacketTooLarge(usize),
+	/// The other end speaks another `PROTOCOL_VERSION`, the one it speaks
+	WrongProtocol(u32),
+}
+
+impl From<std::io::Error> for NetError {
+	fn from(e: std::io::Error) -> NetError {
+		match e.kind() {
+			std::io::ErrorKind::UnexpectedEof
+			| std::io::ErrorKind::ConnectionReset
+			| std::io::ErrorKind::ConnectionAborted
+			| std::io::ErrorKind::BrokenPipe => NetError::ConnectionClosed,
+			_ => NetError::Io(e),
+		}
+	}
 }
 
 pub type EntityID = u32;
 
+/// An absolute world position, split into a whole voxel and a fraction of `1/256` of a voxel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Position {
 	pub x: i32,
 	pub y: i32,
@@ -19,18 +48,92 @@ pub struct Position {
 	pub zf: u8,
 }
 
+impl Position {
+	pub fn from_vec3(pos: Vec3<f64>) -> Position {
+		fn split(v: f64) -> (i32, u8) {
+			let whole = v.floor();
+			(whole as i32, ((v - whole) * 256.0) as u8)
+		}
+		let (x, xf) = split(pos.x);
+		let (y, yf) = split(pos.y);
+		let (z, zf) = split(pos.z);
+		Position { x, y, z, xf, yf, zf }
+	}
+
+	pub fn to_vec3(&self) -> Vec3<f64> {
+		Vec3::new(
+			self.x as f64 + self.xf as f64 / 256.0,
+			self.y as f64 + self.yf as f64 / 256.0,
+			self.z as f64 + self.zf as f64 / 256.0,
+		)
+	}
+}
+
+/// The global position of a single voxel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct VPosition {
 	pub x: i32,
 	pub y: i32,
 	pub z: i32,
 }
 
+impl VPosition {
+	pub fn new(x: i32, y: i32, z: i32) -> VPosition {
+		VPosition { x, y, z }
+	}
+
+	/// The neighbouring voxel in the direction of `face`
+	pub fn offset(&self, face: Face) -> VPosition {
+		let n = face.normal();
+		VPosition::new(self.x + n.x, self.y + n.y, self.z + n.z)
+	}
+
+	/// The centre point of this voxel in world space
+	pub fn centre(&self) -> Vec3<f64> {
+		Vec3::new(self.x as f64 + 0.5, self.y as f64 + 0.5, self.z as f64 + 0.5)
+	}
+}
+
+impl From<Vec3<i32>> for VPosition {
+	fn from(v: Vec3<i32>) -> VPosition {
+		VPosition::new(v.x, v.y, v.z)
+	}
+}
+
+impl From<VPosition> for Vec3<i32> {
+	fn from(v: VPosition) -> Vec3<i32> {
+		Vec3::new(v.x, v.y, v.z)
+	}
+}
+
+/// An absolute rotation in degrees, quantised so that `256` steps make a full turn
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Rotation {
 	pub yaw: u8,
 	pub pitch: u8,
 }
 
+impl Rotation {
+	pub fn from_degrees(yaw: f64, pitch: f64) -> Rotation {
+		fn quantise(deg: f64) -> u8 {
+			(deg.rem_euclid(360.0) / 360.0 * 256.0).round() as u32 as u8
+		}
+		Rotation {
+			yaw: quantise(yaw),
+			pitch: quantise(pitch),
+		}
+	}
+
+	/// Returns `(yaw, pitch)` in degrees, pitch will be in the range `-180..180`
+	pub fn to_degrees(&self) -> (f64, f64) {
+		let yaw = self.yaw as f64 / 256.0 * 360.0;
+		let pitch = self.pitch as i8 as f64 / 256.0 * 360.0;
+		(yaw, pitch)
+	}
+}
+
 #[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Face {
 	Top = 0,
 	Bottom = 1,
@@ -39,3 +142,17 @@ pub enum Face {
 	East = 4,
 	West = 5,
 }
+
+impl Face {
+	/// Unit normal of this face, north is `z-` and east is `x+`
+	pub fn normal(&self) -> Vec3<i32> {
+		match self {
+			Face::Top => Vec3::new(0, 1, 0),
+			Face::Bottom => Vec3::new(0, -1, 0),
+			Face::North => Vec3::new(0, 0, -1),
+			Face::South => Vec3::new(0, 0, 1),
+			Face::East => Vec3::new(1, 0, 0),
+			Face::West => Vec3::new(-1, 0, 0),
+		}
+	}
+}
@@ -1,11 +1,15 @@
 use crate::net::NetError;
 use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 pub struct Packet {
 	data: Vec<u8>,
 }
 
 impl Packet {
+	/// Largest packet we are willing to receive, anything larger is treated as a protocol error
+	pub const MAX_SIZE: usize = 1 << 20;
+
 	pub fn serialize<M: Serialize>(message: &M) -> Self {
 		Packet {
 			data: bincode::serialize(message).unwrap(),
@@ -18,4 +22,27 @@ impl Packet {
 			Err(e) => Err(NetError::Deserialize(e)),
 		}
 	}
+
+	/// Number of bytes this packet takes up on a stream, including its length prefix
+	pub fn wire_len(&self) -> usize {
+		std::mem::size_of::<u32>() + self.data.len()
+	}
+
+	/// Reads a single length prefixed packet from a stream
+	pub async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Packet, NetError> {
+		let len = reader.read_u32_le().await? as usize;
+		if len > Self::MAX_SIZE {
+			return Err(NetError::PacketTooLarge(len));
+		}
+		let mut data = vec![0; len];
+		reader.read_exact(&mut data).await?;
+		Ok(Packet { data })
+	}
+
+	/// Writes this packet to a stream with its length prefixed
+	pub async fn write_to<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<(), NetError> {
+		writer.write_u32_le(self.data.len() as u32).await?;
+		writer.write_all(&self.data).await?;
+		Ok(())
+	}
 }
@@ -1,24 +1,89 @@
 use crate::net::{world::WorldData, EntityID, Position, Rotation, VPosition};
 
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use vek::Vec3;
 
+/// Number of times per second the server ticks, `WorldUpdate::Tick` counts in these
+pub const TICK_RATE: u32 = 20;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ClientBound {
 	Auth(Auth),
 	Data(WorldData),
 	Update(WorldUpdate),
+	/// Checks that the client is still there, it has to answer with `ServerBound::KeepAlive` carrying the
+	/// same `id`
+	KeepAlive {
+		id: u64,
+	},
+	/// A line of chat, `sender` is `None` for messages from the server itself such as command replies
+	Chat {
+		sender: Option<String>,
+		message: String,
+	},
+	/// Answers `ServerBound::TabComplete`, each suggestion is a replacement for the last word of the text
+	TabComplete {
+		suggestions: Vec<String>,
+	},
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Auth {
-	/// Sent to client when successful login
-	LoginSuccess { entity: Uuid },
+	/// Sent to client when successful login, `id` is the id of the player's own entity in world updates
+	LoginSuccess { entity: Uuid, id: EntityID },
 	/// Client should assume connect has been closed when this is sent.
-	Disconnect { message: String },
+	Disconnect { reason: DisconnectReason },
+	/// Answers a status request
+	Status(ServerStatus),
+	/// Answers a status ping with the same `id`
+	Pong { id: u64 },
+}
+
+/// What a server tells clients that ask about it before logging in, such as a server browser
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ServerStatus {
+	/// Message of the day
+	pub motd: String,
+	/// The server's `net::PROTOCOL_VERSION`, clients with another version can't play on it
+	pub protocol_version: u32,
+	pub players: u32,
+	pub max_players: u32,
+	/// Names of the players online, cut short on busy servers
+	pub player_names: Vec<String>,
 }
 
+/// Why the server ended a session
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DisconnectReason {
+	/// Removed by the server, with a message saying why
+	Kicked(String),
+	/// Nothing was heard from the client for too long
+	TimedOut,
+	ShuttingDown,
+	/// The login was refused, with a message saying why
+	LoginRefused(String),
+}
+
+impl std::fmt::Display for DisconnectReason {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			DisconnectReason::Kicked(message) => write!(f, "Kicked: {}", message),
+			DisconnectReason::TimedOut => write!(f, "Timed out"),
+			DisconnectReason::ShuttingDown => write!(f, "Server is shutting down"),
+			DisconnectReason::LoginRefused(message) => write!(f, "Login refused: {}", message),
+		}
+	}
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum WorldUpdate {
 	/// Sent when a block is changed, `pos` is the global position of the voxel that has been changed
 	/// `block_id` is the new voxel state that has been defined in the world voxel palette list
 	BlockChange { pos: VPosition, voxel_id: u32 },
+	/// The server tick that the entity updates after this one happened on, up to the next `Tick`. Lets
+	/// clients place them on a timeline no matter how late they arrive.
+	Tick { tick: u64 },
 	/// Teleport an entity to a certain location, `pos` is an abosolute world coordinate
 	EntityTeleport { entity: EntityID, pos: Position },
 	/// Used when a entity moves less than 8 voxels, `pos` is a relative difference compared to the
@@ -35,6 +100,22 @@ pub enum WorldUpdate {
 		pos: Position,
 		rot: Rotation,
 	},
+	/// The entity has been removed or has left the client's view
+	DespawnEntity { entity: EntityID },
+	/// The authoritative state of the receiving client's own player after the server applied every input
+	/// frame up to and including `seq`
+	PlayerState {
+		seq: u32,
+		pos: (f64, f64, f64),
+		vel: (f64, f64, f64),
+		on_ground: bool,
+	},
+	/// How far around the player the server sends terrain in voxels, the client's own distance capped to the
+	/// server's. Answers `ServerBound::ViewDistance`.
+	ViewDistance { distance: u32 },
+	/// The world's `TimeOfDay`, sent when a player joins, when it is changed and every few seconds to keep
+	/// clients in step
+	TimeOfDay { ticks: u64 },
 	/// Tell's the client to update their copy of a chunk palette with a voxel from the world palette
 	UpdateChunkPalette {
 		chunk_pos: VPosition,
@@ -42,3 +123,102 @@ pub enum WorldUpdate {
 		world_voxel: u32,
 	},
 }
+
+impl WorldUpdate {
+	/// Number of steps per voxel used by the relative `pos` of `WorldUpdate::EntityTransform`
+	pub const TRANSFORM_SCALE: f64 = 4096.0;
+
+	/// Builds the message that moves an entity the receiver thinks is at `from` to `to`. Also returns the
+	/// position the receiver will actually end up at after quantisation, which should be used as `from`
+	/// next time so that no error builds up.
+	pub fn entity_moved(
+		entity: EntityID,
+		from: Vec3<f64>,
+		to: Vec3<f64>,
+		rot: Rotation,
+	) -> (WorldUpdate, Vec3<f64>) {
+		let delta = ((to - from) * Self::TRANSFORM_SCALE).round();
+		let fits = |d: f64| d >= i16::MIN as f64 && d <= i16::MAX as f64;
+		if fits(delta.x) && fits(delta.y) && fits(delta.z) {
+			let pos = (delta.x as i16, delta.y as i16, delta.z as i16);
+			(
+				WorldUpdate::EntityTransform { entity, pos, rot },
+				from + delta / Self::TRANSFORM_SCALE,
+			)
+		} else {
+			let pos = Position::from_vec3(to);
+			(WorldUpdate::EntityTeleport { entity, pos }, pos.to_vec3())
+		}
+	}
+
+	/// The movement in voxels of a relative `pos` from `WorldUpdate::EntityTransform`
+	pub fn transform_delta(pos: (i16, i16, i16)) -> Vec3<f64> {
+		Vec3::new(pos.0 as f64, pos.1 as f64, pos.2 as f64) / Self::TRANSFORM_SCALE
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn moved(from: Vec3<f64>, to: Vec3<f64>) -> (WorldUpdate, Vec3<f64>) {
+		WorldUpdate::entity_moved(7, from, to, Rotation::from_degrees(90.0, 0.0))
+	}
+
+	#[test]
+	fn small_moves_are_quantised_deltas() {
+		let from = Vec3::new(10.0, 64.0, -3.0);
+		let (update, sent) = moved(from, from + Vec3::new(0.1, -0.25, 1.0 / 3.0));
+		match update {
+			WorldUpdate::EntityTransform { entity, pos, .. } => {
+				assert_eq!(entity, 7);
+				assert_eq!(pos, (410, -1024, 1365));
+				// The receiver ends up exactly where we say it does
+				assert_eq!(sent, from + WorldUpdate::transform_delta(pos));
+			}
+			other => panic!("expected a transform, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn quantisation_error_does_not_build_up() {
+		// Each step is too small to send on its own, but they add up
+		let mut real = Vec3::zero();
+		let mut receiver = real;
+		let mut sent = real;
+		for _ in 0..1000 {
+			real.x += 0.0001;
+			let (update, next) = moved(sent, real);
+			if let WorldUpdate::EntityTransform { pos, .. } = update {
+				receiver += WorldUpdate::transform_delta(pos);
+			}
+			sent = next;
+		}
+		assert_eq!(receiver, sent);
+		assert!((receiver.x - real.x).abs() <= 0.5 / WorldUpdate::TRANSFORM_SCALE);
+	}
+
+	#[test]
+	fn moves_of_eight_voxels_or_more_are_teleports() {
+		let from = Vec3::new(0.5, 10.0, 0.5);
+		let limit = i16::MAX as f64 / WorldUpdate::TRANSFORM_SCALE;
+		assert!(matches!(
+			moved(from, from + Vec3::new(limit, 0.0, 0.0)).0,
+			WorldUpdate::EntityTransform { .. }
+		));
+		assert!(matches!(
+			moved(from, from - Vec3::new(0.0, 8.0, 0.0)).0,
+			WorldUpdate::EntityTransform { .. }
+		));
+
+		let to = from + Vec3::new(0.0, 0.0, 8.0);
+		match moved(from, to) {
+			(WorldUpdate::EntityTeleport { entity, pos }, sent) => {
+				assert_eq!(entity, 7);
+				assert_eq!(pos, Position::from_vec3(to));
+				assert_eq!(sent, pos.to_vec3());
+			}
+			(other, _) => panic!("expected a teleport, got {:?}", other),
+		}
+	}
+}
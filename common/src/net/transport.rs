@@ -0,0 +1,142 @@
+use crate::net::{packet::Packet, NetError};
+
+use serde::{de::DeserializeOwned, Serialize};
+use std::net::SocketAddr;
+use tokio::{
+	net::{
+		tcp::{OwnedReadHalf, OwnedWriteHalf},
+		TcpListener, TcpStream,
+	},
+	sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+
+/// A connection between a client and a server, either over TCP or to a server running in the same
+/// process. Local connections pass the same serialized packets as TCP, just without the socket.
+pub enum Stream {
+	Tcp(TcpStream),
+	Local {
+		sender: UnboundedSender<Packet>,
+		receiver: UnboundedReceiver<Packet>,
+	},
+}
+
+impl Stream {
+	pub async fn connect(address: SocketAddr) -> Result<Stream, NetError> {
+		Ok(Stream::Tcp(TcpStream::connect(address).await?))
+	}
+
+	/// Creates two local streams, each one receives what the other sends
+	pub fn local_pair() -> (Stream, Stream) {
+		let (a_sender, b_receiver) = unbounded_channel();
+		let (b_sender, a_receiver) = unbounded_channel();
+		(
+			Stream::Local {
+				sender: a_sender,
+				receiver: a_receiver,
+			},
+			Stream::Local {
+				sender: b_sender,
+				receiver: b_receiver,
+			},
+		)
+	}
+
+	/// Address of the other end, local streams have none
+	pub fn peer_addr(&self) -> Option<SocketAddr> {
+		match self {
+			Stream::Tcp(stream) => stream.peer_addr().ok(),
+			Stream::Local { .. } => None,
+		}
+	}
+
+	/// Splits the stream so that reading and writing can happen on separate tasks
+	pub fn into_split(self) -> (ReadHalf, WriteHalf) {
+		match self {
+			Stream::Tcp(stream) => {
+				let (reader, writer) = stream.into_split();
+				(ReadHalf::Tcp(reader), WriteHalf::Tcp(writer))
+			}
+			Stream::Local { sender, receiver } => (ReadHalf::Local(receiver), WriteHalf::Local(sender)),
+		}
+	}
+}
+
+pub enum ReadHalf {
+	Tcp(OwnedReadHalf),
+	Local(UnboundedReceiver<Packet>),
+}
+
+impl ReadHalf {
+	/// Waits for the next message from the other end
+	pub async fn recv<M: DeserializeOwned>(&mut self) -> Result<M, NetError> {
+		self.recv_packet().await?.deserialize()
+	}
+
+	/// Waits for the next packet from the other end without deserializing it
+	pub async fn recv_packet(&mut self) -> Result<Packet, NetError> {
+		match self {
+			ReadHalf::Tcp(reader) => Packet::read_from(reader).await,
+			ReadHalf::Local(receiver) => receiver.recv().await.ok_or(NetError::ConnectionClosed),
+		}
+	}
+}
+
+pub enum WriteHalf {
+	Tcp(OwnedWriteHalf),
+	Local(UnboundedSender<Packet>),
+}
+
+impl WriteHalf {
+	pub async fn send<M: Serialize>(&mut self, message: &M) -> Result<(), NetError> {
+		self.send_packet(Packet::serialize(message)).await
+	}
+
+	pub async fn send_packet(&mut self, packet: Packet) -> Result<(), NetError> {
+		match self {
+			WriteHalf::Tcp(writer) => packet.write_to(writer).await,
+			WriteHalf::Local(sender) => sender.send(packet).map_err(|_| NetError::ConnectionClosed),
+		}
+	}
+}
+
+/// Accepts new connections for a server
+pub enum Listener {
+	Tcp(TcpListener),
+	Local(UnboundedReceiver<Stream>),
+}
+
+impl Listener {
+	pub async fn bind(address: SocketAddr) -> Result<Listener, NetError> {
+		Ok(Listener::Tcp(TcpListener::bind(address).await?))
+	}
+
+	/// Creates a listener that only accepts the connections made through the returned connector
+	pub fn local() -> (Listener, LocalConnector) {
+		let (sender, receiver) = unbounded_channel();
+		(Listener::Local(receiver), LocalConnector(sender))
+	}
+
+	/// Waits for the next connection. Failing to accept a single TCP connection gives `NetError::Io`, while
+	/// `NetError::ConnectionClosed` means no more connections will ever arrive.
+	pub async fn accept(&mut self) -> Result<Stream, NetError> {
+		match self {
+			Listener::Tcp(listener) => match listener.accept().await {
+				Ok((stream, _)) => Ok(Stream::Tcp(stream)),
+				Err(e) => Err(NetError::Io(e)),
+			},
+			Listener::Local(receiver) => receiver.recv().await.ok_or(NetError::ConnectionClosed),
+		}
+	}
+}
+
+/// Opens connections to a server's local listener
+#[derive(Clone)]
+pub struct LocalConnector(UnboundedSender<Stream>);
+
+impl LocalConnector {
+	pub fn connect(&self) -> Result<Stream, NetError> {
+		let (client, server) = Stream::local_pair();
+		self.0.send(server).map_err(|_| NetError::ConnectionClosed)?;
+		Ok(client)
+	}
+}
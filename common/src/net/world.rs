@@ -1,7 +1,20 @@
 use crate::net::VPosition;
 
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum WorldData {
 	WorldPalette {},
 	ChunkPalette {},
-	ChunkData { pos: VPosition },
+	/// The full contents of a chunk, `pos` is the chunk coordinate, `palette` maps each chunk palette
+	/// index to a world voxel id and `voxels` holds a chunk palette index for every voxel
+	ChunkData {
+		pos: VPosition,
+		palette: Vec<u32>,
+		voxels: Vec<u8>,
+	},
+	/// The client should forget about the chunk at `pos`, it has left the view distance
+	UnloadChunk {
+		pos: VPosition,
+	},
 }
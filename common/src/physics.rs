@@ -0,0 +1,164 @@
+use crate::components::Player;
+use crate::net::{client::InputFrame, VPosition};
+use crate::world::terrain::Terrain;
+
+use vek::Vec3;
+
+/// Length of a single physics step in seconds, every input frame covers exactly one step
+pub const STEP: f64 = 1.0 / 60.0;
+/// Walking speed in voxels per second
+pub const WALK_SPEED: f64 = 4.3;
+/// Sprinting speed in voxels per second
+pub const SPRINT_SPEED: f64 = 5.6;
+/// Upwards speed given by a jump, enough to clear a single voxel
+pub const JUMP_SPEED: f64 = 9.0;
+/// Downwards acceleration in voxels per second squared
+pub const GRAVITY: f64 = 32.0;
+/// Fastest an entity can fall, kept below a voxel per step so nothing falls through the floor
+pub const TERMINAL_SPEED: f64 = 50.0;
+/// Gap left between a body and the voxel it was stopped by
+const CONTACT_GAP: f64 = 1e-4;
+
+/// The movement state of a player that the physics step works on
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Body {
+	pub pos: Vec3<f64>,
+	pub vel: Vec3<f64>,
+	pub on_ground: bool,
+}
+
+/// Advances a player's body by one `STEP` using the given inputs. Both the client and the server run this
+/// so that the client can predict where the server will put it. Nothing happens while the chunk the body
+/// stands in isn't loaded, and voxels in unloaded chunks are treated as solid.
+pub fn step(terrain: &Terrain, body: &mut Body, input: &InputFrame) {
+	if terrain
+		.get_voxel(VPosition::from(body.pos.map(|v| v.floor() as i32)))
+		.is_none()
+	{
+		return;
+	}
+
+	let yaw = (input.yaw as f64).to_radians();
+	let forward = Vec3::new(yaw.cos(), 0.0, yaw.sin());
+	let right = Vec3::new(-yaw.sin(), 0.0, yaw.cos());
+	let mut wish = Vec3::<f64>::zero();
+	if input.forward {
+		wish += forward;
+	}
+	if input.backward {
+		wish -= forward;
+	}
+	if input.right {
+		wish += right;
+	}
+	if input.left {
+		wish -= right;
+	}
+	if wish.magnitude_squared() > 0.0 {
+		wish.normalize();
+	}
+	let speed = if input.sprint { SPRINT_SPEED } else { WALK_SPEED };
+
+	body.vel.x = wish.x * speed;
+	body.vel.z = wish.z * speed;
+	if input.jump && body.on_ground {
+		body.vel.y = JUMP_SPEED;
+	}
+	body.vel.y = (body.vel.y - GRAVITY * STEP).max(-TERMINAL_SPEED);
+
+	// A body that is already stuck inside something, say from a voxel placed on it, is let out freely
+	if overlaps_solid(terrain, body.pos) {
+		body.pos += body.vel * STEP;
+		body.on_ground = false;
+		return;
+	}
+
+	body.on_ground = false;
+	for axis in [1, 0, 2].iter().copied() {
+		let delta = body.vel[axis] * STEP;
+		if delta == 0.0 {
+			continue;
+		}
+		let mut moved = body.pos;
+		moved[axis] += delta;
+		match blocking_voxel(terrain, moved, axis, delta) {
+			Some(voxel) => {
+				let (low, high) = extent(axis);
+				// Move up to the voxel, but never backwards when already closer to it than the gap
+				body.pos[axis] = if delta > 0.0 {
+					(voxel as f64 - high - CONTACT_GAP).max(body.pos[axis])
+				} else {
+					(voxel as f64 + 1.0 - low + CONTACT_GAP).min(body.pos[axis])
+				};
+				if axis == 1 && delta < 0.0 {
+					body.on_ground = true;
+				}
+				body.vel[axis] = 0.0;
+			}
+			None => body.pos = moved,
+		}
+	}
+}
+
+/// How far a player's bounding box reaches below and above its position along an axis
+fn extent(axis: usize) -> (f64, f64) {
+	let half = Player::WIDTH / 2.0;
+	if axis == 1 {
+		(0.0, Player::HEIGHT)
+	} else {
+		(-half, half)
+	}
+}
+
+/// The range of voxel coordinates a player's bounding box at `pos` covers along an axis
+fn voxel_range(pos: Vec3<f64>, axis: usize) -> (i32, i32) {
+	let (low, high) = extent(axis);
+	(
+		(pos[axis] + low).floor() as i32,
+		(pos[axis] + high - f64::EPSILON).floor() as i32,
+	)
+}
+
+fn is_solid(terrain: &Terrain, pos: VPosition) -> bool {
+	terrain.get_voxel(pos).is_none_or(|v| v.collide)
+}
+
+/// Calls `f` with every voxel a player's bounding box at `pos` is inside of, stopping when `f` returns `true`
+fn any_voxel(pos: Vec3<f64>, mut f: impl FnMut(VPosition) -> bool) -> bool {
+	let (x0, x1) = voxel_range(pos, 0);
+	let (y0, y1) = voxel_range(pos, 1);
+	let (z0, z1) = voxel_range(pos, 2);
+	for x in x0..=x1 {
+		for y in y0..=y1 {
+			for z in z0..=z1 {
+				if f(VPosition::new(x, y, z)) {
+					return true;
+				}
+			}
+		}
+	}
+	false
+}
+
+/// Whether a player's bounding box at `pos` is inside of a solid voxel
+pub fn overlaps_solid(terrain: &Terrain, pos: Vec3<f64>) -> bool {
+	any_voxel(pos, |v| is_solid(terrain, v))
+}
+
+/// The coordinate along `axis` of the nearest solid voxel a player's bounding box at `pos` runs into when
+/// moving in the direction of `delta`
+fn blocking_voxel(terrain: &Terrain, pos: Vec3<f64>, axis: usize, delta: f64) -> Option<i32> {
+	let mut nearest: Option<i32> = None;
+	any_voxel(pos, |v| {
+		if is_solid(terrain, v) {
+			let coord = Vec3::<i32>::from(v)[axis];
+			nearest = Some(match nearest {
+				Some(n) if delta > 0.0 => n.min(coord),
+				Some(n) => n.max(coord),
+				None => coord,
+			});
+		}
+		false
+	});
+	nearest
+}
@@ -25,12 +25,17 @@ impl State {
 		let mut world = specs::World::new();
 
 		world.register::<components::Gravity>();
+		world.register::<components::NetworkId>();
+		world.register::<components::OnGround>();
 		world.register::<components::Orientation>();
 		world.register::<components::Player>();
 		world.register::<components::Position>();
+		world.register::<components::Velocity>();
 		world.register::<components::Last<components::Position>>();
+		world.register::<components::Last<components::Orientation>>();
 
 		world.insert(DeltaTime(0.0));
+		world.insert(TimeOfDay(0));
 		#[cfg(feature = "client")]
 		world.insert(Player(None));
 
@@ -21,6 +21,12 @@ pub struct Palette {
 	types: Vec<Voxel>,
 }
 
+impl Default for Palette {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Palette {
 	pub const MAX_SIZE: usize = std::mem::size_of::<u8>() * 8;
 
@@ -35,7 +41,7 @@ impl Palette {
 		self.types
 			.iter()
 			.enumerate()
-			.find(|(_, v)| if *v == voxel { true } else { false })
+			.find(|(_, v)| *v == voxel)
 			.map(|(i, _)| PaletteId::new(i as u8))
 	}
 
@@ -48,6 +54,28 @@ impl Palette {
 			}
 		}
 	}
+
+	/// Returns the id of `voxel` in this palette, adding it if there is still space
+	pub fn get_or_add(&mut self, voxel: &Voxel) -> Option<PaletteId> {
+		if let Some(id) = self.palette_id(voxel) {
+			return Some(id);
+		}
+		self.add_voxel(voxel.clone());
+		self.palette_id(voxel)
+	}
+
+	/// Puts `voxel` at a specific place in the palette, used when rebuilding a palette received from elsewhere
+	pub fn set(&mut self, id: PaletteId, voxel: Voxel) {
+		let i = id.value() as usize;
+		if i < Self::MAX_SIZE {
+			self.types[i] = voxel;
+			self.last_free = self.last_free.max(i + 1);
+		}
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Voxel> {
+		self.types.iter()
+	}
 }
 
 impl std::ops::Index<PaletteId> for Palette {
@@ -78,13 +106,30 @@ impl Chunk {
 		}
 	}
 
+	/// Rebuilds a chunk from its raw palette ids, returns `None` if `voxels` is the wrong length
+	pub fn from_raw(coord: (i32, i32, i32), palette: Palette, voxels: Vec<PaletteId>) -> Option<Chunk> {
+		if voxels.len() != Self::WIDTH * Self::HEIGHT * Self::DEPTH {
+			return None;
+		}
+		Some(Chunk {
+			coord,
+			palette,
+			voxels,
+		})
+	}
+
+	/// The raw palette ids of every voxel, ordered by `y`, then `z`, then `x`
+	pub fn raw_voxels(&self) -> &[PaletteId] {
+		&self.voxels
+	}
+
 	pub fn palette_id(&self, voxel: &Voxel) -> Option<PaletteId> {
 		self.palette.palette_id(voxel)
 	}
 
 	#[inline(always)]
 	pub fn set_voxel(&mut self, x: usize, y: usize, z: usize, id: PaletteId) {
-		if x >= Self::WIDTH || y >= Self::HEIGHT || x >= Self::DEPTH {
+		if x >= Self::WIDTH || y >= Self::HEIGHT || z >= Self::DEPTH {
 			return;
 		}
 		let idx = (y * Self::WIDTH * Self::DEPTH) + (z * Self::WIDTH) + x;
@@ -93,7 +138,7 @@ impl Chunk {
 
 	#[inline(always)]
 	pub fn get_voxel(&self, x: usize, y: usize, z: usize) -> &Voxel {
-		if x >= Self::WIDTH || y >= Self::HEIGHT || x >= Self::DEPTH {
+		if x >= Self::WIDTH || y >= Self::HEIGHT || z >= Self::DEPTH {
 			return &self.palette[PaletteId::new(0)];
 		}
 		let idx = (y * Self::WIDTH * Self::DEPTH) + (z * Self::WIDTH) + x;
@@ -109,3 +154,42 @@ impl Chunk {
 		self.voxels[idx] == PaletteId::new(0)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A chunk of air whose palette has one solid voxel at id 1
+	fn chunk() -> Chunk {
+		let mut palette = Palette::new();
+		palette.add_voxel(Voxel {
+			is_air: false,
+			collide: true,
+			..AIR_VOXEL
+		});
+		Chunk::new((0, 0, 0), palette)
+	}
+
+	#[test]
+	fn indexes_the_last_z_slice() {
+		let mut chunk = chunk();
+		let z = Chunk::DEPTH - 1;
+		chunk.set_voxel(Chunk::WIDTH - 1, 0, z, PaletteId::new(1));
+		assert!(!chunk.is_air(Chunk::WIDTH - 1, 0, z));
+		assert!(!chunk.get_voxel(Chunk::WIDTH - 1, 0, z).is_air);
+		// Nothing else was touched
+		assert!(chunk.is_air(0, 0, z));
+		assert!(chunk.is_air(Chunk::WIDTH - 1, 1, 0));
+	}
+
+	#[test]
+	fn ignores_z_past_the_end() {
+		let mut chunk = chunk();
+		// Unchecked, this lands on the first row of the layer above
+		chunk.set_voxel(0, 0, Chunk::DEPTH, PaletteId::new(1));
+		assert!(chunk.is_air(0, 1, 0));
+
+		chunk.set_voxel(0, 1, 0, PaletteId::new(1));
+		assert!(chunk.get_voxel(0, 0, Chunk::DEPTH).is_air);
+	}
+}
@@ -1,3 +1,5 @@
 pub mod chunk;
+pub mod terrain;
 pub mod voxel;
+#[allow(clippy::module_inception)]
 pub mod world;
@@ -0,0 +1,223 @@
+use crate::net::{world::WorldData, VPosition};
+use crate::world::{
+	chunk::{Chunk, Palette, PaletteId},
+	voxel::{Voxel, AIR_VOXEL},
+	world::generate_chunk,
+};
+
+use std::collections::{HashMap, HashSet};
+
+/// The list of every voxel type in the world, a voxel's index in this list is the world voxel id that is
+/// used when talking over the network. Id `0` is always air.
+#[derive(Clone)]
+pub struct WorldPalette {
+	voxels: Vec<Voxel>,
+}
+
+impl Default for WorldPalette {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl WorldPalette {
+	pub fn new() -> WorldPalette {
+		WorldPalette {
+			voxels: vec![AIR_VOXEL],
+		}
+	}
+
+	/// Adds a voxel type to the end of the palette and returns its id
+	pub fn add_voxel(&mut self, voxel: Voxel) -> u32 {
+		self.voxels.push(voxel);
+		self.voxels.len() as u32 - 1
+	}
+
+	pub fn get(&self, id: u32) -> Option<&Voxel> {
+		self.voxels.get(id as usize)
+	}
+
+	pub fn id_of(&self, voxel: &Voxel) -> Option<u32> {
+		self.voxels.iter().position(|v| v == voxel).map(|i| i as u32)
+	}
+
+	pub fn len(&self) -> usize {
+		self.voxels.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.voxels.is_empty()
+	}
+}
+
+/// All of the chunks that are currently loaded in a world
+pub struct Terrain {
+	pub palette: WorldPalette,
+	/// Seed that chunks are generated from
+	pub seed: u32,
+	chunks: HashMap<(i32, i32, i32), Chunk>,
+	/// Chunks that were changed since they were last saved. Chunks that were only generated aren't, as they
+	/// come out the same from the seed every time.
+	dirty: HashSet<(i32, i32, i32)>,
+}
+
+impl Terrain {
+	pub fn new(palette: WorldPalette) -> Terrain {
+		Self::with_seed(palette, 0)
+	}
+
+	pub fn with_seed(palette: WorldPalette, seed: u32) -> Terrain {
+		Terrain {
+			palette,
+			seed,
+			chunks: HashMap::new(),
+			dirty: HashSet::new(),
+		}
+	}
+
+	/// Splits a global voxel position into the coordinate of the chunk that holds it and the position
+	/// of the voxel within that chunk
+	pub fn split_pos(pos: VPosition) -> ((i32, i32, i32), (usize, usize, usize)) {
+		let (w, h, d) = (Chunk::WIDTH as i32, Chunk::HEIGHT as i32, Chunk::DEPTH as i32);
+		(
+			(pos.x.div_euclid(w), pos.y.div_euclid(h), pos.z.div_euclid(d)),
+			(
+				pos.x.rem_euclid(w) as usize,
+				pos.y.rem_euclid(h) as usize,
+				pos.z.rem_euclid(d) as usize,
+			),
+		)
+	}
+
+	/// How many chunks out from a centre chunk are within `distance` voxels, horizontally and vertically
+	pub fn chunk_radius(distance: u32) -> (i32, i32) {
+		(
+			((distance as f32) / Chunk::WIDTH as f32).ceil() as i32,
+			((distance as f32) / Chunk::HEIGHT as f32).ceil() as i32,
+		)
+	}
+
+	pub fn chunk(&self, coord: (i32, i32, i32)) -> Option<&Chunk> {
+		self.chunks.get(&coord)
+	}
+
+	/// Coordinates of every loaded chunk
+	pub fn chunk_coords(&self) -> impl Iterator<Item = (i32, i32, i32)> + '_ {
+		self.chunks.keys().copied()
+	}
+
+	pub fn insert_chunk(&mut self, chunk: Chunk) {
+		self.chunks.insert(chunk.coord, chunk);
+	}
+
+	/// Unloads a chunk, any changes to it that haven't been saved are lost
+	pub fn remove_chunk(&mut self, coord: (i32, i32, i32)) -> Option<Chunk> {
+		self.dirty.remove(&coord);
+		self.chunks.remove(&coord)
+	}
+
+	/// Takes the coordinates of the chunks that need saving, they count as saved from now on
+	pub fn take_dirty(&mut self) -> HashSet<(i32, i32, i32)> {
+		std::mem::take(&mut self.dirty)
+	}
+
+	/// Whether a chunk has changes that haven't been saved
+	pub fn is_dirty(&self, coord: (i32, i32, i32)) -> bool {
+		self.dirty.contains(&coord)
+	}
+
+	/// Marks a chunk as needing to be saved, such as after a save of it failed
+	pub fn mark_dirty(&mut self, coord: (i32, i32, i32)) {
+		if self.chunks.contains_key(&coord) {
+			self.dirty.insert(coord);
+		}
+	}
+
+	/// Loads a chunk sent over the network, `palette` maps the chunk's palette ids to world voxel ids.
+	/// Returns `false` if the data doesn't describe a valid chunk.
+	pub fn insert_chunk_data(&mut self, pos: VPosition, palette: &[u32], voxels: Vec<u8>) -> bool {
+		if palette.len() > Palette::MAX_SIZE {
+			return false;
+		}
+		let mut chunk_palette = Palette::new();
+		for (i, id) in palette.iter().enumerate() {
+			match self.palette.get(*id) {
+				Some(voxel) => chunk_palette.set(PaletteId::new(i as u8), voxel.clone()),
+				None => return false,
+			}
+		}
+		if voxels.iter().any(|v| *v as usize >= palette.len().max(1)) {
+			return false;
+		}
+		let voxels = voxels.into_iter().map(PaletteId::new).collect();
+		match Chunk::from_raw((pos.x, pos.y, pos.z), chunk_palette, voxels) {
+			Some(chunk) => {
+				self.insert_chunk(chunk);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Returns the chunk at `coord`, generating it first if it isn't loaded
+	pub fn get_or_generate(&mut self, coord: (i32, i32, i32)) -> &Chunk {
+		let palette = &self.palette;
+		let seed = self.seed;
+		self.chunks.entry(coord).or_insert_with(|| {
+			let mut chunk_palette = Palette::new();
+			if let Some(solid) = palette.get(1) {
+				chunk_palette.add_voxel(solid.clone());
+			}
+			generate_chunk(coord.0, coord.1, coord.2, seed, chunk_palette)
+		})
+	}
+
+	/// Returns the voxel at a global position, or `None` if its chunk isn't loaded
+	pub fn get_voxel(&self, pos: VPosition) -> Option<&Voxel> {
+		let (coord, (x, y, z)) = Self::split_pos(pos);
+		self.chunks.get(&coord).map(|c| c.get_voxel(x, y, z))
+	}
+
+	/// Returns the world voxel id at a global position, or `None` if its chunk isn't loaded
+	pub fn voxel_id(&self, pos: VPosition) -> Option<u32> {
+		self.get_voxel(pos).and_then(|v| self.palette.id_of(v))
+	}
+
+	/// Sets the voxel at a global position to a world voxel id. Returns `false` if the chunk isn't loaded,
+	/// the id is unknown or the chunk palette has no room left for another voxel type.
+	pub fn set_voxel(&mut self, pos: VPosition, voxel_id: u32) -> bool {
+		let (coord, (x, y, z)) = Self::split_pos(pos);
+		let voxel = match self.palette.get(voxel_id) {
+			Some(v) => v,
+			None => return false,
+		};
+		let chunk = match self.chunks.get_mut(&coord) {
+			Some(c) => c,
+			None => return false,
+		};
+		match chunk.palette.get_or_add(voxel) {
+			Some(id) => {
+				chunk.set_voxel(x, y, z, id);
+				self.dirty.insert(coord);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// Packs a loaded chunk up to be sent over the network
+	pub fn chunk_data(&self, coord: (i32, i32, i32)) -> Option<WorldData> {
+		let chunk = self.chunks.get(&coord)?;
+		let palette = chunk
+			.palette
+			.iter()
+			.map(|v| self.palette.id_of(v).unwrap_or(0))
+			.collect();
+		let voxels = chunk.raw_voxels().iter().map(|id| id.value()).collect();
+		Some(WorldData::ChunkData {
+			pos: VPosition::new(coord.0, coord.1, coord.2),
+			palette,
+			voxels,
+		})
+	}
+}
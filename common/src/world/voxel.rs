@@ -1,8 +1,13 @@
+use serde::{Deserialize, Serialize};
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct Voxel {
 	pub is_air: bool,
 	pub collide: bool,
+	/// Time in milliseconds it takes to break this voxel, `0` breaks instantly
+	pub hardness: u32,
 	pub mesh: VoxelMesh,
+	pub transparency: Transparency,
 	#[cfg(feature = "client")]
 	pub texture: VoxelTexture,
 }
@@ -10,7 +15,9 @@ pub struct Voxel {
 pub const AIR_VOXEL: Voxel = Voxel {
 	is_air: true,
 	collide: false,
+	hardness: 0,
 	mesh: VoxelMesh::Nil,
+	transparency: Transparency::Translucent,
 	#[cfg(feature = "client")]
 	texture: VoxelTexture::None,
 };
@@ -56,26 +63,51 @@ pub enum VoxelTexture {
 }
 
 impl Voxel {
-	#[cfg(not(feature = "client"))]
 	pub fn new_full() -> Voxel {
 		Voxel {
 			is_air: false,
 			collide: true,
+			hardness: 750,
 			mesh: VoxelMesh::Full,
+			transparency: Transparency::Opaque,
+			#[cfg(feature = "client")]
+			texture: VoxelTexture::None,
 		}
 	}
 
+	pub fn with_hardness(mut self, hardness: u32) -> Voxel {
+		self.hardness = hardness;
+		self
+	}
+
+	pub fn with_transparency(mut self, transparency: Transparency) -> Voxel {
+		self.transparency = transparency;
+		self
+	}
+
+	/// How long this voxel has to be mined for before it breaks
+	pub fn break_time(&self) -> std::time::Duration {
+		std::time::Duration::from_millis(self.hardness as u64)
+	}
+
 	#[cfg(feature = "client")]
-	pub fn new_full(texture: VoxelTexture) -> Voxel {
-		Voxel {
-			is_air: false,
-			collide: true,
-			mesh: VoxelMesh::Full,
-			texture,
-		}
+	pub fn with_texture(mut self, texture: VoxelTexture) -> Voxel {
+		self.texture = texture;
+		self
 	}
 }
 
+/// How much can be seen through a voxel
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Transparency {
+	/// Nothing, like stone
+	Opaque,
+	/// Only through the fully transparent parts of its texture, like leaves
+	Cutout,
+	/// Through all of it, tinted by its texture, like glass or water
+	Translucent,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum VoxelMesh {
 	/// A full filled standard voxel
@@ -1,10 +1,10 @@
 use crate::world::chunk::{Chunk, Palette, PaletteId};
 
-use noise::{NoiseFn, OpenSimplex};
+use noise::{NoiseFn, OpenSimplex, Seedable};
 
-pub fn generate_chunk(cx: i32, cy: i32, cz: i32, palette: Palette) -> Chunk {
+pub fn generate_chunk(cx: i32, cy: i32, cz: i32, seed: u32, palette: Palette) -> Chunk {
 	let mut chunk = Chunk::new((cx, cy, cz), palette);
-	let simplex = OpenSimplex::new();
+	let simplex = OpenSimplex::new().set_seed(seed);
 	for z in 0..Chunk::DEPTH {
 		// let ax = x as i32 + (cx * Chunk::WIDTH as i32);
 		// let az = z as i32 + (cz * Chunk::DEPTH as i32);
@@ -7,19 +7,16 @@ in vec3 frag_pos;
 out vec4 o_colour;
 
 uniform sampler2DArray u_tex;
+// Texels less opaque than this are thrown away, the translucent pass keeps everything
+uniform float u_alpha_cutoff;
 
-const vec3 light_colour = vec3(1.0, 1.0, 1.0);
-const vec3 light_pos = vec3(1.2, 1.0, 2.0);
+#include "include/lighting.glsl"
+#include "include/fog.glsl"
 
 void main()
 {
-	float ambient_strength = 0.2;
-	vec3 ambient = ambient_strength * light_colour;
-	vec3 albedo = texture(u_tex, tex_coord).rgb;
-	
-	vec3 light_dir = normalize(-light_pos/*  - frag_pos */);
-	vec3 diffuse = max(dot(f_norm, light_dir), 0.0) * light_colour;
-	
-	vec3 result = (ambient + diffuse) * albedo;
-	o_colour = vec4(result, 1.0);
+	vec4 texel = texture(u_tex, tex_coord);
+	if (texel.a < u_alpha_cutoff)
+		discard;
+	o_colour = vec4(apply_fog(lighting(f_norm) * texel.rgb, frag_pos), texel.a);
 }
@@ -0,0 +1,18 @@
+// Fades the world into the sky before it reaches the edge of what is loaded
+
+#include "sky.glsl"
+
+uniform vec3 u_eye;
+// Distance the world is loaded to, everything is hidden by the time it is this far away
+uniform float u_fog_distance;
+
+// Fog only starts this far along the way to the edge
+const float fog_start = 0.6;
+
+vec3 apply_fog(vec3 colour, vec3 pos)
+{
+	// Terrain is loaded further above and below than around, so only distance along the ground counts
+	float distance = length(pos.xz - u_eye.xz);
+	float fog = smoothstep(fog_start * u_fog_distance, u_fog_distance, distance);
+	return mix(colour, sky_colour(pos - u_eye), fog);
+}
@@ -0,0 +1,14 @@
+// Light falling on surfaces, shared by everything drawn in the world
+
+const vec3 light_colour = vec3(1.0, 1.0, 1.0);
+const vec3 light_pos = vec3(1.2, 1.0, 2.0);
+const float ambient_strength = 0.2;
+
+// How much light reaches a surface facing along `normal`
+vec3 lighting(vec3 normal)
+{
+	vec3 ambient = ambient_strength * light_colour;
+	vec3 light_dir = normalize(-light_pos);
+	vec3 diffuse = max(dot(normal, light_dir), 0.0) * light_colour;
+	return ambient + diffuse;
+}
@@ -0,0 +1,10 @@
+// What every post-processing pass is given
+
+in vec2 tex_coord;
+
+out vec4 o_colour;
+
+// The output of the pass before, or the world for the first
+uniform sampler2D screen_tex;
+// Depth of the world, from 0 at the near plane to 1 at the far plane
+uniform sampler2D depth_tex;
@@ -0,0 +1,11 @@
+// Colour of the sky, shared by the sky itself and the fog that fades the world into it
+
+const vec3 sky_horizon = vec3(0.66, 0.79, 0.88);
+const vec3 sky_zenith = vec3(0.26, 0.47, 0.78);
+
+// Colour of the sky seen looking along `dir`
+vec3 sky_colour(vec3 dir)
+{
+	float height = clamp(normalize(dir).y, 0.0, 1.0);
+	return mix(sky_horizon, sky_zenith, sqrt(height));
+}
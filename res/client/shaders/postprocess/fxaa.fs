@@ -0,0 +1,47 @@
+#version 440 core
+
+#include "../include/postprocess.glsl"
+
+// Fast approximate anti-aliasing, after the console version of FXAA 3.11 by Timothy Lottes
+
+const float span_max = 8.0;
+const float reduce_mul = 1.0 / 8.0;
+const float reduce_min = 1.0 / 128.0;
+
+float luma(vec3 colour)
+{
+	return dot(colour, vec3(0.299, 0.587, 0.114));
+}
+
+vec3 sample_at(vec2 offset)
+{
+	return texture(screen_tex, tex_coord + offset).rgb;
+}
+
+void main()
+{
+	vec2 texel = 1.0 / vec2(textureSize(screen_tex, 0));
+	vec3 colour = sample_at(vec2(0.0));
+	float luma_m = luma(colour);
+	float luma_nw = luma(sample_at(vec2(-1.0, -1.0) * texel));
+	float luma_ne = luma(sample_at(vec2(1.0, -1.0) * texel));
+	float luma_sw = luma(sample_at(vec2(-1.0, 1.0) * texel));
+	float luma_se = luma(sample_at(vec2(1.0, 1.0) * texel));
+	float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+	float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+	// Blur along the edge, not across it
+	vec2 dir = vec2(
+		(luma_sw + luma_se) - (luma_nw + luma_ne),
+		(luma_nw + luma_sw) - (luma_ne + luma_se)
+	);
+	float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+	float scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+	dir = clamp(dir * scale, vec2(-span_max), vec2(span_max)) * texel;
+
+	vec3 near = 0.5 * (sample_at(dir * (1.0 / 3.0 - 0.5)) + sample_at(dir * (2.0 / 3.0 - 0.5)));
+	vec3 far = 0.5 * near + 0.25 * (sample_at(dir * -0.5) + sample_at(dir * 0.5));
+	// Reaching too far picks up colours from past the edge
+	float luma_far = luma(far);
+	o_colour = vec4(luma_far < luma_min || luma_far > luma_max ? near : far, 1.0);
+}
@@ -0,0 +1,14 @@
+#version 440 core
+
+#include "../include/postprocess.glsl"
+
+// Given by the game from the settings
+#ifndef GAMMA
+#define GAMMA 2.2
+#endif
+
+void main()
+{
+	vec3 colour = texture(screen_tex, tex_coord).rgb;
+	o_colour = vec4(pow(max(colour, vec3(0.0)), vec3(1.0 / GAMMA)), 1.0);
+}
@@ -0,0 +1,19 @@
+#version 440 core
+
+#include "../include/postprocess.glsl"
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x)
+{
+	const float a = 2.51;
+	const float b = 0.03;
+	const float c = 2.43;
+	const float d = 0.59;
+	const float e = 0.14;
+	return clamp((x * (a * x + b)) / (x * (c * x + d) + e), 0.0, 1.0);
+}
+
+void main()
+{
+	o_colour = vec4(aces(texture(screen_tex, tex_coord).rgb), 1.0);
+}
@@ -0,0 +1,14 @@
+#version 440 core
+
+#include "../include/postprocess.glsl"
+
+// How dark the very corners get
+const float strength = 0.4;
+
+void main()
+{
+	vec3 colour = texture(screen_tex, tex_coord).rgb;
+	// Keeps the middle of the screen untouched, fading out towards the corners
+	float fade = smoothstep(0.3, 0.8, length(tex_coord - 0.5));
+	o_colour = vec4(colour * (1.0 - strength * fade), 1.0);
+}
@@ -0,0 +1,18 @@
+#version 440 core
+
+#include "include/sky.glsl"
+
+in vec2 ndc;
+
+out vec4 o_colour;
+
+// Takes points on the screen back into the world, as seen from the origin
+uniform mat4 u_inverse;
+
+void main()
+{
+	// Two points along the ray through this pixel give its direction
+	vec4 near = u_inverse * vec4(ndc, 0.0, 1.0);
+	vec4 far = u_inverse * vec4(ndc, 1.0, 1.0);
+	o_colour = vec4(sky_colour(far.xyz / far.w - near.xyz / near.w), 1.0);
+}
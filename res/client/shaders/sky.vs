@@ -0,0 +1,10 @@
+#version 440 core
+
+out vec2 ndc;
+
+void main()
+{
+	// One triangle covering the whole screen, no vertex buffer needed
+	ndc = vec2(gl_VertexID == 1 ? 3.0 : -1.0, gl_VertexID == 2 ? 3.0 : -1.0);
+	gl_Position = vec4(ndc, 0.0, 1.0);
+}
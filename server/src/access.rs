@@ -0,0 +1,256 @@
+use crate::{command::Permission, storage::write_atomic};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+	fs, io,
+	net::IpAddr,
+	path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+const WHITELIST_FILE: &str = "Server_whitelist.ron";
+const BANS_FILE: &str = "Server_bans.ron";
+const OPS_FILE: &str = "Server_ops.ron";
+
+/// The UUID a player gets from their username
+pub fn player_uuid(username: &str) -> Uuid {
+	Uuid::new_v5(&Uuid::NAMESPACE_OID, username.as_bytes())
+}
+
+/// A player named in one of the lists, they match by UUID or by name ignoring case
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlayerEntry {
+	pub name: String,
+	pub uuid: Uuid,
+}
+
+impl PlayerEntry {
+	pub fn new(name: &str) -> PlayerEntry {
+		PlayerEntry {
+			name: name.to_owned(),
+			uuid: player_uuid(name),
+		}
+	}
+
+	pub fn matches(&self, name: &str, uuid: Uuid) -> bool {
+		self.uuid == uuid || self.name.eq_ignore_ascii_case(name)
+	}
+}
+
+/// Players allowed to join while `enabled`, operators can always join
+#[derive(Default, Serialize, Deserialize)]
+pub struct Whitelist {
+	pub enabled: bool,
+	pub players: Vec<PlayerEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerBan {
+	pub player: PlayerEntry,
+	pub reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IpBan {
+	pub ip: IpAddr,
+	pub reason: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct BanList {
+	pub players: Vec<PlayerBan>,
+	pub ips: Vec<IpBan>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Operator {
+	pub player: PlayerEntry,
+	pub permission: Permission,
+}
+
+/// Who may join the server and who is trusted with more than a player, each list is kept in a RON file next
+/// to the server settings
+pub struct AccessLists {
+	dir: PathBuf,
+	pub whitelist: Whitelist,
+	pub bans: BanList,
+	pub ops: Vec<Operator>,
+}
+
+impl AccessLists {
+	/// Loads the lists from `dir`, lists that don't exist yet are created empty
+	pub fn load(dir: &Path) -> AccessLists {
+		let dir = dir.to_owned();
+		AccessLists {
+			whitelist: load_file(&dir.join(WHITELIST_FILE)).unwrap_or_default(),
+			bans: load_file(&dir.join(BANS_FILE)).unwrap_or_default(),
+			ops: load_file(&dir.join(OPS_FILE)).unwrap_or_default(),
+			dir,
+		}
+	}
+
+	/// Reads the lists again after they were edited by hand. A list that fails to parse is left as it was,
+	/// and the files that failed are returned.
+	pub fn reload(&mut self) -> Vec<&'static str> {
+		let mut failed = Vec::new();
+		match load_file(&self.dir.join(WHITELIST_FILE)) {
+			Some(whitelist) => self.whitelist = whitelist,
+			None => failed.push(WHITELIST_FILE),
+		}
+		match load_file(&self.dir.join(BANS_FILE)) {
+			Some(bans) => self.bans = bans,
+			None => failed.push(BANS_FILE),
+		}
+		match load_file(&self.dir.join(OPS_FILE)) {
+			Some(ops) => self.ops = ops,
+			None => failed.push(OPS_FILE),
+		}
+		failed
+	}
+
+	pub fn save_whitelist(&self) -> io::Result<()> {
+		save_file(&self.dir.join(WHITELIST_FILE), &self.whitelist)
+	}
+
+	pub fn save_bans(&self) -> io::Result<()> {
+		save_file(&self.dir.join(BANS_FILE), &self.bans)
+	}
+
+	pub fn save_ops(&self) -> io::Result<()> {
+		save_file(&self.dir.join(OPS_FILE), &self.ops)
+	}
+
+	/// Checks whether a player may join, giving the reason they can't. Anyone with more than
+	/// `Permission::Player` gets past the whitelist.
+	pub fn check_login(
+		&self,
+		name: &str,
+		uuid: Uuid,
+		ip: Option<IpAddr>,
+		permission: Permission,
+	) -> Result<(), String> {
+		if let Some(ban) = self.bans.players.iter().find(|b| b.player.matches(name, uuid)) {
+			return Err(format!("You are banned: {}", ban.reason));
+		}
+		if let Some(ban) = self.bans.ips.iter().find(|b| Some(b.ip) == ip) {
+			return Err(format!("Your address is banned: {}", ban.reason));
+		}
+		let whitelisted = self.whitelist.players.iter().any(|p| p.matches(name, uuid));
+		if self.whitelist.enabled && !whitelisted && permission == Permission::Player {
+			return Err("You are not whitelisted on this server".to_owned());
+		}
+		Ok(())
+	}
+
+	/// The permission an operator has been given, `None` for everyone else
+	pub fn permission(&self, name: &str, uuid: Uuid) -> Option<Permission> {
+		self.ops
+			.iter()
+			.find(|o| o.player.matches(name, uuid))
+			.map(|o| o.permission)
+	}
+}
+
+/// Reads a list, writing an empty one if there is no file. Returns `None` if the file couldn't be read.
+fn load_file<T: Default + Serialize + DeserializeOwned>(path: &Path) -> Option<T> {
+	match fs::File::open(path) {
+		Ok(file) => match ron::de::from_reader(file) {
+			Ok(list) => Some(list),
+			Err(e) => {
+				log::warn!("Failed to parse {}: {:?}", path.display(), e);
+				None
+			}
+		},
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			let list = T::default();
+			if let Err(e) = save_file(path, &list) {
+				log::warn!("Failed to create {}: {:?}", path.display(), e);
+			}
+			Some(list)
+		}
+		Err(e) => {
+			log::warn!("Failed to open {}: {:?}", path.display(), e);
+			None
+		}
+	}
+}
+
+fn save_file<T: Serialize>(path: &Path, list: &T) -> io::Result<()> {
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir)?;
+	}
+	let stringify = ron::ser::to_string_pretty(
+		list,
+		ron::ser::PrettyConfig::default().with_indentor("\t".to_owned()),
+	)
+	.map_err(io::Error::other)?;
+	write_atomic(path, stringify.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing;
+
+	fn lists(name: &str) -> AccessLists {
+		AccessLists::load(&testing::temp_dir(name))
+	}
+
+	#[test]
+	fn bans_match_by_name_or_uuid() {
+		let mut lists = lists("access-bans");
+		lists.bans.players.push(PlayerBan {
+			player: PlayerEntry::new("Griefer"),
+			reason: "Griefing".to_owned(),
+		});
+
+		let check = |name: &str, uuid| lists.check_login(name, uuid, None, Permission::Player);
+		assert_eq!(
+			check("Griefer", player_uuid("Griefer")),
+			Err("You are banned: Griefing".to_owned())
+		);
+		// Names match ignoring case, and a UUID matches under any name
+		assert!(check("GRIEFER", player_uuid("GRIEFER")).is_err());
+		assert!(check("Renamed", player_uuid("Griefer")).is_err());
+		assert!(check("Someone", player_uuid("Someone")).is_ok());
+		// Not even operators get past a ban
+		assert!(lists
+			.check_login("Griefer", player_uuid("Griefer"), None, Permission::Admin)
+			.is_err());
+	}
+
+	#[test]
+	fn address_bans_match_the_address() {
+		let mut lists = lists("access-ip-bans");
+		let banned: IpAddr = "10.0.0.1".parse().unwrap();
+		lists.bans.ips.push(IpBan {
+			ip: banned,
+			reason: "Spam".to_owned(),
+		});
+
+		let check = |ip| lists.check_login("Someone", player_uuid("Someone"), ip, Permission::Player);
+		assert_eq!(
+			check(Some(banned)),
+			Err("Your address is banned: Spam".to_owned())
+		);
+		assert!(check("10.0.0.2".parse().ok()).is_ok());
+		// Local connections have no address to ban
+		assert!(check(None).is_ok());
+	}
+
+	#[test]
+	fn whitelist_lets_in_listed_players_and_operators() {
+		let mut lists = lists("access-whitelist");
+		lists.whitelist.players.push(PlayerEntry::new("Friend"));
+
+		let check = |name: &str, permission| lists.check_login(name, player_uuid(name), None, permission);
+		assert!(check("Stranger", Permission::Player).is_ok());
+
+		lists.whitelist.enabled = true;
+		let check = |name: &str, permission| lists.check_login(name, player_uuid(name), None, permission);
+		assert!(check("Friend", Permission::Player).is_ok());
+		assert!(check("friend", Permission::Player).is_ok());
+		assert!(check("Stranger", Permission::Player).is_err());
+		assert!(check("Stranger", Permission::Moderator).is_ok());
+	}
+}
@@ -0,0 +1,165 @@
+use std::{fmt, net::IpAddr, path::PathBuf, str::FromStr};
+
+use crate::settings::Settings;
+
+pub const USAGE: &str = "\
+Usage: takh-server [options]
+
+Options:
+	--config <file>         Settings file to use instead of the one in the config directory
+	--world <dir>           Directory the world is saved in
+	--address <ip>          Address to listen on
+	--port <port>           Port to listen on
+	--log-level <level>     One of off, error, warn, info, debug or trace
+	--pregenerate <radius>  Generate and save the world within <radius> voxels of spawn, then exit
+	--help                  Print this message";
+
+/// Options given to the server on the command line, they override the settings file
+#[derive(Default)]
+pub struct Args {
+	pub config: Option<PathBuf>,
+	pub world: Option<PathBuf>,
+	pub address: Option<IpAddr>,
+	pub port: Option<u16>,
+	pub log_level: Option<log::LevelFilter>,
+	pub pregenerate: Option<u32>,
+	pub help: bool,
+}
+
+#[derive(Debug)]
+pub enum ArgsError {
+	Unknown(String),
+	MissingValue(&'static str),
+	InvalidValue { option: &'static str, value: String },
+}
+
+impl fmt::Display for ArgsError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ArgsError::Unknown(option) => write!(f, "Unknown option '{}'", option),
+			ArgsError::MissingValue(option) => write!(f, "'{}' needs a value", option),
+			ArgsError::InvalidValue { option, value } => {
+				write!(f, "Invalid value '{}' for '{}'", value, option)
+			}
+		}
+	}
+}
+
+impl Args {
+	/// Parses the arguments, not including the program name
+	pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, ArgsError> {
+		let mut parsed = Args::default();
+		let mut args = args.into_iter();
+		while let Some(arg) = args.next() {
+			// Both `--option value` and `--option=value` are accepted
+			let (option, inline) = match arg.split_once('=') {
+				Some((option, value)) => (option.to_owned(), Some(value.to_owned())),
+				None => (arg, None),
+			};
+			let mut value = |name: &'static str| {
+				inline
+					.clone()
+					.or_else(|| args.next())
+					.ok_or(ArgsError::MissingValue(name))
+			};
+			match option.as_str() {
+				"--config" => parsed.config = Some(PathBuf::from(value("--config")?)),
+				"--world" => parsed.world = Some(PathBuf::from(value("--world")?)),
+				"--address" => parsed.address = Some(parse_value("--address", value("--address")?)?),
+				"--port" => parsed.port = Some(parse_value("--port", value("--port")?)?),
+				"--log-level" => parsed.log_level = Some(parse_value("--log-level", value("--log-level")?)?),
+				"--pregenerate" => {
+					parsed.pregenerate = Some(parse_value("--pregenerate", value("--pregenerate")?)?)
+				}
+				"-h" | "--help" => parsed.help = true,
+				_ => return Err(ArgsError::Unknown(option)),
+			}
+		}
+		Ok(parsed)
+	}
+
+	/// Overrides the settings with the options that were given
+	pub fn apply(&self, settings: &mut Settings) {
+		if let Some(world) = &self.world {
+			// Relative to where the server was started, as anyone typing it would expect
+			settings.world_path = std::env::current_dir()
+				.map(|dir| dir.join(world))
+				.unwrap_or_else(|_| world.clone());
+		}
+		if let Some(address) = self.address {
+			settings.server_address.set_ip(address);
+		}
+		if let Some(port) = self.port {
+			settings.server_address.set_port(port);
+		}
+	}
+}
+
+fn parse_value<T: FromStr>(option: &'static str, value: String) -> Result<T, ArgsError> {
+	value
+		.parse()
+		.map_err(|_| ArgsError::InvalidValue { option, value })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(args: &[&str]) -> Result<Args, ArgsError> {
+		Args::parse(args.iter().map(|a| a.to_string()))
+	}
+
+	#[test]
+	fn takes_values_after_a_space_or_an_equals_sign() {
+		let args = parse(&[
+			"--port",
+			"7000",
+			"--world=saves/one",
+			"--log-level=debug",
+			"--pregenerate",
+			"64",
+		])
+		.unwrap();
+		assert_eq!(args.port, Some(7000));
+		assert_eq!(args.world, Some(PathBuf::from("saves/one")));
+		assert_eq!(args.log_level, Some(log::LevelFilter::Debug));
+		assert_eq!(args.pregenerate, Some(64));
+		assert!(args.address.is_none() && args.config.is_none() && !args.help);
+	}
+
+	#[test]
+	fn refuses_bad_options() {
+		assert!(matches!(
+			parse(&["--port"]),
+			Err(ArgsError::MissingValue("--port"))
+		));
+		assert!(matches!(
+			parse(&["--port", "lots"]),
+			Err(ArgsError::InvalidValue { option: "--port", .. })
+		));
+		assert!(matches!(parse(&["--colour"]), Err(ArgsError::Unknown(o)) if o == "--colour"));
+		assert!(matches!(parse(&["--colour=red"]), Err(ArgsError::Unknown(o)) if o == "--colour"));
+	}
+
+	#[test]
+	fn options_given_override_the_settings() {
+		let mut settings = Settings::default();
+		let world = settings.world_path.clone();
+		parse(&["--port", "7000"]).unwrap().apply(&mut settings);
+		assert_eq!(settings.server_address.port(), 7000);
+		assert_eq!(
+			settings.server_address.ip(),
+			Settings::default().server_address.ip()
+		);
+		assert_eq!(settings.world_path, world);
+
+		parse(&["--address=127.0.0.1", "--world", "other"])
+			.unwrap()
+			.apply(&mut settings);
+		assert_eq!(settings.server_address, "127.0.0.1:7000".parse().unwrap());
+		assert_eq!(
+			settings.world_path,
+			std::env::current_dir().unwrap().join("other")
+		);
+	}
+}
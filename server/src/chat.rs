@@ -0,0 +1,68 @@
+use crate::{
+	client::Client,
+	command::{self, Source},
+	Server,
+};
+
+use common::net::server::ClientBound;
+
+use specs::{Entity, WorldExt};
+use std::time::Duration;
+
+/// Handles a line of chat from a player, lines starting with `/` are run as commands. Players that send
+/// more than the rate limit allows are refused, and kicked if they keep going.
+pub fn handle_chat(server: &mut Server, entity: Entity, message: String) {
+	let settings = &server.settings.chat;
+	let window = Duration::from_secs(settings.rate_window_secs as u64);
+	let (username, count) = match server.state.ecs().write_storage::<Client>().get_mut(entity) {
+		Some(client) => (client.username.clone(), client.add_chat_message(window)),
+		None => return,
+	};
+	if matches!(settings.kick_after, Some(max) if count >= max as usize) {
+		server.kick(entity, "Sending messages too fast");
+		return;
+	}
+	if count > settings.rate_limit as usize {
+		server.send_message(entity, "You are sending messages too fast");
+		return;
+	}
+
+	let message: String = message
+		.chars()
+		.filter(|c| !c.is_control())
+		.take(settings.max_length as usize)
+		.collect();
+	let message = message.trim();
+	if message.is_empty() {
+		return;
+	}
+
+	match message.strip_prefix('/') {
+		Some(line) => {
+			log::info!("{} ran /{}", username, line);
+			let reply = match command::run(server, &Source::Player(entity), line) {
+				Ok(reply) => reply,
+				Err(e) => e.to_string(),
+			};
+			if !reply.is_empty() {
+				server.send_message(entity, &reply);
+			}
+		}
+		None => {
+			log::info!("<{}> {}", username, message);
+			server.broadcast(ClientBound::Chat {
+				sender: Some(username),
+				message: message.to_owned(),
+			});
+		}
+	}
+}
+
+/// Suggests ways to finish the last word of a partly typed chat line, commands are completed by the
+/// command dispatcher and anything else by player names
+pub fn complete(server: &Server, entity: Entity, text: &str) -> Vec<String> {
+	match text.strip_prefix('/') {
+		Some(line) => command::complete(server, &Source::Player(entity), line),
+		None => command::complete_player(server, text.rsplit(' ').next().unwrap_or("")),
+	}
+}
@@ -1 +1,182 @@
-pub struct Client {}
+use crate::{access::player_uuid, command::Permission};
+
+use std::collections::{HashSet, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use common::{
+	net::{
+		client::{InputFrame, ServerBound},
+		server::{ClientBound, DisconnectReason},
+		EntityID, VPosition,
+	},
+	physics::Body,
+};
+
+use specs::{Component, HashMapStorage};
+use tokio::{sync::mpsc::UnboundedSender, task::JoinHandle};
+use uuid::Uuid;
+
+/// Unique id given to every accepted connection, before it has a player entity
+pub type ConnectionId = u64;
+
+/// Messages passed from the network tasks to the server tick
+pub enum Incoming {
+	/// A client has sent a valid login request
+	Connected {
+		id: ConnectionId,
+		username: String,
+		/// Where the connection came from, `None` for local connections
+		address: Option<IpAddr>,
+		sender: UnboundedSender<ClientBound>,
+		/// Task writing the messages given to `sender`
+		writer: JoinHandle<()>,
+		/// Task reading messages from the connection
+		reader: JoinHandle<()>,
+		/// When the login request arrived
+		time: Instant,
+	},
+	Message {
+		id: ConnectionId,
+		message: ServerBound,
+	},
+	/// The connection was closed, sent something we could not read or went quiet for too long, `reason` is
+	/// what the client should be told if it is still listening
+	Disconnected {
+		id: ConnectionId,
+		reason: Option<DisconnectReason>,
+	},
+}
+
+/// A voxel a player has told us they started mining
+pub struct Mining {
+	pub voxel: VPosition,
+	/// When the `Started` message arrived
+	pub started: Instant,
+}
+
+/// A logged in player's connection, attached to their player entity
+pub struct Client {
+	pub id: ConnectionId,
+	pub username: String,
+	pub uuid: Uuid,
+	/// Where the connection came from, `None` for local connections
+	pub address: Option<IpAddr>,
+	pub permission: Permission,
+	/// Selected hotbar slot, 0-9
+	pub hand: u8,
+	/// World voxel id held in each hotbar slot, `0` for an empty one
+	pub hotbar: [u32; 10],
+	/// The voxel currently being mined, if any
+	pub mining: Option<Mining>,
+	/// Chunks that have been sent to this client and that it should be kept up to date on
+	pub loaded_chunks: HashSet<(i32, i32, i32)>,
+	/// View distance the client asked for, `None` uses the server's
+	pub view_distance: Option<u32>,
+	/// Entities that have been spawned on this client
+	pub known_entities: HashSet<EntityID>,
+	/// Input frames that have arrived but haven't been simulated yet
+	pub inputs: VecDeque<InputFrame>,
+	/// Sequence number of the last input frame that was simulated
+	pub last_input: Option<u32>,
+	/// Seconds of input frames the client is allowed to have simulated right now
+	pub input_budget: f64,
+	/// When `input_budget` was last topped up
+	pub budget_updated: Instant,
+	/// Where the player was at the end of the last tick that passed every movement check
+	pub last_valid: Body,
+	/// When each recent movement violation happened
+	pub violations: VecDeque<Instant>,
+	/// When each recent chat message or command was sent
+	pub chat_messages: VecDeque<Instant>,
+	/// The keep-alive we are waiting on an answer for and when it was sent
+	pub keep_alive: Option<(u64, Instant)>,
+	/// When the next keep-alive should be sent
+	pub next_keep_alive: Instant,
+	/// Round trip time measured by the last answered keep-alive
+	pub rtt: Option<Duration>,
+	/// Task writing to the connection, it finishes once this client is dropped and its queue is sent
+	pub writer: JoinHandle<()>,
+	/// Task reading from the connection, it is aborted on disconnect so that a kicked client can't keep
+	/// sending us messages
+	pub reader: JoinHandle<()>,
+	sender: UnboundedSender<ClientBound>,
+}
+
+impl Client {
+	/// A hotbar holding every voxel of a palette `palette_len` long in order, leaving out air. Slots past
+	/// the end of the palette are left empty.
+	pub fn default_hotbar(palette_len: usize) -> [u32; 10] {
+		let mut hotbar = [0; 10];
+		for (slot, id) in hotbar.iter_mut().zip(1..palette_len as u32) {
+			*slot = id;
+		}
+		hotbar
+	}
+
+	pub fn new(
+		id: ConnectionId,
+		username: String,
+		address: Option<IpAddr>,
+		sender: UnboundedSender<ClientBound>,
+		writer: JoinHandle<()>,
+		reader: JoinHandle<()>,
+	) -> Client {
+		let uuid = player_uuid(&username);
+		Client {
+			id,
+			username,
+			uuid,
+			address,
+			permission: Permission::Player,
+			hand: 0,
+			hotbar: [0; 10],
+			mining: None,
+			loaded_chunks: HashSet::new(),
+			view_distance: None,
+			known_entities: HashSet::new(),
+			inputs: VecDeque::new(),
+			last_input: None,
+			input_budget: 0.0,
+			budget_updated: Instant::now(),
+			last_valid: Body::default(),
+			violations: VecDeque::new(),
+			chat_messages: VecDeque::new(),
+			keep_alive: None,
+			next_keep_alive: Instant::now(),
+			rtt: None,
+			writer,
+			reader,
+			sender,
+		}
+	}
+
+	/// Records a movement violation and returns how many happened within the last `window`
+	pub fn add_violation(&mut self, window: Duration) -> usize {
+		record_recent(&mut self.violations, window)
+	}
+
+	/// Records a chat message and returns how many were sent within the last `window`
+	pub fn add_chat_message(&mut self, window: Duration) -> usize {
+		record_recent(&mut self.chat_messages, window)
+	}
+
+	/// Queue a message to be sent to this client, messages to a closed connection are dropped
+	pub fn send(&self, message: ClientBound) {
+		let _ = self.sender.send(message);
+	}
+}
+
+/// Adds the current time to `times`, forgetting the ones older than `window`, and returns how many are left
+fn record_recent(times: &mut VecDeque<Instant>, window: Duration) -> usize {
+	let now = Instant::now();
+	while matches!(times.front(), Some(t) if now.duration_since(*t) > window) {
+		times.pop_front();
+	}
+	times.push_back(now);
+	times.len()
+}
+
+impl Component for Client {
+	type Storage = HashMapStorage<Self>;
+}
@@ -0,0 +1,1002 @@
+use crate::{
+	access::{IpBan, Operator, PlayerBan, PlayerEntry},
+	client::Client,
+	movement, Server,
+};
+
+use common::{
+	components::Position,
+	ecsres::TimeOfDay,
+	net::server::{ClientBound, WorldUpdate},
+	world::terrain::Terrain,
+};
+
+use serde::{Deserialize, Serialize};
+use specs::{Entities, Entity, Join, WorldExt};
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+use vek::Vec3;
+
+/// How far a player is trusted, every command needs at least a certain level
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Permission {
+	Player,
+	/// Can move players around, hand out voxels, change the time and kick
+	Moderator,
+	Admin,
+}
+
+impl FromStr for Permission {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Permission, ()> {
+		match s.to_ascii_lowercase().as_str() {
+			"player" => Ok(Permission::Player),
+			"moderator" => Ok(Permission::Moderator),
+			"admin" => Ok(Permission::Admin),
+			_ => Err(()),
+		}
+	}
+}
+
+impl fmt::Display for Permission {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Permission::Player => write!(f, "player"),
+			Permission::Moderator => write!(f, "moderator"),
+			Permission::Admin => write!(f, "admin"),
+		}
+	}
+}
+
+/// Who ran a command
+pub enum Source {
+	Player(Entity),
+	/// The server's own terminal, which can run anything
+	Console,
+}
+
+impl Source {
+	fn permission(&self, server: &Server) -> Permission {
+		match self {
+			Source::Player(entity) => server
+				.state
+				.ecs()
+				.read_storage::<Client>()
+				.get(*entity)
+				.map_or(Permission::Player, |c| c.permission),
+			Source::Console => Permission::Admin,
+		}
+	}
+
+	fn name(&self, server: &Server) -> String {
+		match self {
+			Source::Player(entity) => username(server, *entity),
+			Source::Console => "Server".to_owned(),
+		}
+	}
+
+	/// Checks that whoever ran the command has more permission than the player `name` with `permission`,
+	/// so that moderators can't act against each other or against admins. The console outranks everyone.
+	fn outranks(&self, server: &Server, name: &str, permission: Permission) -> Result<(), CommandError> {
+		match self {
+			Source::Player(_) if self.permission(server) <= permission => {
+				Err(CommandError::Outranked(name.to_owned()))
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// The player that ran the command, for commands that act on whoever ran them
+	fn player(&self) -> Result<Entity, CommandError> {
+		match self {
+			Source::Player(entity) => Ok(*entity),
+			Source::Console => Err(CommandError::NotAPlayer),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+	Unknown(String),
+	NoPermission,
+	/// The arguments didn't fit the command, holds the command so its usage can be shown
+	Usage(&'static Command),
+	InvalidArgument {
+		name: &'static str,
+		value: String,
+	},
+	PlayerNotFound(String),
+	/// The target player has as much permission as whoever ran the command
+	Outranked(String),
+	/// The command acts on whoever ran it, which has to be a player
+	NotAPlayer,
+	Failed(String),
+}
+
+impl fmt::Display for CommandError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CommandError::Unknown(name) => write!(f, "Unknown command /{}, try /help", name),
+			CommandError::NoPermission => write!(f, "You don't have permission to use that command"),
+			CommandError::Usage(command) => write!(f, "Usage: /{} {}", command.name, command.usage),
+			CommandError::InvalidArgument { name, value } => write!(f, "Invalid {}: {}", name, value),
+			CommandError::PlayerNotFound(name) => write!(f, "No player called {} is online", name),
+			CommandError::Outranked(name) => {
+				write!(
+					f,
+					"You can't do that to {}, they have as much permission as you",
+					name
+				)
+			}
+			CommandError::NotAPlayer => {
+				write!(f, "Only players can do that, name a player to do it to instead")
+			}
+			CommandError::Failed(message) => write!(f, "{}", message),
+		}
+	}
+}
+
+/// What an argument is, used to suggest values for it
+#[derive(Debug)]
+pub enum Arg {
+	Player,
+	Command,
+	/// One of a few fixed words
+	Literal(&'static [&'static str]),
+	Other,
+}
+
+type Run = fn(&mut Server, &Source, &mut Args) -> Result<String, CommandError>;
+
+pub struct Command {
+	pub name: &'static str,
+	pub usage: &'static str,
+	pub description: &'static str,
+	pub permission: Permission,
+	args: &'static [Arg],
+	run: Run,
+}
+
+impl fmt::Debug for Command {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "/{}", self.name)
+	}
+}
+
+pub const COMMANDS: &[Command] = &[
+	Command {
+		name: "help",
+		usage: "[command]",
+		description: "Lists the commands you can use, or describes one of them",
+		permission: Permission::Player,
+		args: &[Arg::Command],
+		run: help,
+	},
+	Command {
+		name: "seed",
+		usage: "",
+		description: "Shows the seed the world was generated from",
+		permission: Permission::Player,
+		args: &[],
+		run: seed,
+	},
+	Command {
+		name: "list",
+		usage: "",
+		description: "Lists the players that are online",
+		permission: Permission::Player,
+		args: &[],
+		run: list,
+	},
+	Command {
+		name: "tp",
+		usage: "[player] <target player | x y z>",
+		description: "Teleports a player, coordinates starting with ~ are relative to where they are",
+		permission: Permission::Moderator,
+		args: &[Arg::Player, Arg::Player],
+		run: tp,
+	},
+	Command {
+		name: "give",
+		usage: "<player> <voxel id> [slot]",
+		description: "Puts a voxel into a hotbar slot of a player, their selected one by default",
+		permission: Permission::Moderator,
+		args: &[Arg::Player],
+		run: give,
+	},
+	Command {
+		name: "time",
+		usage: "<query | set <ticks | day | noon | night | midnight> | add <ticks>>",
+		description: "Shows or changes the time of day",
+		permission: Permission::Moderator,
+		args: &[
+			Arg::Literal(&["query", "set", "add"]),
+			Arg::Literal(&["day", "noon", "night", "midnight"]),
+		],
+		run: time,
+	},
+	Command {
+		name: "kick",
+		usage: "<player> [reason]",
+		description: "Disconnects a player",
+		permission: Permission::Moderator,
+		args: &[Arg::Player, Arg::Other],
+		run: kick,
+	},
+	Command {
+		name: "ban",
+		usage: "<player> [reason]",
+		description: "Stops a player from joining and kicks them if they are online",
+		permission: Permission::Moderator,
+		args: &[Arg::Player, Arg::Other],
+		run: ban,
+	},
+	Command {
+		name: "ban-ip",
+		usage: "<address | player> [reason]",
+		description: "Stops everyone from an address joining and kicks them if they are online",
+		permission: Permission::Moderator,
+		args: &[Arg::Player, Arg::Other],
+		run: ban_ip,
+	},
+	Command {
+		name: "pardon",
+		usage: "<player>",
+		description: "Lets a banned player join again",
+		permission: Permission::Moderator,
+		args: &[],
+		run: pardon,
+	},
+	Command {
+		name: "pardon-ip",
+		usage: "<address>",
+		description: "Lets a banned address join again",
+		permission: Permission::Moderator,
+		args: &[],
+		run: pardon_ip,
+	},
+	Command {
+		name: "banlist",
+		usage: "",
+		description: "Lists the banned players and addresses",
+		permission: Permission::Moderator,
+		args: &[],
+		run: banlist,
+	},
+	Command {
+		name: "whitelist",
+		usage: "<on | off | list | add <player> | remove <player>>",
+		description: "Turns the whitelist on or off, or changes who is on it",
+		permission: Permission::Admin,
+		args: &[Arg::Literal(&["on", "off", "list", "add", "remove"]), Arg::Player],
+		run: whitelist,
+	},
+	Command {
+		name: "op",
+		usage: "<player> [moderator | admin]",
+		description: "Trusts a player with more commands, admin if no level is given",
+		permission: Permission::Admin,
+		args: &[Arg::Player, Arg::Literal(&["moderator", "admin"])],
+		run: op,
+	},
+	Command {
+		name: "deop",
+		usage: "<player>",
+		description: "Takes a player's operator permissions away",
+		permission: Permission::Admin,
+		args: &[Arg::Player],
+		run: deop,
+	},
+	Command {
+		name: "reload",
+		usage: "",
+		description: "Reads the whitelist, ban list and operator list again after editing them by hand",
+		permission: Permission::Admin,
+		args: &[],
+		run: reload,
+	},
+	Command {
+		name: "say",
+		usage: "<message>",
+		description: "Sends a message to everyone from the server",
+		permission: Permission::Moderator,
+		args: &[],
+		run: say,
+	},
+	Command {
+		name: "save",
+		usage: "",
+		description: "Saves the world",
+		permission: Permission::Admin,
+		args: &[],
+		run: save,
+	},
+	Command {
+		name: "stop",
+		usage: "",
+		description: "Disconnects everyone, saves the world and shuts the server down",
+		permission: Permission::Admin,
+		args: &[],
+		run: stop,
+	},
+];
+
+/// The arguments given to a command, split on whitespace
+pub struct Args<'a> {
+	command: &'static Command,
+	words: Vec<&'a str>,
+	next: usize,
+}
+
+impl<'a> Args<'a> {
+	pub fn len(&self) -> usize {
+		self.words.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.words.is_empty()
+	}
+
+	pub fn next_word(&mut self) -> Result<&'a str, CommandError> {
+		let word = self.words.get(self.next).ok_or_else(|| self.usage())?;
+		self.next += 1;
+		Ok(word)
+	}
+
+	/// Parses the next argument, `name` describes it in errors
+	pub fn next<T: FromStr>(&mut self, name: &'static str) -> Result<T, CommandError> {
+		let word = self.next_word()?;
+		word.parse().map_err(|_| CommandError::InvalidArgument {
+			name,
+			value: word.to_owned(),
+		})
+	}
+
+	/// Parses the next argument if there is one
+	pub fn optional<T: FromStr>(&mut self, name: &'static str) -> Result<Option<T>, CommandError> {
+		if self.next < self.words.len() {
+			self.next(name).map(Some)
+		} else {
+			Ok(None)
+		}
+	}
+
+	/// Parses a coordinate, which is relative to `origin` when it starts with `~`
+	pub fn coordinate(&mut self, name: &'static str, origin: f64) -> Result<f64, CommandError> {
+		let word = self.next_word()?;
+		let (base, number) = match word.strip_prefix('~') {
+			Some("") => return Ok(origin),
+			Some(rest) => (origin, rest),
+			None => (0.0, word),
+		};
+		match number.parse::<f64>() {
+			Ok(n) if n.is_finite() => Ok(base + n),
+			_ => Err(CommandError::InvalidArgument {
+				name,
+				value: word.to_owned(),
+			}),
+		}
+	}
+
+	/// Everything that is left joined back together, or `None` if nothing is
+	pub fn rest(&mut self) -> Option<String> {
+		let rest = self.words.get(self.next..).filter(|r| !r.is_empty())?.join(" ");
+		self.next = self.words.len();
+		Some(rest)
+	}
+
+	/// Fails if there are arguments that haven't been used
+	pub fn finish(&self) -> Result<(), CommandError> {
+		if self.next < self.words.len() {
+			Err(self.usage())
+		} else {
+			Ok(())
+		}
+	}
+
+	pub fn usage(&self) -> CommandError {
+		CommandError::Usage(self.command)
+	}
+}
+
+pub fn find(name: &str) -> Option<&'static Command> {
+	COMMANDS.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+}
+
+/// Runs a command line without its leading `/`, returning what to reply with
+pub fn run(server: &mut Server, source: &Source, line: &str) -> Result<String, CommandError> {
+	let mut words = line.split_whitespace();
+	let name = words.next().unwrap_or("");
+	let command = find(name).ok_or_else(|| CommandError::Unknown(name.to_owned()))?;
+	if command.permission > source.permission(server) {
+		return Err(CommandError::NoPermission);
+	}
+	let mut args = Args {
+		command,
+		words: words.collect(),
+		next: 0,
+	};
+	(command.run)(server, source, &mut args)
+}
+
+/// Suggests ways to finish the last word of a partly typed command line, given without its leading `/`
+pub fn complete(server: &Server, source: &Source, line: &str) -> Vec<String> {
+	let permission = source.permission(server);
+	let words: Vec<&str> = line.split(' ').collect();
+	let partial = words.last().copied().unwrap_or("");
+	if words.len() == 1 {
+		return command_names(permission, partial)
+			.map(|name| format!("/{}", name))
+			.collect();
+	}
+
+	let command = match find(words[0]) {
+		Some(c) if c.permission <= permission => c,
+		_ => return Vec::new(),
+	};
+	match command.args.get(words.len() - 2) {
+		Some(Arg::Player) => complete_player(server, partial),
+		Some(Arg::Command) => command_names(permission, partial).map(str::to_owned).collect(),
+		Some(Arg::Literal(options)) => options
+			.iter()
+			.filter(|o| starts_with_ignore_case(o, partial))
+			.map(|o| (*o).to_owned())
+			.collect(),
+		Some(Arg::Other) | None => Vec::new(),
+	}
+}
+
+/// Names of online players that start with `partial`
+pub fn complete_player(server: &Server, partial: &str) -> Vec<String> {
+	let mut names: Vec<String> = server
+		.state
+		.ecs()
+		.read_storage::<Client>()
+		.join()
+		.filter(|c| starts_with_ignore_case(&c.username, partial))
+		.map(|c| c.username.clone())
+		.collect();
+	names.sort();
+	names
+}
+
+fn command_names(permission: Permission, partial: &str) -> impl Iterator<Item = &'static str> + '_ {
+	COMMANDS
+		.iter()
+		.filter(move |c| c.permission <= permission && starts_with_ignore_case(c.name, partial))
+		.map(|c| c.name)
+}
+
+fn starts_with_ignore_case(s: &str, prefix: &str) -> bool {
+	s.len() >= prefix.len()
+		&& s.is_char_boundary(prefix.len())
+		&& s[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// Finds an online player by name, ignoring case
+fn player(server: &Server, name: &str) -> Result<Entity, CommandError> {
+	let ecs = server.state.ecs();
+	let entities = ecs.system_data::<Entities>();
+	let clients = ecs.read_storage::<Client>();
+	(&entities, &clients)
+		.join()
+		.find(|(_, c)| c.username.eq_ignore_ascii_case(name))
+		.map(|(e, _)| e)
+		.ok_or_else(|| CommandError::PlayerNotFound(name.to_owned()))
+}
+
+fn username(server: &Server, entity: Entity) -> String {
+	server
+		.state
+		.ecs()
+		.read_storage::<Client>()
+		.get(entity)
+		.map(|c| c.username.clone())
+		.unwrap_or_default()
+}
+
+fn position(server: &Server, entity: Entity) -> Vec3<f64> {
+	server
+		.state
+		.ecs()
+		.read_storage::<Position>()
+		.get(entity)
+		.map(|p| p.0)
+		.unwrap_or_default()
+}
+
+fn help(server: &mut Server, source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let permission = source.permission(server);
+	let name: Option<String> = args.optional("command")?;
+	args.finish()?;
+	match name {
+		Some(name) => {
+			let command = find(&name)
+				.filter(|c| c.permission <= permission)
+				.ok_or(CommandError::Unknown(name))?;
+			Ok(format!(
+				"/{} {}\n{}",
+				command.name, command.usage, command.description
+			))
+		}
+		None => Ok(COMMANDS
+			.iter()
+			.filter(|c| c.permission <= permission)
+			.map(|c| format!("/{} {}", c.name, c.usage).trim_end().to_owned())
+			.collect::<Vec<_>>()
+			.join("\n")),
+	}
+}
+
+fn seed(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	args.finish()?;
+	Ok(format!(
+		"Seed: {}",
+		server.state.ecs().read_resource::<Terrain>().seed
+	))
+}
+
+fn tp(server: &mut Server, source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let coordinates = |server: &Server, entity: Entity, args: &mut Args| {
+		let origin = position(server, entity);
+		Ok::<_, CommandError>(Vec3::new(
+			args.coordinate("x", origin.x)?,
+			args.coordinate("y", origin.y)?,
+			args.coordinate("z", origin.z)?,
+		))
+	};
+	let (entity, pos) = match args.len() {
+		1 => {
+			let target = player(server, args.next_word()?)?;
+			(source.player()?, position(server, target))
+		}
+		2 => {
+			let entity = player(server, args.next_word()?)?;
+			let target = player(server, args.next_word()?)?;
+			(entity, position(server, target))
+		}
+		3 => {
+			let entity = source.player()?;
+			(entity, coordinates(server, entity, args)?)
+		}
+		4 => {
+			let entity = player(server, args.next_word()?)?;
+			(entity, coordinates(server, entity, args)?)
+		}
+		_ => return Err(args.usage()),
+	};
+	movement::teleport(server.state.ecs(), entity, pos);
+	Ok(format!(
+		"Teleported {} to {:.1} {:.1} {:.1}",
+		username(server, entity),
+		pos.x,
+		pos.y,
+		pos.z
+	))
+}
+
+fn give(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let entity = player(server, args.next_word()?)?;
+	let voxel: u32 = args.next("voxel id")?;
+	let slot: Option<usize> = args.optional("slot")?;
+	args.finish()?;
+
+	let ecs = server.state.ecs();
+	if voxel == 0 || voxel as usize >= ecs.read_resource::<Terrain>().palette.len() {
+		return Err(CommandError::InvalidArgument {
+			name: "voxel id",
+			value: voxel.to_string(),
+		});
+	}
+	let mut clients = ecs.write_storage::<Client>();
+	let client = clients
+		.get_mut(entity)
+		.ok_or_else(|| CommandError::PlayerNotFound(username(server, entity)))?;
+	let slot = slot.unwrap_or(client.hand as usize);
+	match client.hotbar.get_mut(slot) {
+		Some(held) => *held = voxel,
+		None => {
+			return Err(CommandError::InvalidArgument {
+				name: "slot",
+				value: slot.to_string(),
+			})
+		}
+	}
+	Ok(format!(
+		"Gave {} voxel {} in slot {}",
+		client.username, voxel, slot
+	))
+}
+
+fn time(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let current = *server.state.ecs().read_resource::<TimeOfDay>();
+	let new = match args.next_word()? {
+		"query" => {
+			args.finish()?;
+			return Ok(format!(
+				"It is day {}, {} ticks in",
+				current.day(),
+				current.time()
+			));
+		}
+		"set" => {
+			let ticks = match args.next_word()? {
+				"day" => 1000,
+				"noon" => 6000,
+				"night" => 13000,
+				"midnight" => 18000,
+				word => word.parse::<u64>().map_err(|_| CommandError::InvalidArgument {
+					name: "time",
+					value: word.to_owned(),
+				})?,
+			};
+			// Only the time within the current day changes
+			let day_start = current.0 - current.time();
+			TimeOfDay(day_start + ticks % TimeOfDay::DAY_LENGTH)
+		}
+		"add" => TimeOfDay(current.0.saturating_add(args.next("ticks")?)),
+		_ => return Err(args.usage()),
+	};
+	args.finish()?;
+
+	*server.state.ecs().write_resource::<TimeOfDay>() = new;
+	server.broadcast(ClientBound::Update(WorldUpdate::TimeOfDay { ticks: new.0 }));
+	Ok(format!("Set the time to {}", new.time()))
+}
+
+fn kick(server: &mut Server, source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let entity = player(server, args.next_word()?)?;
+	let reason = args.rest().unwrap_or_else(|| "Kicked by an operator".to_owned());
+	let name = username(server, entity);
+	let permission = server
+		.state
+		.ecs()
+		.read_storage::<Client>()
+		.get(entity)
+		.map_or(Permission::Player, |c| c.permission);
+	source.outranks(server, &name, permission)?;
+	server.kick(entity, &reason);
+	Ok(format!("Kicked {}", name))
+}
+
+fn list(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	args.finish()?;
+	let mut names: Vec<String> = server
+		.state
+		.ecs()
+		.read_storage::<Client>()
+		.join()
+		.map(|c| c.username.clone())
+		.collect();
+	names.sort();
+	Ok(format!("{} players online: {}", names.len(), names.join(", ")))
+}
+
+fn say(server: &mut Server, source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let message = args.rest().ok_or_else(|| args.usage())?;
+	let message = format!("[{}] {}", source.name(server), message);
+	log::info!("{}", message);
+	server.broadcast(ClientBound::Chat {
+		sender: None,
+		message,
+	});
+	Ok(String::new())
+}
+
+fn save(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	args.finish()?;
+	if server.save() {
+		Ok("Saved the world".to_owned())
+	} else {
+		Err(CommandError::Failed("Failed to save the world".to_owned()))
+	}
+}
+
+fn stop(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	args.finish()?;
+	server.stop();
+	Ok(String::new())
+}
+
+/// The list entry for a player, using the name they are online with if they are
+fn entry(server: &Server, name: &str) -> PlayerEntry {
+	match player(server, name) {
+		Ok(entity) => PlayerEntry::new(&username(server, entity)),
+		Err(_) => PlayerEntry::new(name),
+	}
+}
+
+fn saved(result: io::Result<()>) -> Result<(), CommandError> {
+	result.map_err(|e| CommandError::Failed(format!("The change was made but could not be saved: {:?}", e)))
+}
+
+fn ban(server: &mut Server, source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let player = entry(server, args.next_word()?);
+	let reason = args.rest().unwrap_or_else(|| "Banned by an operator".to_owned());
+	source.outranks(
+		server,
+		&player.name,
+		server.permission_of(&player.name, player.uuid),
+	)?;
+	let bans = &mut server.access.bans.players;
+	bans.retain(|b| !b.player.matches(&player.name, player.uuid));
+	bans.push(PlayerBan {
+		player: player.clone(),
+		reason,
+	});
+	server.enforce_access();
+	saved(server.access.save_bans())?;
+	Ok(format!("Banned {}", player.name))
+}
+
+fn ban_ip(server: &mut Server, source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let target = args.next_word()?;
+	let ip = match target.parse::<IpAddr>() {
+		Ok(ip) => ip,
+		Err(_) => {
+			let entity = player(server, target)?;
+			server
+				.state
+				.ecs()
+				.read_storage::<Client>()
+				.get(entity)
+				.and_then(|c| c.address)
+				.ok_or_else(|| CommandError::Failed(format!("{} has no address to ban", target)))?
+		}
+	};
+	let reason = args.rest().unwrap_or_else(|| "Banned by an operator".to_owned());
+	// Everyone at the address is kicked, so whoever bans it has to outrank all of them
+	for client in server.state.ecs().read_storage::<Client>().join() {
+		if client.address == Some(ip) {
+			source.outranks(server, &client.username, client.permission)?;
+		}
+	}
+	let bans = &mut server.access.bans.ips;
+	bans.retain(|b| b.ip != ip);
+	bans.push(IpBan { ip, reason });
+	server.enforce_access();
+	saved(server.access.save_bans())?;
+	Ok(format!("Banned {}", ip))
+}
+
+fn pardon(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let player = PlayerEntry::new(args.next_word()?);
+	args.finish()?;
+	let bans = &mut server.access.bans.players;
+	let before = bans.len();
+	bans.retain(|b| !b.player.matches(&player.name, player.uuid));
+	if bans.len() == before {
+		return Err(CommandError::Failed(format!("{} isn't banned", player.name)));
+	}
+	saved(server.access.save_bans())?;
+	Ok(format!("Unbanned {}", player.name))
+}
+
+fn pardon_ip(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let ip: IpAddr = args.next("address")?;
+	args.finish()?;
+	let bans = &mut server.access.bans.ips;
+	let before = bans.len();
+	bans.retain(|b| b.ip != ip);
+	if bans.len() == before {
+		return Err(CommandError::Failed(format!("{} isn't banned", ip)));
+	}
+	saved(server.access.save_bans())?;
+	Ok(format!("Unbanned {}", ip))
+}
+
+fn banlist(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	args.finish()?;
+	let bans = &server.access.bans;
+	let mut lines = vec![format!(
+		"{} banned players and {} banned addresses",
+		bans.players.len(),
+		bans.ips.len()
+	)];
+	lines.extend(
+		bans.players
+			.iter()
+			.map(|b| format!("{}: {}", b.player.name, b.reason)),
+	);
+	lines.extend(bans.ips.iter().map(|b| format!("{}: {}", b.ip, b.reason)));
+	Ok(lines.join("\n"))
+}
+
+fn whitelist(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let reply = match args.next_word()? {
+		"on" => {
+			args.finish()?;
+			server.access.whitelist.enabled = true;
+			server.enforce_access();
+			"Turned the whitelist on".to_owned()
+		}
+		"off" => {
+			args.finish()?;
+			server.access.whitelist.enabled = false;
+			"Turned the whitelist off".to_owned()
+		}
+		"list" => {
+			args.finish()?;
+			let whitelist = &server.access.whitelist;
+			let names: Vec<&str> = whitelist.players.iter().map(|p| p.name.as_str()).collect();
+			return Ok(format!(
+				"The whitelist is {} with {} players: {}",
+				if whitelist.enabled { "on" } else { "off" },
+				names.len(),
+				names.join(", ")
+			));
+		}
+		"add" => {
+			let player = entry(server, args.next_word()?);
+			args.finish()?;
+			let players = &mut server.access.whitelist.players;
+			if players.iter().any(|p| p.matches(&player.name, player.uuid)) {
+				return Err(CommandError::Failed(format!(
+					"{} is already whitelisted",
+					player.name
+				)));
+			}
+			players.push(player.clone());
+			format!("Added {} to the whitelist", player.name)
+		}
+		"remove" => {
+			let player = PlayerEntry::new(args.next_word()?);
+			args.finish()?;
+			let players = &mut server.access.whitelist.players;
+			let before = players.len();
+			players.retain(|p| !p.matches(&player.name, player.uuid));
+			if players.len() == before {
+				return Err(CommandError::Failed(format!("{} isn't whitelisted", player.name)));
+			}
+			server.enforce_access();
+			format!("Removed {} from the whitelist", player.name)
+		}
+		_ => return Err(args.usage()),
+	};
+	saved(server.access.save_whitelist())?;
+	Ok(reply)
+}
+
+fn op(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let player = entry(server, args.next_word()?);
+	let permission = args.optional("permission")?.unwrap_or(Permission::Admin);
+	args.finish()?;
+	let ops = &mut server.access.ops;
+	ops.retain(|o| !o.player.matches(&player.name, player.uuid));
+	ops.push(Operator {
+		player: player.clone(),
+		permission,
+	});
+	server.enforce_access();
+	saved(server.access.save_ops())?;
+	Ok(format!("Made {} {}", player.name, permission))
+}
+
+fn deop(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	let player = entry(server, args.next_word()?);
+	args.finish()?;
+	let ops = &mut server.access.ops;
+	let before = ops.len();
+	ops.retain(|o| !o.player.matches(&player.name, player.uuid));
+	if ops.len() == before {
+		return Err(CommandError::Failed(format!("{} isn't an operator", player.name)));
+	}
+	server.enforce_access();
+	saved(server.access.save_ops())?;
+	Ok(format!("{} is no longer an operator", player.name))
+}
+
+fn reload(server: &mut Server, _source: &Source, args: &mut Args) -> Result<String, CommandError> {
+	args.finish()?;
+	let failed = server.access.reload();
+	server.enforce_access();
+	if failed.is_empty() {
+		Ok("Reloaded the whitelist, ban list and operator list".to_owned())
+	} else {
+		Err(CommandError::Failed(format!(
+			"Failed to reload {}, the old lists are kept",
+			failed.join(", ")
+		)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{access::player_uuid, settings::Settings, testing};
+
+	/// A server with nobody online and `ops` on its operator list
+	fn server(name: &str, ops: &[(&str, Permission)]) -> Server {
+		let mut server = Server::offline(Settings {
+			config_dir: testing::temp_dir(name),
+			..Settings::default()
+		});
+		for (name, permission) in ops {
+			server.access.ops.push(Operator {
+				player: PlayerEntry::new(name),
+				permission: *permission,
+			});
+		}
+		server
+	}
+
+	fn join(server: &mut Server, name: &str, address: Option<IpAddr>) -> Entity {
+		let (entity, _) = testing::join(&mut server.state, Vec3::zero());
+		if let Some(client) = server.state.ecs().write_storage::<Client>().get_mut(entity) {
+			client.username = name.to_owned();
+			client.uuid = player_uuid(name);
+			client.address = address;
+		}
+		server.enforce_access();
+		entity
+	}
+
+	fn online(server: &Server, name: &str) -> bool {
+		player(server, name).is_ok()
+	}
+
+	#[test]
+	fn moderators_can_only_act_on_players_below_them() {
+		let mut server = server(
+			"command-ranks",
+			&[
+				("Mod", Permission::Moderator),
+				("OtherMod", Permission::Moderator),
+				("Admin", Permission::Admin),
+				("AwayAdmin", Permission::Admin),
+			],
+		);
+		let moderator = Source::Player(join(&mut server, "Mod", None));
+		join(&mut server, "OtherMod", None);
+		join(&mut server, "Admin", None);
+		join(&mut server, "Someone", None);
+
+		let refused = |result| matches!(result, Err(CommandError::Outranked(_)));
+		assert!(refused(run(&mut server, &moderator, "kick OtherMod")));
+		assert!(refused(run(&mut server, &moderator, "kick Admin")));
+		assert!(refused(run(&mut server, &moderator, "ban Admin")));
+		// Operators are protected while they are offline too
+		assert!(refused(run(&mut server, &moderator, "ban AwayAdmin")));
+		assert!(refused(run(&mut server, &moderator, "kick Mod")));
+		assert!(online(&server, "OtherMod") && online(&server, "Admin"));
+		assert!(server.access.bans.players.is_empty());
+
+		run(&mut server, &moderator, "kick Someone").unwrap();
+		assert!(!online(&server, "Someone"));
+		run(&mut server, &moderator, "ban Nobody").unwrap();
+		assert_eq!(server.access.bans.players.len(), 1);
+	}
+
+	#[test]
+	fn address_bans_have_to_outrank_everyone_there() {
+		let mut server = server(
+			"command-ip-ranks",
+			&[("Mod", Permission::Moderator), ("Admin", Permission::Admin)],
+		);
+		let shared = "10.0.0.1".parse().ok();
+		let moderator = Source::Player(join(&mut server, "Mod", None));
+		join(&mut server, "Admin", shared);
+		join(&mut server, "Someone", shared);
+
+		assert!(matches!(
+			run(&mut server, &moderator, "ban-ip Someone"),
+			Err(CommandError::Outranked(name)) if name == "Admin"
+		));
+		assert!(server.access.bans.ips.is_empty());
+		assert!(online(&server, "Someone"));
+	}
+
+	#[test]
+	fn the_console_can_act_on_anyone() {
+		let mut server = server("command-console", &[("Admin", Permission::Admin)]);
+		join(&mut server, "Admin", "10.0.0.1".parse().ok());
+
+		run(&mut server, &Source::Console, "ban-ip Admin").unwrap();
+		assert!(!online(&server, "Admin"));
+		run(&mut server, &Source::Console, "ban Admin").unwrap();
+		assert_eq!(server.access.bans.players.len(), 1);
+	}
+}
@@ -0,0 +1,51 @@
+use crate::{
+	command::{self, Source},
+	Server,
+};
+
+use std::io::BufRead;
+use std::sync::mpsc;
+
+/// Reads commands typed into the server's terminal on a thread of its own and hands each line over through
+/// the returned channel
+pub fn spawn() -> mpsc::Receiver<String> {
+	let (sender, receiver) = mpsc::channel();
+	let thread = std::thread::Builder::new()
+		.name("console".to_owned())
+		.spawn(move || {
+			for line in std::io::stdin().lock().lines() {
+				match line {
+					Ok(line) => {
+						if sender.send(line).is_err() {
+							break;
+						}
+					}
+					Err(e) => {
+						log::warn!("Failed to read from the console: {:?}", e);
+						break;
+					}
+				}
+			}
+		});
+	if let Err(e) = thread {
+		log::warn!("Failed to start the console: {:?}", e);
+	}
+	receiver
+}
+
+/// Runs a line typed into the console as a command, the leading `/` is optional
+pub fn run_line(server: &mut Server, line: &str) {
+	let line = line.trim();
+	let line = line.strip_prefix('/').unwrap_or(line);
+	if line.is_empty() {
+		return;
+	}
+	match command::run(server, &Source::Console, line) {
+		Ok(reply) => {
+			for line in reply.lines() {
+				log::info!("{}", line);
+			}
+		}
+		Err(e) => log::warn!("{}", e),
+	}
+}
@@ -1,45 +1,161 @@
+pub mod access;
+pub mod args;
+pub mod chat;
 pub mod client;
+pub mod command;
+pub mod console;
+pub mod movement;
+pub mod player_action;
+pub mod replication;
 pub mod settings;
+pub mod storage;
+pub mod terrain;
+#[cfg(test)]
+mod testing;
 
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use client::Client;
-use common::state::State;
+use access::AccessLists;
+use client::{Client, ConnectionId, Incoming};
+use command::Permission;
+use common::{
+	components::{Last, NetworkId, OnGround, Orientation, Player, Position, Velocity},
+	ecsres::{DeltaTime, TimeOfDay},
+	net::{
+		client::{Auth as ClientAuth, ServerBound},
+		server::{Auth, ClientBound, DisconnectReason, ServerStatus, WorldUpdate},
+		transport::{Listener, LocalConnector, ReadHalf, Stream},
+		EntityID, NetError, PROTOCOL_VERSION,
+	},
+	state::State,
+	world::{
+		terrain::{Terrain, WorldPalette},
+		voxel::{Transparency, Voxel},
+	},
+};
 use settings::Settings;
+use storage::{BackgroundSave, WorldSnapshot, WorldStorage};
 
-use tokio::{
-	net::{TcpListener, TcpStream},
-	runtime::Runtime,
-};
+use specs::{Builder, Entities, Entity, Join, WorldExt};
+use tokio::{runtime::Runtime, task::JoinHandle};
+use uuid::Uuid;
 
 pub struct Server {
 	settings: Settings,
 	state: State,
 	runtime: Arc<Runtime>,
+	incoming: mpsc::Receiver<Incoming>,
+	connections: HashMap<ConnectionId, Entity>,
+	next_keep_alive_id: u64,
+	/// Ticks since the server started
+	ticks: u64,
+	/// Network id given to the next entity, see `NetworkId`
+	next_entity_id: EntityID,
+	/// Permission given to everyone who logs in, singleplayer trusts its only player with everything
+	default_permission: Permission,
+	/// Whitelist, bans and operators
+	access: AccessLists,
+	storage: WorldStorage,
+	/// Autosave being written in the background
+	saving: Option<BackgroundSave>,
+	last_save: Instant,
+	last_backup: Instant,
+	/// Cleared to stop the server at the end of the tick
+	running: Arc<AtomicBool>,
+	/// Lines typed into the console
+	console: Option<mpsc::Receiver<String>>,
+	/// Answer to status requests, kept up to date for the connection tasks to read
+	status: Arc<Mutex<ServerStatus>>,
 }
 
 impl Server {
+	/// Number of times per second the server ticks
+	pub const TICK_RATE: u32 = common::net::server::TICK_RATE;
+	/// Ticks between sending everyone the time of day
+	const TIME_SYNC_INTERVAL: u64 = 5 * Self::TICK_RATE as u64;
+	/// Most player names given in a status answer
+	const STATUS_NAMES: usize = 12;
+	/// Chunks pregenerated before they are saved and unloaded
+	const PREGENERATE_BATCH: usize = 512;
+
+	/// Starts a dedicated server listening for TCP connections on `settings.server_address`
 	pub fn new(settings: Settings) -> Server {
-		let state = State::server();
-
-		let runtime = tokio::runtime::Builder::new_multi_thread()
-			.enable_io()
-			.thread_name_fn(|| {
-				static ATOMIC_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
-				let id = ATOMIC_THREAD_ID.fetch_add(1, Ordering::SeqCst);
-				format!("tokio-runtime-{}", id)
-			})
-			.build()
-			.expect("Failed to build Tokio runtime");
+		let runtime = build_runtime();
+		let listener = runtime
+			.block_on(Listener::bind(settings.server_address))
+			.expect("Failed to bind server address");
+		Self::with_listener(settings, Arc::new(runtime), listener, Permission::Player)
+	}
+
+	/// Starts a server inside of another program on its runtime, such as the client for singleplayer. The
+	/// server can only be reached through the returned connector.
+	pub fn integrated(settings: Settings, runtime: Arc<Runtime>) -> (Server, LocalConnector) {
+		let (listener, connector) = Listener::local();
+		(
+			Self::with_listener(settings, runtime, listener, Permission::Admin),
+			connector,
+		)
+	}
+
+	/// Loads the world without listening for anyone, for working on it from the command line
+	pub fn offline(settings: Settings) -> Server {
+		// With the connector gone the listener closes straight away
+		let (listener, _) = Listener::local();
+		Self::with_listener(settings, Arc::new(build_runtime()), listener, Permission::Player)
+	}
+
+	fn with_listener(
+		settings: Settings,
+		runtime: Arc<Runtime>,
+		mut listener: Listener,
+		default_permission: Permission,
+	) -> Server {
+		let mut state = State::server();
+		state.ecs_mut().register::<Client>();
+		state.ecs_mut().register::<replication::Moving>();
+
+		// A saved world carries on where it left off
+		let access = AccessLists::load(&settings.config_dir);
+		let storage = WorldStorage::new(settings.world_dir());
+		let (seed, time) = match storage.load_info() {
+			Some(info) => {
+				log::info!("Loading the world from {}", storage.path().display());
+				(info.seed, info.time)
+			}
+			None => (settings.seed, 0),
+		};
+		state.ecs_mut().insert(Terrain::with_seed(world_palette(), seed));
+		state.ecs_mut().insert(TimeOfDay(time));
 
-		let server_address = settings.server_address.clone();
+		let (incoming_tx, incoming) = mpsc::channel();
+		let timeout = settings.network.timeout();
+		let status = Arc::new(Mutex::new(ServerStatus {
+			motd: settings.motd.clone(),
+			protocol_version: PROTOCOL_VERSION,
+			players: 0,
+			max_players: settings.max_players,
+			player_names: Vec::new(),
+		}));
+		let connection_status = status.clone();
 		runtime.spawn(async move {
-			let listener = TcpListener::bind(server_address).await.unwrap();
+			static CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
 			loop {
 				match listener.accept().await {
-					Ok((s, _)) => handle_new_connection(s).await,
-					Err(e) => log::warn!("Failed to connect new client: {}", e),
+					Ok(stream) => {
+						let id = CONNECTION_ID.fetch_add(1, Ordering::SeqCst);
+						tokio::spawn(handle_new_connection(
+							stream,
+							id,
+							timeout,
+							connection_status.clone(),
+							incoming_tx.clone(),
+						));
+					}
+					Err(NetError::ConnectionClosed) => break,
+					Err(e) => log::warn!("Failed to connect new client: {:?}", e),
 				}
 			}
 		});
@@ -47,15 +163,848 @@ impl Server {
 		Server {
 			settings,
 			state,
-			runtime: Arc::new(runtime),
+			runtime,
+			incoming,
+			connections: HashMap::new(),
+			next_keep_alive_id: 0,
+			ticks: 0,
+			next_entity_id: 0,
+			default_permission,
+			access,
+			storage,
+			saving: None,
+			last_save: Instant::now(),
+			last_backup: Instant::now(),
+			running: Arc::new(AtomicBool::new(true)),
+			console: None,
+			status,
+		}
+	}
+
+	/// Runs the commands typed into `lines` each tick, see `console::spawn`
+	pub fn attach_console(&mut self, lines: mpsc::Receiver<String>) {
+		self.console = Some(lines);
+	}
+
+	/// Ticks the server at `TICK_RATE` until `running` is cleared or the server is stopped with a command,
+	/// then disconnects everyone and saves the world
+	pub fn run(mut self, running: Arc<AtomicBool>) {
+		self.running = running;
+		let tick_length = Duration::from_secs_f64(1.0 / Self::TICK_RATE as f64);
+		while self.running.load(Ordering::Relaxed) {
+			let start = Instant::now();
+			self.tick(tick_length);
+			if let Some(rest) = tick_length.checked_sub(start.elapsed()) {
+				std::thread::sleep(rest);
+			}
+		}
+		log::info!("Stopping the server");
+		self.shutdown();
+		self.save();
+	}
+
+	/// Stops the server at the end of the current tick
+	pub fn stop(&self) {
+		self.running.store(false, Ordering::Relaxed);
+	}
+
+	/// Saves the changes to the world right away, waiting for any autosave first. Returns whether it worked.
+	pub fn save(&mut self) -> bool {
+		self.finish_autosave();
+		let snapshot = self.snapshot();
+		match self.storage.write(&snapshot) {
+			Ok(chunks) => {
+				log::info!("Saved {} chunks to {}", chunks, self.storage.path().display());
+				true
+			}
+			Err(e) => {
+				log::error!("Failed to save the world: {:?}", e);
+				self.mark_unsaved(snapshot.coords());
+				false
+			}
+		}
+	}
+
+	/// Starts writing the changed chunks on another thread when an autosave is due, so the tick isn't held
+	/// up by the disk
+	fn autosave(&mut self) {
+		if self.saving.as_ref().is_some_and(BackgroundSave::is_finished) {
+			self.finish_autosave();
+		}
+		let interval = match self.settings.autosave.interval_secs {
+			Some(secs) => Duration::from_secs(secs as u64),
+			None => return,
+		};
+		if self.saving.is_some() || self.last_save.elapsed() < interval {
+			return;
+		}
+		self.last_save = Instant::now();
+
+		let backup_due = match self.settings.autosave.backup_interval_secs {
+			Some(secs) => self.last_backup.elapsed() >= Duration::from_secs(secs as u64),
+			None => false,
+		};
+		let backups = if backup_due {
+			self.last_backup = Instant::now();
+			self.settings.autosave.backups
+		} else {
+			0
+		};
+
+		let snapshot = self.snapshot();
+		let coords = snapshot.coords();
+		match self.storage.write_in_background(snapshot, backups) {
+			Ok(save) => self.saving = Some(save),
+			Err(e) => {
+				log::error!("Failed to start an autosave: {:?}", e);
+				self.mark_unsaved(coords);
+			}
+		}
+	}
+
+	/// Waits for the autosave being written, if there is one, and logs how it went
+	fn finish_autosave(&mut self) {
+		let save = match self.saving.take() {
+			Some(save) => save,
+			None => return,
+		};
+		match save.finish(&mut self.state.ecs().write_resource::<Terrain>()) {
+			Ok(0) => log::debug!("Autosaved, no chunks had changed"),
+			Ok(chunks) => log::info!("Autosaved {} chunks", chunks),
+			Err(e) => log::error!("Failed to autosave the world: {:?}", e),
+		}
+	}
+
+	/// Takes the parts of the world that changed since the last save
+	fn snapshot(&self) -> WorldSnapshot {
+		let ecs = self.state.ecs();
+		let time = *ecs.read_resource::<TimeOfDay>();
+		WorldSnapshot::take(&mut ecs.write_resource::<Terrain>(), time)
+	}
+
+	/// Chunks whose save failed are saved again next time
+	fn mark_unsaved(&self, coords: Vec<(i32, i32, i32)>) {
+		let mut terrain = self.state.ecs().write_resource::<Terrain>();
+		for coord in coords {
+			terrain.mark_dirty(coord);
+		}
+	}
+
+	/// Loads or generates every chunk within `radius` voxels of spawn and saves the ones that were generated.
+	/// They are saved and unloaded a batch at a time so a large radius doesn't have to fit in memory. Returns
+	/// whether saving worked.
+	pub fn pregenerate(&mut self, radius: u32) -> bool {
+		let mut chunks: Vec<_> = terrain::chunks_around((0, 0, 0), radius).into_iter().collect();
+		chunks.sort_unstable();
+		log::info!("Pregenerating {} chunks", chunks.len());
+		let started = Instant::now();
+		let mut done = 0;
+		for batch in chunks.chunks(Self::PREGENERATE_BATCH) {
+			{
+				let mut terrain = self.state.ecs().write_resource::<Terrain>();
+				for &coord in batch {
+					if terrain::load_chunk(&mut terrain, &self.storage, coord) {
+						terrain.mark_dirty(coord);
+					}
+				}
+			}
+			if !self.save() {
+				return false;
+			}
+			// Nobody is online, so everything that was saved can go
+			terrain::unload_unused_chunks(self.state.ecs());
+			done += batch.len();
+			log::info!("Pregenerated {} of {} chunks", done, chunks.len());
+		}
+		log::info!("Pregenerating took {:.1?}", started.elapsed());
+		true
+	}
+
+	/// Tells every player that the server is going away and gives their connections a moment to send it
+	fn shutdown(&mut self) {
+		let players: Vec<Entity> = self.connections.values().copied().collect();
+		let writers: Vec<JoinHandle<()>> = players
+			.into_iter()
+			.filter_map(|entity| self.disconnect(entity, Some(DisconnectReason::ShuttingDown)))
+			.map(|client| client.writer)
+			.collect();
+		self.runtime.block_on(async {
+			for writer in writers {
+				let _ = tokio::time::timeout(Duration::from_secs(1), writer).await;
+			}
+		});
+	}
+
+	pub fn tick(&mut self, dt: Duration) {
+		self.ticks += 1;
+		self.state.ecs_mut().write_resource::<DeltaTime>().0 = dt.as_secs_f64();
+		// 1) handle new connections, read all network messages from clients and run console commands
+		self.handle_incoming();
+		self.handle_console();
+		// 2) check that clients are still there
+		self.keep_alive();
+		// 3) move the time of day along
+		self.advance_time();
+		// 4) simulate the movement inputs clients have sent
+		for entity in movement::apply_inputs(self.state.ecs(), &self.settings.movement) {
+			self.kick(entity, "Moving illegally");
+		}
+		// 5) send clients the terrain around them and forget about terrain nobody is near
+		terrain::update_chunk_interest(self.state.ecs(), &self.storage, self.settings.view_distance);
+		// An autosave being written still needs its chunks around in case it fails
+		if self.ticks.is_multiple_of(Self::TICK_RATE as u64) && self.saving.is_none() {
+			terrain::unload_unused_chunks(self.state.ecs());
+		}
+		// 6) tell clients how the entities around them have moved
+		replication::replicate_entities(self.state.ecs(), self.ticks);
+		// 7) save the world every so often
+		self.autosave();
+
+		self.state.ecs_mut().maintain();
+	}
+
+	pub fn runtime(&self) -> &Arc<Runtime> {
+		&self.runtime
+	}
+
+	fn handle_incoming(&mut self) {
+		while let Ok(incoming) = self.incoming.try_recv() {
+			match incoming {
+				Incoming::Connected {
+					id,
+					username,
+					address,
+					sender,
+					writer,
+					reader,
+					time,
+				} => {
+					let mut client = Client::new(id, username, address, sender, writer, reader);
+					// Inputs may have been sent from the moment the login arrived, not just from when we got
+					// to it
+					client.budget_updated = time;
+					self.login(client);
+				}
+				Incoming::Message { id, message } => {
+					if let Some(entity) = self.connections.get(&id).copied() {
+						self.handle_message(entity, message);
+					}
+				}
+				Incoming::Disconnected { id, reason } => {
+					if let Some(entity) = self.connections.get(&id).copied() {
+						self.disconnect(entity, reason);
+					}
+				}
+			}
+		}
+	}
+
+	fn handle_console(&mut self) {
+		let lines: Vec<String> = match &self.console {
+			Some(console) => console.try_iter().collect(),
+			None => return,
+		};
+		for line in lines {
+			console::run_line(self, &line);
+		}
+	}
+
+	fn login(&mut self, mut client: Client) {
+		client.permission = self.permission_of(&client.username, client.uuid);
+		client.hotbar = Client::default_hotbar(self.state.ecs().read_resource::<Terrain>().palette.len());
+
+		let already_online = self
+			.state
+			.ecs()
+			.read_storage::<Client>()
+			.join()
+			.any(|c| c.uuid == client.uuid);
+		if already_online {
+			client.send(ClientBound::Auth(Auth::Disconnect {
+				reason: DisconnectReason::LoginRefused("You are already logged in".to_owned()),
+			}));
+			return;
+		}
+		if let Err(reason) =
+			self.access
+				.check_login(&client.username, client.uuid, client.address, client.permission)
+		{
+			log::info!("{} was refused: {}", client.username, reason);
+			client.send(ClientBound::Auth(Auth::Disconnect {
+				reason: DisconnectReason::LoginRefused(reason),
+			}));
+			return;
+		}
+
+		let full = self.connections.len() >= self.settings.max_players as usize;
+		if full && client.permission == Permission::Player {
+			log::info!("{} was refused: the server is full", client.username);
+			client.send(ClientBound::Auth(Auth::Disconnect {
+				reason: DisconnectReason::LoginRefused("The server is full".to_owned()),
+			}));
+			return;
+		}
+
+		log::info!("{} joined the game", client.username);
+		self.broadcast(ClientBound::Chat {
+			sender: None,
+			message: format!("{} joined the game", client.username),
+		});
+
+		let network_id = NetworkId(self.next_entity_id);
+		self.next_entity_id += 1;
+		let entity = self
+			.state
+			.ecs_mut()
+			.create_entity()
+			.with(network_id)
+			.with(Position::default())
+			.with(Last(Position::default()))
+			.with(Orientation::default())
+			.with(Last(Orientation::default()))
+			.with(Velocity::default())
+			.with(OnGround::default())
+			.with(Player)
+			.build();
+		client.send(ClientBound::Auth(Auth::LoginSuccess {
+			entity: client.uuid,
+			id: network_id.0,
+		}));
+		client.send(ClientBound::Update(WorldUpdate::TimeOfDay {
+			ticks: self.state.ecs().read_resource::<TimeOfDay>().0,
+		}));
+		self.connections.insert(client.id, entity);
+		let _ = self.state.ecs().write_storage::<Client>().insert(entity, client);
+		self.update_status();
+	}
+
+	fn update_status(&self) {
+		let mut names: Vec<String> = self
+			.state
+			.ecs()
+			.read_storage::<Client>()
+			.join()
+			.map(|c| c.username.clone())
+			.collect();
+		names.sort();
+		let mut status = self.status.lock().unwrap();
+		status.players = names.len() as u32;
+		names.truncate(Self::STATUS_NAMES);
+		status.player_names = names;
+	}
+
+	/// The permission a player gets from the operator list, never less than what everyone gets
+	fn permission_of(&self, name: &str, uuid: Uuid) -> Permission {
+		self.access
+			.permission(name, uuid)
+			.map_or(self.default_permission, |p| p.max(self.default_permission))
+	}
+
+	/// Applies changes to the access lists to the players that are online, updating their permissions and
+	/// kicking the ones that would no longer be allowed to join
+	pub fn enforce_access(&mut self) {
+		let mut refused = Vec::new();
+		{
+			let entities = self.state.ecs().system_data::<Entities>();
+			let mut clients = self.state.ecs().write_storage::<Client>();
+			for (entity, client) in (&entities, &mut clients).join() {
+				client.permission = self.permission_of(&client.username, client.uuid);
+				let check =
+					self.access
+						.check_login(&client.username, client.uuid, client.address, client.permission);
+				if let Err(reason) = check {
+					refused.push((entity, reason));
+				}
+			}
+		}
+		for (entity, reason) in refused {
+			self.kick(entity, &reason);
+		}
+	}
+
+	/// Disconnects a player, telling them why
+	pub fn kick(&mut self, entity: Entity, message: &str) {
+		self.disconnect(entity, Some(DisconnectReason::Kicked(message.to_owned())));
+	}
+
+	/// Ends a player's session, telling them why if there is a `reason`. Their entity is removed and
+	/// despawned for everyone who could see it, and the chunks they had loaded go with it. Returns the
+	/// client so that its connection can be waited on.
+	fn disconnect(&mut self, entity: Entity, reason: Option<DisconnectReason>) -> Option<Client> {
+		let client = self.state.ecs().write_storage::<Client>().remove(entity)?;
+		client.reader.abort();
+		match &reason {
+			Some(reason) => {
+				log::info!("{} left the game: {}", client.username, reason);
+				client.send(ClientBound::Auth(Auth::Disconnect {
+					reason: reason.clone(),
+				}));
+			}
+			None => log::info!("{} left the game", client.username),
+		}
+		self.connections.remove(&client.id);
+
+		// Despawn it now rather than waiting for replication to notice it is gone
+		if let Some(NetworkId(id)) = self.state.ecs().read_storage::<NetworkId>().get(entity).copied() {
+			for other in (&mut self.state.ecs().write_storage::<Client>()).join() {
+				if other.known_entities.remove(&id) {
+					other.send(ClientBound::Update(WorldUpdate::DespawnEntity { entity: id }));
+				}
+			}
+		}
+		let _ = self.state.ecs_mut().delete_entity(entity);
+		self.update_status();
+
+		if !matches!(reason, Some(DisconnectReason::ShuttingDown)) {
+			self.broadcast(ClientBound::Chat {
+				sender: None,
+				message: format!("{} left the game", client.username),
+			});
+		}
+		Some(client)
+	}
+
+	/// Queues a message for every logged in player
+	pub fn broadcast(&self, message: ClientBound) {
+		for client in self.state.ecs().read_storage::<Client>().join() {
+			client.send(message.clone());
+		}
+	}
+
+	/// Sends a chat message from the server to a single player
+	pub fn send_message(&self, entity: Entity, message: &str) {
+		if let Some(client) = self.state.ecs().read_storage::<Client>().get(entity) {
+			client.send(ClientBound::Chat {
+				sender: None,
+				message: message.to_owned(),
+			});
+		}
+	}
+
+	fn advance_time(&mut self) {
+		let ticks = {
+			let mut time = self.state.ecs().write_resource::<TimeOfDay>();
+			time.0 += 1;
+			time.0
+		};
+		if ticks % Self::TIME_SYNC_INTERVAL == 0 {
+			self.broadcast(ClientBound::Update(WorldUpdate::TimeOfDay { ticks }));
+		}
+	}
+
+	/// Sends keep-alives to the clients that are due one and disconnects those that have not answered the
+	/// last one in time
+	fn keep_alive(&mut self) {
+		let now = Instant::now();
+		let timeout = self.settings.network.timeout();
+		let mut timed_out = Vec::new();
+		{
+			let entities = self.state.ecs().system_data::<Entities>();
+			let mut clients = self.state.ecs().write_storage::<Client>();
+			for (entity, client) in (&entities, &mut clients).join() {
+				match client.keep_alive {
+					Some((_, sent)) if now.duration_since(sent) > timeout => timed_out.push(entity),
+					None if now >= client.next_keep_alive => {
+						let id = self.next_keep_alive_id;
+						self.next_keep_alive_id += 1;
+						client.send(ClientBound::KeepAlive { id });
+						client.keep_alive = Some((id, now));
+					}
+					_ => {}
+				}
+			}
+		}
+		for entity in timed_out {
+			self.disconnect(entity, Some(DisconnectReason::TimedOut));
+		}
+	}
+
+	fn handle_message(&mut self, entity: Entity, message: ServerBound) {
+		match message {
+			ServerBound::Auth(_) => {}
+			ServerBound::KeepAlive { id } => {
+				let interval = self.settings.network.keep_alive_interval();
+				if let Some(client) = self.state.ecs().write_storage::<Client>().get_mut(entity) {
+					if let Some((expected, sent)) = client.keep_alive {
+						if expected == id {
+							client.rtt = Some(sent.elapsed());
+							client.keep_alive = None;
+							client.next_keep_alive = sent + interval;
+						}
+					}
+				}
+			}
+			ServerBound::Chat { message } => chat::handle_chat(self, entity, message),
+			ServerBound::TabComplete { text } => {
+				let suggestions = chat::complete(self, entity, &text);
+				if let Some(client) = self.state.ecs().read_storage::<Client>().get(entity) {
+					client.send(ClientBound::TabComplete { suggestions });
+				}
+			}
+			ServerBound::ViewDistance { distance } => {
+				if let Some(client) = self.state.ecs().write_storage::<Client>().get_mut(entity) {
+					client.view_distance = Some(distance);
+					client.send(ClientBound::Update(WorldUpdate::ViewDistance {
+						distance: distance.min(self.settings.view_distance),
+					}));
+				}
+			}
+			ServerBound::PlayerAction(action) => {
+				player_action::handle_player_action(self.state.ecs(), entity, action)
+			}
 		}
 	}
+}
+
+/// The voxels the world is made of, in the same order as the client's `client/voxels.ron`
+fn world_palette() -> WorldPalette {
+	let mut palette = WorldPalette::new();
+	// Dirt
+	palette.add_voxel(Voxel::new_full());
+	// Leaves
+	palette.add_voxel(
+		Voxel::new_full()
+			.with_transparency(Transparency::Cutout)
+			.with_hardness(200),
+	);
+	// Glass
+	palette.add_voxel(
+		Voxel::new_full()
+			.with_transparency(Transparency::Translucent)
+			.with_hardness(300),
+	);
+	palette
+}
+
+fn build_runtime() -> Runtime {
+	tokio::runtime::Builder::new_multi_thread()
+		.enable_all()
+		.thread_name_fn(|| {
+			static ATOMIC_THREAD_ID: AtomicUsize = AtomicUsize::new(0);
+			let id = ATOMIC_THREAD_ID.fetch_add(1, Ordering::SeqCst);
+			format!("tokio-runtime-{}", id)
+		})
+		.build()
+		.expect("Failed to build Tokio runtime")
+}
+
+async fn handle_new_connection(
+	stream: Stream,
+	id: ConnectionId,
+	timeout: Duration,
+	status: Arc<Mutex<ServerStatus>>,
+	incoming: mpsc::Sender<Incoming>,
+) {
+	let address = stream.peer_addr().map(|a| a.ip());
+	let (mut reader, mut writer) = stream.into_split();
 
-	pub fn tick(&mut self) {
-		// 1) handle new connections
-		// 2) read all network messages from clients
-		// 3)
+	// The first message from a client has to be a login request
+	let username = match tokio::time::timeout(timeout, reader.recv::<ServerBound>()).await {
+		Ok(Ok(ServerBound::Auth(ClientAuth::LoginRequest {
+			protocol_version: PROTOCOL_VERSION,
+			username,
+		}))) => ClientAuth::username(&username),
+		Ok(Ok(ServerBound::Auth(ClientAuth::LoginRequest { protocol_version, .. }))) => {
+			log::info!("Connection {} is on protocol version {}", id, protocol_version);
+			let reason = DisconnectReason::LoginRefused(format!(
+				"The server is on protocol version {}, you are on {}",
+				PROTOCOL_VERSION, protocol_version
+			));
+			let _ = writer.send(&ClientBound::Auth(Auth::Disconnect { reason })).await;
+			return;
+		}
+		Ok(Ok(ServerBound::Auth(ClientAuth::StatusRequest))) => {
+			let status = status.lock().unwrap().clone();
+			if writer
+				.send(&ClientBound::Auth(Auth::Status(status)))
+				.await
+				.is_err()
+			{
+				return;
+			}
+			// The client may ping us afterwards to see how far away we are
+			if let Ok(Ok(ServerBound::Auth(ClientAuth::Ping { id }))) =
+				tokio::time::timeout(timeout, reader.recv::<ServerBound>()).await
+			{
+				let _ = writer.send(&ClientBound::Auth(Auth::Pong { id })).await;
+			}
+			return;
+		}
+		Ok(Ok(_)) => {
+			log::warn!("Connection {} sent a message before logging in", id);
+			return;
+		}
+		Ok(Err(e)) => {
+			log::warn!("Connection {} failed to log in: {:?}", id, e);
+			return;
+		}
+		Err(_) => {
+			log::warn!("Connection {} timed out before logging in", id);
+			return;
+		}
+	};
+
+	// The writer finishes once the server drops the sender and everything queued has been sent
+	let (sender, mut outgoing) = tokio::sync::mpsc::unbounded_channel::<ClientBound>();
+	let writer = tokio::spawn(async move {
+		while let Some(message) = outgoing.recv().await {
+			if let Err(e) = writer.send(&message).await {
+				log::debug!("Failed to write to connection {}: {:?}", id, e);
+				break;
+			}
+		}
+	});
+
+	// Messages are read on a task of their own so that it can be stopped when the client is kicked. It waits
+	// until the server knows about the login so that none of its messages get there first.
+	let (start, started) = tokio::sync::oneshot::channel();
+	let reader = tokio::spawn(read_messages(reader, id, timeout, incoming.clone(), started));
+	if incoming
+		.send(Incoming::Connected {
+			id,
+			username,
+			address,
+			sender,
+			writer,
+			reader,
+			time: Instant::now(),
+		})
+		.is_ok()
+	{
+		let _ = start.send(());
 	}
 }
 
-async fn handle_new_connection(stream: TcpStream) {}
+/// Passes on the messages a logged in client sends until the connection closes or goes quiet for `timeout`
+async fn read_messages(
+	mut reader: ReadHalf,
+	id: ConnectionId,
+	timeout: Duration,
+	incoming: mpsc::Sender<Incoming>,
+	started: tokio::sync::oneshot::Receiver<()>,
+) {
+	if started.await.is_err() {
+		return;
+	}
+	loop {
+		match tokio::time::timeout(timeout, reader.recv::<ServerBound>()).await {
+			Ok(Ok(message)) => {
+				if incoming.send(Incoming::Message { id, message }).is_err() {
+					break;
+				}
+			}
+			Ok(Err(e)) => {
+				log::debug!("Connection {} closed: {:?}", id, e);
+				let _ = incoming.send(Incoming::Disconnected { id, reason: None });
+				break;
+			}
+			Err(_) => {
+				let _ = incoming.send(Incoming::Disconnected {
+					id,
+					reason: Some(DisconnectReason::TimedOut),
+				});
+				break;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use common::net::{
+		client::{PlayerAction, PlayerMiningStatus},
+		transport::WriteHalf,
+		world::WorldData,
+		Face, VPosition,
+	};
+
+	/// A singleplayer server with its world in a directory of its own, and a client connected to it
+	fn connect(name: &str) -> (Server, ReadHalf, WriteHalf) {
+		let settings = Settings {
+			view_distance: 16,
+			config_dir: testing::temp_dir(name),
+			..Settings::default()
+		};
+		let (server, connector) = Server::integrated(settings, Arc::new(build_runtime()));
+		let (reader, writer) = connector.connect().unwrap().into_split();
+		(server, reader, writer)
+	}
+
+	fn send(server: &Server, writer: &mut WriteHalf, message: ServerBound) {
+		server.runtime().block_on(writer.send(&message)).unwrap();
+	}
+
+	/// Ticks the server until it sends something `pick` is looking for
+	fn wait_for<T>(
+		server: &mut Server,
+		reader: &mut ReadHalf,
+		mut pick: impl FnMut(ClientBound) -> Option<T>,
+	) -> T {
+		let runtime = server.runtime().clone();
+		for _ in 0..200 {
+			server.tick(Duration::from_millis(50));
+			while let Ok(message) = runtime.block_on(async {
+				tokio::time::timeout(Duration::from_millis(5), reader.recv::<ClientBound>()).await
+			}) {
+				if let Some(found) = pick(message.unwrap()) {
+					return found;
+				}
+			}
+		}
+		panic!("the server never sent what we were waiting for");
+	}
+
+	fn block_change(message: ClientBound) -> Option<(VPosition, u32)> {
+		match message {
+			ClientBound::Update(WorldUpdate::BlockChange { pos, voxel_id }) => Some((pos, voxel_id)),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn answers_status_requests_and_pings() {
+		let (mut server, mut reader, mut writer) = connect("status");
+		send(&server, &mut writer, ServerBound::Auth(ClientAuth::StatusRequest));
+		let status = wait_for(&mut server, &mut reader, |m| match m {
+			ClientBound::Auth(Auth::Status(status)) => Some(status),
+			_ => None,
+		});
+		assert_eq!(status.protocol_version, PROTOCOL_VERSION);
+		assert_eq!(status.motd, Settings::default().motd);
+		assert_eq!(status.players, 0);
+
+		send(
+			&server,
+			&mut writer,
+			ServerBound::Auth(ClientAuth::Ping { id: 7 }),
+		);
+		let pong = wait_for(&mut server, &mut reader, |m| match m {
+			ClientBound::Auth(Auth::Pong { id }) => Some(id),
+			_ => None,
+		});
+		assert_eq!(pong, 7);
+		assert!(server.connections.is_empty());
+	}
+
+	#[test]
+	fn refuses_clients_on_another_protocol_version() {
+		let (mut server, mut reader, mut writer) = connect("protocol-version");
+		let mut request = ClientAuth::login_request("Tester");
+		if let ClientAuth::LoginRequest { protocol_version, .. } = &mut request {
+			*protocol_version += 1;
+		}
+		send(&server, &mut writer, ServerBound::Auth(request));
+		wait_for(&mut server, &mut reader, |m| match m {
+			ClientBound::Auth(Auth::Disconnect {
+				reason: DisconnectReason::LoginRefused(_),
+			}) => Some(()),
+			_ => None,
+		});
+		assert!(server.connections.is_empty());
+	}
+
+	#[test]
+	fn pregenerating_saves_and_unloads_the_chunks() {
+		let dir = testing::temp_dir("pregenerate");
+		let mut server = Server::offline(Settings {
+			config_dir: dir.clone(),
+			..Settings::default()
+		});
+		assert!(server.pregenerate(16));
+
+		let expected = terrain::chunks_around((0, 0, 0), 16);
+		let saved = std::fs::read_dir(dir.join("world").join("chunks"))
+			.unwrap()
+			.count();
+		assert_eq!(saved, expected.len());
+		assert_eq!(
+			server
+				.state
+				.ecs()
+				.read_resource::<Terrain>()
+				.chunk_coords()
+				.count(),
+			0
+		);
+	}
+
+	#[test]
+	fn caps_view_distances_to_its_own() {
+		let (mut server, mut reader, mut writer) = connect("view-distance");
+		send(
+			&server,
+			&mut writer,
+			ServerBound::Auth(ClientAuth::login_request("Tester")),
+		);
+		let mut view_distance = |server: &mut Server, distance| {
+			send(server, &mut writer, ServerBound::ViewDistance { distance });
+			wait_for(server, &mut reader, |m| match m {
+				ClientBound::Update(WorldUpdate::ViewDistance { distance }) => Some(distance),
+				_ => None,
+			})
+		};
+		assert_eq!(view_distance(&mut server, 64), 16);
+		assert_eq!(view_distance(&mut server, 8), 8);
+	}
+
+	#[test]
+	fn plays_over_a_local_connection() {
+		let (mut server, mut reader, mut writer) = connect("local-connection");
+		send(
+			&server,
+			&mut writer,
+			ServerBound::Auth(ClientAuth::login_request("Tester")),
+		);
+		wait_for(&mut server, &mut reader, |m| match m {
+			ClientBound::Auth(Auth::LoginSuccess { .. }) => Some(()),
+			_ => None,
+		});
+		wait_for(&mut server, &mut reader, |m| match m {
+			ClientBound::Data(WorldData::ChunkData { pos, .. }) if pos == VPosition::new(0, 0, 0) => Some(()),
+			_ => None,
+		});
+
+		// Whatever was generated at spawn, clear a spot in reach of the player at the origin to build on
+		let ground = VPosition::new(2, 0, 0);
+		let above = ground.offset(Face::Top);
+		{
+			let mut terrain = server.state.ecs().write_resource::<Terrain>();
+			terrain.set_voxel(ground, 1);
+			terrain.set_voxel(above, 0);
+		}
+
+		send(
+			&server,
+			&mut writer,
+			ServerBound::PlayerAction(PlayerAction::PlaceVoxel {
+				pos: ground,
+				face: Face::Top,
+			}),
+		);
+		let (pos, placed) = wait_for(&mut server, &mut reader, block_change);
+		assert_eq!(pos, above);
+		assert_ne!(placed, 0);
+
+		let mining = |status| {
+			ServerBound::PlayerAction(PlayerAction::PlayerMining {
+				status,
+				voxel: above,
+				face: Face::Top,
+			})
+		};
+		send(&server, &mut writer, mining(PlayerMiningStatus::Started));
+		while server
+			.state
+			.ecs()
+			.read_storage::<Client>()
+			.join()
+			.all(|c| c.mining.is_none())
+		{
+			server.tick(Duration::from_millis(50));
+		}
+		std::thread::sleep(Voxel::new_full().break_time());
+		send(&server, &mut writer, mining(PlayerMiningStatus::Completed));
+		assert_eq!(wait_for(&mut server, &mut reader, block_change), (above, 0));
+	}
+}
@@ -1,10 +1,43 @@
-use takh_server::{settings::Settings, Server};
+use std::sync::{atomic::AtomicBool, Arc};
+
+use takh_server::{
+	args::{Args, USAGE},
+	console,
+	settings::Settings,
+	Server,
+};
 
 fn main() {
-	let settings = Settings::load();
-	let mut server = Server::new(settings);
+	let args = match Args::parse(std::env::args().skip(1)) {
+		Ok(args) => args,
+		Err(e) => {
+			eprintln!("{}\n\n{}", e, USAGE);
+			std::process::exit(2);
+		}
+	};
+	if args.help {
+		println!("{}", USAGE);
+		return;
+	}
+
+	// Establish our logger
+	simple_logger::SimpleLogger::new()
+		.with_level(args.log_level.unwrap_or(log::LevelFilter::Info))
+		.init()
+		.ok();
 
-	loop {
-		server.tick();
+	let mut settings = match &args.config {
+		Some(path) => Settings::load_from(path),
+		None => Settings::load(),
+	};
+	args.apply(&mut settings);
+
+	if let Some(radius) = args.pregenerate {
+		let saved = Server::offline(settings).pregenerate(radius);
+		std::process::exit(if saved { 0 } else { 1 });
 	}
+
+	let mut server = Server::new(settings);
+	server.attach_console(console::spawn());
+	server.run(Arc::new(AtomicBool::new(true)));
 }
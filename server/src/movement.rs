@@ -0,0 +1,365 @@
+use crate::{client::Client, settings::MovementSettings};
+
+use common::{
+	components::{NetworkId, OnGround, Orientation, Position, Velocity},
+	net::{
+		client::InputFrame,
+		server::{ClientBound, WorldUpdate},
+		Position as NetPosition,
+	},
+	physics::{self, Body},
+	world::terrain::Terrain,
+};
+
+use specs::{Entities, Entity, Join, World, WorldExt};
+use std::time::{Duration, Instant};
+use vek::Vec3;
+
+/// Most input frames kept waiting for a single client, anything past this is dropped on arrival
+const MAX_QUEUED_INPUTS: usize = 240;
+/// Rounding slack for the movement checks
+const EPSILON: f64 = 1e-6;
+
+/// Ways a player's movement can fail validation. Speed, gravity and collisions can't be broken as we run
+/// the physics ourselves, only inputs are taken from the client.
+#[derive(Debug)]
+enum Violation {
+	/// Sent more input frames than real time allows, which is how a speed hack looks with inputs
+	AheadOfTime,
+	/// An input frame had an impossible look direction
+	BadInput,
+}
+
+/// Queues an input frame from a client to be simulated on a later tick. Frames that are older than ones
+/// already simulated are dropped.
+pub fn queue_input(client: &mut Client, frame: InputFrame) {
+	let newest = client.inputs.back().map(|f| f.seq).or(client.last_input);
+	if matches!(newest, Some(seq) if frame.seq <= seq) || client.inputs.len() == MAX_QUEUED_INPUTS {
+		return;
+	}
+	client.inputs.push_back(frame);
+}
+
+/// Moves a player straight to `pos`, dropping the inputs they sent for where they were before
+pub fn teleport(ecs: &World, entity: Entity, pos: Vec3<f64>) {
+	// Rounded the same way the client will see it so that both agree on where the player is
+	let sent = NetPosition::from_vec3(pos);
+	let body = Body {
+		pos: sent.to_vec3(),
+		..Body::default()
+	};
+	if let Some(pos) = ecs.write_storage::<Position>().get_mut(entity) {
+		pos.0 = body.pos;
+	}
+	if let Some(vel) = ecs.write_storage::<Velocity>().get_mut(entity) {
+		vel.0 = body.vel;
+	}
+	if let Some(on_ground) = ecs.write_storage::<OnGround>().get_mut(entity) {
+		on_ground.0 = body.on_ground;
+	}
+	let id = ecs.read_storage::<NetworkId>().get(entity).copied();
+	if let (Some(client), Some(NetworkId(id))) = (ecs.write_storage::<Client>().get_mut(entity), id) {
+		client.inputs.clear();
+		client.last_valid = body;
+		client.send(ClientBound::Update(WorldUpdate::EntityTeleport {
+			entity: id,
+			pos: sent,
+		}));
+	}
+}
+
+/// Runs the queued input frames through the shared physics, then tells each client where their player
+/// ended up so it can correct its prediction. Each client may only simulate as much time as has really
+/// passed, give or take `jitter_tolerance_ms`. Players that fail a check are put back where they last
+/// were valid. Returns the players that have failed often enough to be kicked.
+pub fn apply_inputs(ecs: &World, settings: &MovementSettings) -> Vec<Entity> {
+	let entities = ecs.system_data::<Entities>();
+	let ids = ecs.read_storage::<NetworkId>();
+	let terrain = ecs.read_resource::<Terrain>();
+	let mut clients = ecs.write_storage::<Client>();
+	let mut positions = ecs.write_storage::<Position>();
+	let mut orientations = ecs.write_storage::<Orientation>();
+	let mut velocities = ecs.write_storage::<Velocity>();
+	let mut on_grounds = ecs.write_storage::<OnGround>();
+
+	let tolerance = settings.jitter_tolerance_ms as f64 / 1000.0;
+	let window = Duration::from_secs(settings.violation_window_secs as u64);
+	let mut kicked = Vec::new();
+	let now = Instant::now();
+
+	for (entity, &NetworkId(id), client, pos, ori, vel, on_ground) in (
+		&entities,
+		&ids,
+		&mut clients,
+		&mut positions,
+		&mut orientations,
+		&mut velocities,
+		&mut on_grounds,
+	)
+		.join()
+	{
+		let elapsed = now.duration_since(client.budget_updated).as_secs_f64();
+		client.budget_updated = now;
+		client.input_budget = (client.input_budget + elapsed).min(tolerance.max(elapsed));
+		if client.inputs.is_empty() {
+			continue;
+		}
+
+		let mut body = Body {
+			pos: pos.0,
+			vel: vel.0,
+			on_ground: on_ground.0,
+		};
+		let mut violation = None;
+		while client.input_budget + EPSILON >= physics::STEP {
+			let frame = match client.inputs.pop_front() {
+				Some(f) => f,
+				None => break,
+			};
+			client.input_budget -= physics::STEP;
+			client.last_input = Some(frame.seq);
+
+			if !frame.yaw.is_finite() || !frame.pitch.is_finite() {
+				violation = Some(Violation::BadInput);
+				break;
+			}
+			physics::step(&terrain, &mut body, &frame);
+			ori.0 = Vec3::new(frame.yaw as f64, frame.pitch.clamp(-90.0, 90.0) as f64, 0.0);
+		}
+		// Whatever is still waiting has to fit within the jitter tolerance
+		if violation.is_none() && client.inputs.len() as f64 * physics::STEP > tolerance + EPSILON {
+			violation = Some(Violation::AheadOfTime);
+		}
+
+		match violation {
+			Some(violation) => {
+				let count = client.add_violation(window);
+				log::warn!(
+					"{} failed a movement check ({:?}), {} in the last {}s",
+					client.username,
+					violation,
+					count,
+					window.as_secs()
+				);
+				if matches!(settings.kick_after, Some(max) if count >= max as usize) {
+					kicked.push(entity);
+				}
+
+				// Rubber-band back to the last good position, rounded the same way the client will see it
+				client.inputs.clear();
+				let sent = NetPosition::from_vec3(client.last_valid.pos);
+				body = Body {
+					pos: sent.to_vec3(),
+					..Body::default()
+				};
+				client.send(ClientBound::Update(WorldUpdate::EntityTeleport {
+					entity: id,
+					pos: sent,
+				}));
+			}
+			None => {
+				if let Some(seq) = client.last_input {
+					client.send(ClientBound::Update(WorldUpdate::PlayerState {
+						seq,
+						pos: body.pos.into_tuple(),
+						vel: body.vel.into_tuple(),
+						on_ground: body.on_ground,
+					}));
+				}
+			}
+		}
+		client.last_valid = body;
+		pos.0 = body.pos;
+		vel.0 = body.vel;
+		on_ground.0 = body.on_ground;
+	}
+	kicked
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{self, received};
+
+	const START: Vec3<f64> = Vec3::new(4.5, 1.0, 4.5);
+
+	fn settings(kick_after: Option<u32>) -> MovementSettings {
+		MovementSettings {
+			jitter_tolerance_ms: 500,
+			kick_after,
+			violation_window_secs: 30,
+		}
+	}
+
+	/// Queues standing still for the frames `seqs`, as if `elapsed` had passed since the last tick
+	fn send_inputs(ecs: &World, entity: Entity, seqs: std::ops::Range<u32>, elapsed: Duration) {
+		let mut clients = ecs.write_storage::<Client>();
+		let client = clients.get_mut(entity).unwrap();
+		client.budget_updated = Instant::now() - elapsed;
+		for seq in seqs {
+			queue_input(
+				client,
+				InputFrame {
+					seq,
+					..InputFrame::default()
+				},
+			);
+		}
+	}
+
+	fn teleported_to(messages: &[ClientBound]) -> Option<Vec3<f64>> {
+		messages.iter().find_map(|m| match m {
+			ClientBound::Update(WorldUpdate::EntityTeleport { pos, .. }) => Some(pos.to_vec3()),
+			_ => None,
+		})
+	}
+
+	#[test]
+	fn queue_drops_stale_and_excess_frames() {
+		let mut state = testing::world();
+		let (entity, _receiver) = testing::join(&mut state, START);
+		let mut clients = state.ecs().write_storage::<Client>();
+		let client = clients.get_mut(entity).unwrap();
+
+		for seq in [5, 5, 3, 6].iter() {
+			queue_input(
+				client,
+				InputFrame {
+					seq: *seq,
+					..InputFrame::default()
+				},
+			);
+		}
+		assert_eq!(
+			client.inputs.iter().map(|f| f.seq).collect::<Vec<_>>(),
+			vec![5, 6]
+		);
+
+		// Already simulated frames stay dropped once the queue has run dry
+		client.inputs.clear();
+		client.last_input = Some(10);
+		queue_input(
+			client,
+			InputFrame {
+				seq: 8,
+				..InputFrame::default()
+			},
+		);
+		assert!(client.inputs.is_empty());
+
+		for seq in 11..11 + 2 * MAX_QUEUED_INPUTS as u32 {
+			queue_input(
+				client,
+				InputFrame {
+					seq,
+					..InputFrame::default()
+				},
+			);
+		}
+		assert_eq!(client.inputs.len(), MAX_QUEUED_INPUTS);
+	}
+
+	#[test]
+	fn simulates_only_the_time_that_has_passed() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = testing::join(&mut state, START);
+
+		// 0.2s of input after 0.1s: half of it has to wait, but that is within the jitter tolerance
+		send_inputs(state.ecs(), entity, 0..12, Duration::from_millis(100));
+		assert!(apply_inputs(state.ecs(), &settings(Some(1))).is_empty());
+
+		let clients = state.ecs().read_storage::<Client>();
+		let client = clients.get(entity).unwrap();
+		assert_eq!(client.last_input, Some(5));
+		assert_eq!(client.inputs.len(), 6);
+		let messages = received(&mut receiver);
+		assert!(teleported_to(&messages).is_none());
+		assert!(messages
+			.iter()
+			.any(|m| matches!(m, ClientBound::Update(WorldUpdate::PlayerState { seq: 5, .. }))));
+	}
+
+	#[test]
+	fn inputs_held_up_by_the_network_catch_up() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = testing::join(&mut state, START);
+
+		// Nothing arrived for 0.3s, then all of it at once
+		send_inputs(state.ecs(), entity, 0..18, Duration::from_millis(300));
+		assert!(apply_inputs(state.ecs(), &settings(Some(1))).is_empty());
+		// And the next batch is a little early
+		send_inputs(state.ecs(), entity, 18..42, Duration::from_millis(0));
+		assert!(apply_inputs(state.ecs(), &settings(Some(1))).is_empty());
+
+		let clients = state.ecs().read_storage::<Client>();
+		assert_eq!(clients.get(entity).unwrap().last_input, Some(17));
+		assert!(teleported_to(&received(&mut receiver)).is_none());
+	}
+
+	#[test]
+	fn rubber_bands_inputs_ahead_of_time() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = testing::join(&mut state, START);
+
+		// A second of input with no time passed is far past the jitter tolerance
+		send_inputs(state.ecs(), entity, 0..60, Duration::from_millis(0));
+		assert!(apply_inputs(state.ecs(), &settings(None)).is_empty());
+
+		assert_eq!(teleported_to(&received(&mut receiver)), Some(START));
+		assert_eq!(
+			state.ecs().read_storage::<Position>().get(entity).unwrap().0,
+			START
+		);
+		let clients = state.ecs().read_storage::<Client>();
+		let client = clients.get(entity).unwrap();
+		assert!(client.inputs.is_empty());
+		assert_eq!(client.violations.len(), 1);
+	}
+
+	#[test]
+	fn rubber_bands_impossible_look_directions() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = testing::join(&mut state, START);
+
+		{
+			let mut clients = state.ecs().write_storage::<Client>();
+			let client = clients.get_mut(entity).unwrap();
+			client.budget_updated = Instant::now() - Duration::from_millis(100);
+			queue_input(
+				client,
+				InputFrame {
+					yaw: f32::NAN,
+					..InputFrame::default()
+				},
+			);
+		}
+		apply_inputs(state.ecs(), &settings(None));
+		assert_eq!(teleported_to(&received(&mut receiver)), Some(START));
+	}
+
+	#[test]
+	fn kicks_after_enough_violations_within_the_window() {
+		let mut state = testing::world();
+		let (entity, _receiver) = testing::join(&mut state, START);
+
+		// Violations from before the window don't count
+		{
+			let mut clients = state.ecs().write_storage::<Client>();
+			let old = Instant::now() - Duration::from_secs(60);
+			clients
+				.get_mut(entity)
+				.unwrap()
+				.violations
+				.extend([old, old].iter());
+		}
+		for attempt in 1..=3 {
+			send_inputs(state.ecs(), entity, 0..60, Duration::from_millis(0));
+			let kicked = apply_inputs(state.ecs(), &settings(Some(3)));
+			if attempt < 3 {
+				assert!(kicked.is_empty(), "kicked after {} violations", attempt);
+			} else {
+				assert_eq!(kicked, vec![entity]);
+			}
+		}
+	}
+}
@@ -0,0 +1,425 @@
+use crate::{
+	client::{Client, Mining},
+	movement,
+	terrain::broadcast_voxel,
+};
+
+use common::{
+	components::{Player, Position},
+	net::{
+		client::{PlayerAction, PlayerMiningStatus},
+		server::{ClientBound, WorldUpdate},
+		Face, VPosition,
+	},
+	world::terrain::{Terrain, WorldPalette},
+};
+
+use specs::{Entity, Join, World, WorldExt};
+use std::time::{Duration, Instant};
+use vek::Vec3;
+
+/// Slack given to mining times, to allow for the `Started` message arriving late
+const MINING_TOLERANCE: Duration = Duration::from_millis(100);
+
+/// Reasons a voxel edit from a client can be refused
+#[derive(Debug)]
+enum Rejection {
+	/// The voxel's chunk isn't loaded on the server
+	NotLoaded,
+	/// The voxel is further than `Player::REACH` from the player's eyes
+	OutOfReach,
+	/// Tried to break air, or place against air
+	NothingThere,
+	/// Claimed to finish mining before the voxel's break time was up
+	TooFast,
+	/// Tried to place into a voxel that is already filled
+	Occupied,
+	/// Placing the voxel would put it inside an entity
+	Collides,
+	/// The player's hand doesn't hold a placeable voxel
+	EmptyHand,
+}
+
+pub fn handle_player_action(ecs: &World, entity: Entity, action: PlayerAction) {
+	match action {
+		PlayerAction::PlayerInput(frame) => {
+			if let Some(client) = ecs.write_storage::<Client>().get_mut(entity) {
+				movement::queue_input(client, frame);
+			}
+		}
+		PlayerAction::PlayerMining { status, voxel, .. } => {
+			let mut clients = ecs.write_storage::<Client>();
+			let client = match clients.get_mut(entity) {
+				Some(c) => c,
+				None => return,
+			};
+			match status {
+				PlayerMiningStatus::Started => {
+					client.mining = Some(Mining {
+						voxel,
+						started: Instant::now(),
+					})
+				}
+				PlayerMiningStatus::Stopped => client.mining = None,
+				PlayerMiningStatus::Completed => {
+					let started = match client.mining.take() {
+						Some(m) if m.voxel == voxel => Some(m.started),
+						_ => None,
+					};
+					drop(clients);
+					break_voxel(ecs, entity, voxel, started);
+				}
+			}
+		}
+		PlayerAction::PlaceVoxel { pos, face } => place_voxel(ecs, entity, pos, face),
+		PlayerAction::SetHand(slot) => {
+			if let Some(client) = ecs.write_storage::<Client>().get_mut(entity) {
+				client.hand = slot.min(9);
+			}
+		}
+	}
+}
+
+/// The world voxel id held in the player's selected hotbar slot, air is never placeable
+fn hand_voxel(palette: &WorldPalette, client: &Client) -> Option<u32> {
+	let id = client.hotbar[client.hand as usize];
+	if id != 0 && (id as usize) < palette.len() {
+		Some(id)
+	} else {
+		None
+	}
+}
+
+/// Breaks the voxel at `pos`, `started` is when the player started mining this voxel
+fn break_voxel(ecs: &World, entity: Entity, pos: VPosition, started: Option<Instant>) {
+	let result = {
+		let terrain = ecs.read_resource::<Terrain>();
+		let eye = eye_pos(ecs, entity);
+		match terrain.get_voxel(pos) {
+			None => Err(Rejection::NotLoaded),
+			Some(_) if !in_reach(eye, pos) => Err(Rejection::OutOfReach),
+			Some(v) if v.is_air => Err(Rejection::NothingThere),
+			Some(v) if !mined_long_enough(v.break_time(), started) => Err(Rejection::TooFast),
+			Some(_) => Ok(()),
+		}
+	};
+
+	match result {
+		Ok(()) => {
+			if ecs.write_resource::<Terrain>().set_voxel(pos, 0) {
+				broadcast_voxel(ecs, pos, 0);
+			} else {
+				correct_voxel(ecs, entity, pos);
+			}
+		}
+		Err(reason) => {
+			log::debug!("Rejected voxel break at {:?}: {:?}", pos, reason);
+			correct_voxel(ecs, entity, pos);
+		}
+	}
+}
+
+fn place_voxel(ecs: &World, entity: Entity, pos: VPosition, face: Face) {
+	let target = pos.offset(face);
+	let result = {
+		let terrain = ecs.read_resource::<Terrain>();
+		let eye = eye_pos(ecs, entity);
+		let clients = ecs.read_storage::<Client>();
+		let client = clients.get(entity);
+		match (terrain.get_voxel(pos), terrain.get_voxel(target)) {
+			(None, _) | (_, None) => Err(Rejection::NotLoaded),
+			_ if !in_reach(eye, target) => Err(Rejection::OutOfReach),
+			(Some(against), _) if against.is_air => Err(Rejection::NothingThere),
+			(_, Some(existing)) if !existing.is_air => Err(Rejection::Occupied),
+			_ if collides_with_entity(ecs, target) => Err(Rejection::Collides),
+			_ => client
+				.and_then(|client| hand_voxel(&terrain.palette, client))
+				.ok_or(Rejection::EmptyHand),
+		}
+	};
+
+	match result {
+		Ok(voxel_id) => {
+			if ecs.write_resource::<Terrain>().set_voxel(target, voxel_id) {
+				broadcast_voxel(ecs, target, voxel_id);
+			} else {
+				correct_voxel(ecs, entity, target);
+			}
+		}
+		Err(reason) => {
+			log::debug!("Rejected voxel place at {:?}: {:?}", target, reason);
+			correct_voxel(ecs, entity, target);
+		}
+	}
+}
+
+/// Whether enough time has passed since `started` to break a voxel, voxels with no break time can be
+/// broken without having started mining them
+fn mined_long_enough(break_time: Duration, started: Option<Instant>) -> bool {
+	if break_time == Duration::from_millis(0) {
+		return true;
+	}
+	match started {
+		Some(started) => started.elapsed() + MINING_TOLERANCE >= break_time,
+		None => false,
+	}
+}
+
+fn eye_pos(ecs: &World, entity: Entity) -> Option<Vec3<f64>> {
+	ecs.read_storage::<Position>()
+		.get(entity)
+		.map(|p| p.0 + Vec3::new(0.0, Player::EYE_HEIGHT, 0.0))
+}
+
+fn in_reach(eye: Option<Vec3<f64>>, pos: VPosition) -> bool {
+	match eye {
+		Some(eye) => eye.distance(pos.centre()) <= Player::REACH,
+		None => false,
+	}
+}
+
+/// Whether a voxel at `pos` would overlap the bounding box of any player
+fn collides_with_entity(ecs: &World, pos: VPosition) -> bool {
+	let min = Vec3::new(pos.x as f64, pos.y as f64, pos.z as f64);
+	let max = min + 1.0;
+	let half = Player::WIDTH / 2.0;
+	let positions = ecs.read_storage::<Position>();
+	let players = ecs.read_storage::<Player>();
+	(&positions, &players).join().any(|(p, _)| {
+		let p_min = p.0 - Vec3::new(half, 0.0, half);
+		let p_max = p.0 + Vec3::new(half, Player::HEIGHT, half);
+		p_min.x < max.x
+			&& p_max.x > min.x
+			&& p_min.y < max.y
+			&& p_max.y > min.y
+			&& p_min.z < max.z
+			&& p_max.z > min.z
+	})
+}
+
+/// Tells a client what is really at `pos` after it predicted an edit that we refused
+fn correct_voxel(ecs: &World, entity: Entity, pos: VPosition) {
+	let voxel_id = match ecs.read_resource::<Terrain>().voxel_id(pos) {
+		Some(id) => id,
+		None => return,
+	};
+	if let Some(client) = ecs.read_storage::<Client>().get(entity) {
+		client.send(ClientBound::Update(WorldUpdate::BlockChange { pos, voxel_id }));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{self, STONE};
+
+	use common::{net::client::PlayerMiningStatus, state::State};
+	use tokio::sync::mpsc::UnboundedReceiver;
+
+	/// A player standing on the floor of the test world, the voxel under their feet is `(4, 0, 4)`
+	fn player(state: &mut State) -> (Entity, UnboundedReceiver<ClientBound>) {
+		let (entity, receiver) = testing::join(state, Vec3::new(4.5, 1.0, 4.5));
+		let palette_len = state.ecs().read_resource::<Terrain>().palette.len();
+		state
+			.ecs()
+			.write_storage::<Client>()
+			.get_mut(entity)
+			.unwrap()
+			.hotbar = Client::default_hotbar(palette_len);
+		(entity, receiver)
+	}
+
+	/// Finishes mining `voxel` after having started `mined_for` ago
+	fn mine(state: &State, entity: Entity, voxel: VPosition, mined_for: Duration) {
+		state
+			.ecs()
+			.write_storage::<Client>()
+			.get_mut(entity)
+			.unwrap()
+			.mining = Some(Mining {
+			voxel,
+			started: Instant::now() - mined_for,
+		});
+		handle_player_action(
+			state.ecs(),
+			entity,
+			PlayerAction::PlayerMining {
+				status: PlayerMiningStatus::Completed,
+				voxel,
+				face: Face::Top,
+			},
+		);
+	}
+
+	fn voxel_at(state: &State, pos: VPosition) -> Option<u32> {
+		state.ecs().read_resource::<Terrain>().voxel_id(pos)
+	}
+
+	/// Whether the only thing sent was `pos` being set to `voxel_id`
+	fn only_block_change(messages: &[ClientBound], pos: VPosition, voxel_id: u32) -> bool {
+		matches!(
+			messages,
+			[ClientBound::Update(WorldUpdate::BlockChange { pos: p, voxel_id: v })] if *p == pos && *v == voxel_id
+		)
+	}
+
+	#[test]
+	fn breaks_a_mined_voxel_and_tells_everyone() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = player(&mut state);
+		let (_, mut other) = player(&mut state);
+		let floor = VPosition::new(4, 0, 5);
+
+		mine(&state, entity, floor, Duration::from_secs(1));
+		assert_eq!(voxel_at(&state, floor), Some(0));
+		assert!(only_block_change(&testing::received(&mut receiver), floor, 0));
+		assert!(only_block_change(&testing::received(&mut other), floor, 0));
+	}
+
+	#[test]
+	fn refuses_to_break_out_of_reach() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = player(&mut state);
+		let far = VPosition::new(20, 0, 20);
+
+		mine(&state, entity, far, Duration::from_secs(1));
+		assert_eq!(voxel_at(&state, far), Some(STONE));
+		// The client is told what is really there, undoing its prediction
+		assert!(only_block_change(&testing::received(&mut receiver), far, STONE));
+	}
+
+	#[test]
+	fn refuses_to_break_air() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = player(&mut state);
+		let air = VPosition::new(5, 1, 5);
+
+		mine(&state, entity, air, Duration::from_secs(1));
+		assert!(only_block_change(&testing::received(&mut receiver), air, 0));
+	}
+
+	#[test]
+	fn refuses_to_break_before_the_break_time() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = player(&mut state);
+		let floor = VPosition::new(4, 0, 5);
+
+		mine(&state, entity, floor, Duration::from_millis(100));
+		assert_eq!(voxel_at(&state, floor), Some(STONE));
+		assert!(only_block_change(&testing::received(&mut receiver), floor, STONE));
+		// Finishing a voxel other than the one that was started doesn't count either
+		state
+			.ecs()
+			.write_storage::<Client>()
+			.get_mut(entity)
+			.unwrap()
+			.mining = Some(Mining {
+			voxel: VPosition::new(5, 0, 5),
+			started: Instant::now() - Duration::from_secs(1),
+		});
+		handle_player_action(
+			state.ecs(),
+			entity,
+			PlayerAction::PlayerMining {
+				status: PlayerMiningStatus::Completed,
+				voxel: floor,
+				face: Face::Top,
+			},
+		);
+		assert_eq!(voxel_at(&state, floor), Some(STONE));
+	}
+
+	#[test]
+	fn mining_time_allows_for_the_tolerance() {
+		let break_time = Duration::from_millis(750);
+		let now = Instant::now();
+		assert!(mined_long_enough(Duration::from_millis(0), None));
+		assert!(!mined_long_enough(break_time, None));
+		assert!(!mined_long_enough(break_time, Some(now)));
+		assert!(mined_long_enough(
+			break_time,
+			Some(now - break_time + MINING_TOLERANCE)
+		));
+		assert!(!mined_long_enough(
+			break_time,
+			Some(now - break_time + MINING_TOLERANCE * 2)
+		));
+	}
+
+	#[test]
+	fn places_against_a_voxel() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = player(&mut state);
+		let floor = VPosition::new(6, 0, 4);
+
+		let action = PlayerAction::PlaceVoxel {
+			pos: floor,
+			face: Face::Top,
+		};
+		handle_player_action(state.ecs(), entity, action);
+		let placed = floor.offset(Face::Top);
+		assert_eq!(voxel_at(&state, placed), Some(STONE));
+		assert!(only_block_change(
+			&testing::received(&mut receiver),
+			placed,
+			STONE
+		));
+	}
+
+	#[test]
+	fn refuses_to_place_against_air_or_into_a_voxel() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = player(&mut state);
+
+		// Nothing to place against
+		let air = VPosition::new(6, 2, 4);
+		let action = PlayerAction::PlaceVoxel {
+			pos: air,
+			face: Face::Top,
+		};
+		handle_player_action(state.ecs(), entity, action);
+		assert_eq!(voxel_at(&state, air.offset(Face::Top)), Some(0));
+		assert!(only_block_change(
+			&testing::received(&mut receiver),
+			air.offset(Face::Top),
+			0
+		));
+
+		// Against the side of the floor, into the floor next to it
+		let floor = VPosition::new(6, 0, 4);
+		let action = PlayerAction::PlaceVoxel {
+			pos: floor,
+			face: Face::East,
+		};
+		handle_player_action(state.ecs(), entity, action);
+		assert!(only_block_change(
+			&testing::received(&mut receiver),
+			floor.offset(Face::East),
+			STONE
+		));
+	}
+
+	#[test]
+	fn refuses_to_place_out_of_reach_or_inside_a_player() {
+		let mut state = testing::world();
+		let (entity, mut receiver) = player(&mut state);
+
+		let far = VPosition::new(20, 0, 20);
+		let action = PlayerAction::PlaceVoxel {
+			pos: far,
+			face: Face::Top,
+		};
+		handle_player_action(state.ecs(), entity, action);
+		assert_eq!(voxel_at(&state, far.offset(Face::Top)), Some(0));
+
+		// Where the player is standing
+		let under = VPosition::new(4, 0, 4);
+		let action = PlayerAction::PlaceVoxel {
+			pos: under,
+			face: Face::Top,
+		};
+		handle_player_action(state.ecs(), entity, action);
+		assert_eq!(voxel_at(&state, under.offset(Face::Top)), Some(0));
+		assert_eq!(testing::received(&mut receiver).len(), 2);
+	}
+}
@@ -0,0 +1,177 @@
+use crate::{client::Client, terrain::chunk_at};
+
+use common::{
+	components::{Last, NetworkId, Orientation, Player, Position},
+	net::{
+		server::{ClientBound, WorldUpdate},
+		EntityID, Position as NetPosition, Rotation,
+	},
+};
+
+use specs::{Component, Entities, Join, NullStorage, World, WorldExt};
+use std::collections::HashSet;
+
+/// Marks an entity that moved the last time it was replicated, so that clients are told when it stops
+#[derive(Default)]
+pub struct Moving;
+
+impl Component for Moving {
+	type Storage = NullStorage<Self>;
+}
+
+/// What changed about an entity since it was last replicated
+struct EntityState {
+	id: EntityID,
+	chunk: (i32, i32, i32),
+	pos: NetPosition,
+	rot: Rotation,
+	/// Updates to send to clients that already know about this entity
+	updates: Vec<WorldUpdate>,
+}
+
+/// Sends every client the movement of the entities near them on server tick `tick`, spawning entities
+/// that come into view and despawning the ones that leave it
+pub fn replicate_entities(ecs: &World, tick: u64) {
+	let entities = ecs.system_data::<Entities>();
+	let mut moving = ecs.write_storage::<Moving>();
+	let ids = ecs.read_storage::<NetworkId>();
+	let positions = ecs.read_storage::<Position>();
+	let orientations = ecs.read_storage::<Orientation>();
+	let players = ecs.read_storage::<Player>();
+	let mut last_positions = ecs.write_storage::<Last<Position>>();
+	let mut last_orientations = ecs.write_storage::<Last<Orientation>>();
+
+	let mut states = Vec::new();
+	for (entity, &NetworkId(id), pos, ori, _, last_pos, last_ori) in (
+		&entities,
+		&ids,
+		&positions,
+		&orientations,
+		&players,
+		&mut last_positions,
+		&mut last_orientations,
+	)
+		.join()
+	{
+		let rot = Rotation::from_degrees(ori.0.x, ori.0.y);
+		let last_rot = Rotation::from_degrees((last_ori.0).0.x, (last_ori.0).0.y);
+
+		let mut updates = Vec::new();
+		let (update, sent_pos) = WorldUpdate::entity_moved(id, (last_pos.0).0, pos.0, rot);
+		let moved = sent_pos != (last_pos.0).0 || rot != last_rot;
+		match update {
+			WorldUpdate::EntityTeleport { .. } => {
+				updates.push(update);
+				// Teleports carry no rotation, so follow up with one if it changed
+				if rot != last_rot {
+					updates.push(WorldUpdate::EntityTransform {
+						entity: id,
+						pos: (0, 0, 0),
+						rot,
+					});
+				}
+			}
+			// Once it stops, one more update without movement shows it has arrived rather than being late
+			_ if moved || moving.contains(entity) => updates.push(update),
+			_ => {}
+		}
+		if moved {
+			let _ = moving.insert(entity, Moving);
+		} else {
+			moving.remove(entity);
+		}
+		(last_pos.0).0 = sent_pos;
+		last_ori.0 = *ori;
+
+		states.push(EntityState {
+			id,
+			chunk: chunk_at(pos),
+			pos: NetPosition::from_vec3(sent_pos),
+			rot,
+			updates,
+		});
+	}
+
+	let mut clients = ecs.write_storage::<Client>();
+	for (&NetworkId(own_id), client) in (&ids, &mut clients).join() {
+		let mut in_view = HashSet::new();
+		let mut tick_sent = false;
+		for state in &states {
+			if state.id == own_id || !client.loaded_chunks.contains(&state.chunk) {
+				continue;
+			}
+			in_view.insert(state.id);
+			let known = client.known_entities.contains(&state.id);
+			if !tick_sent && (!known || !state.updates.is_empty()) {
+				client.send(ClientBound::Update(WorldUpdate::Tick { tick }));
+				tick_sent = true;
+			}
+			if known {
+				for update in &state.updates {
+					client.send(ClientBound::Update(update.clone()));
+				}
+			} else {
+				client.send(ClientBound::Update(WorldUpdate::SpawnPlayer {
+					entity: state.id,
+					pos: state.pos,
+					rot: state.rot,
+				}));
+			}
+		}
+
+		for gone in client.known_entities.difference(&in_view) {
+			client.send(ClientBound::Update(WorldUpdate::DespawnEntity { entity: *gone }));
+		}
+		client.known_entities = in_view;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing;
+
+	use vek::Vec3;
+
+	#[test]
+	fn tells_clients_when_an_entity_stops() {
+		let mut state = testing::world();
+		let (walker, _) = testing::join(&mut state, Vec3::new(4.5, 1.0, 4.5));
+		let (_, mut watcher) = testing::join(&mut state, Vec3::new(8.5, 1.0, 8.5));
+		let ecs = state.ecs();
+
+		replicate_entities(ecs, 1);
+		assert!(matches!(
+			testing::received(&mut watcher)[..],
+			[
+				ClientBound::Update(WorldUpdate::Tick { tick: 1 }),
+				ClientBound::Update(WorldUpdate::SpawnPlayer { .. })
+			]
+		));
+
+		ecs.write_storage::<Position>().get_mut(walker).unwrap().0.x += 0.25;
+		replicate_entities(ecs, 2);
+		assert!(matches!(
+			testing::received(&mut watcher)[..],
+			[
+				ClientBound::Update(WorldUpdate::Tick { tick: 2 }),
+				ClientBound::Update(WorldUpdate::EntityTransform {
+					pos: (1024, 0, 0),
+					..
+				})
+			]
+		));
+
+		// Stopping is sent once, then nothing more until it moves again
+		replicate_entities(ecs, 3);
+		assert!(matches!(
+			testing::received(&mut watcher)[..],
+			[
+				ClientBound::Update(WorldUpdate::Tick { tick: 3 }),
+				ClientBound::Update(WorldUpdate::EntityTransform { pos: (0, 0, 0), .. })
+			]
+		));
+		replicate_entities(ecs, 4);
+		assert!(testing::received(&mut watcher).is_empty());
+	}
+}
@@ -2,6 +2,7 @@ use std::{
 	fs,
 	net::SocketAddr,
 	path::{Path, PathBuf},
+	time::Duration,
 };
 
 use common::config_root;
@@ -11,31 +12,165 @@ use serde::{Deserialize, Serialize};
 const DEFAULT_PORT: u16 = 7878;
 
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
 	pub server_address: SocketAddr,
+	/// Message of the day, shown in server browsers
+	pub motd: String,
+	/// Most players online at once, operators can join past it
+	pub max_players: u32,
+	/// How far around a player terrain is sent to them, in voxels
 	pub view_distance: u32,
+	/// Where the world is saved, relative paths are inside the config directory
+	pub world_path: PathBuf,
+	/// Seed that a new world is generated from, a saved world keeps the seed it was made with
+	pub seed: u32,
+	pub autosave: AutosaveSettings,
+	pub network: NetworkSettings,
+	pub movement: MovementSettings,
+	pub chat: ChatSettings,
+	/// Directory the settings were loaded from, the access lists and a relative `world_path` are found there
+	#[serde(skip, default = "config_root")]
+	pub config_dir: PathBuf,
 }
 
 impl std::default::Default for Settings {
 	fn default() -> Self {
 		Settings {
 			server_address: SocketAddr::from(([0; 4], DEFAULT_PORT)),
+			motd: "A Takh server".to_owned(),
+			max_players: 20,
 			view_distance: 32,
+			world_path: PathBuf::from("world"),
+			seed: 0,
+			autosave: AutosaveSettings::default(),
+			network: NetworkSettings::default(),
+			movement: MovementSettings::default(),
+			chat: ChatSettings::default(),
+			config_dir: config_root(),
+		}
+	}
+}
+
+/// How often the world is saved while the server runs, it is always saved when the server stops
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutosaveSettings {
+	/// Seconds between saves of the chunks that changed, `None` only saves when the server stops
+	pub interval_secs: Option<u32>,
+	/// Seconds between backups of the whole world, they are taken along with an autosave
+	pub backup_interval_secs: Option<u32>,
+	/// Number of backups kept, the oldest are deleted
+	pub backups: u32,
+}
+
+impl std::default::Default for AutosaveSettings {
+	fn default() -> Self {
+		AutosaveSettings {
+			interval_secs: Some(300),
+			backup_interval_secs: Some(3600),
+			backups: 3,
+		}
+	}
+}
+
+/// How the server notices clients that have gone away
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+	/// Seconds between keep-alive messages sent to each client
+	pub keep_alive_interval_secs: u32,
+	/// Seconds without hearing from a client, or without an answer to a keep-alive, before it is dropped
+	pub timeout_secs: u32,
+}
+
+impl std::default::Default for NetworkSettings {
+	fn default() -> Self {
+		NetworkSettings {
+			keep_alive_interval_secs: 5,
+			timeout_secs: 30,
+		}
+	}
+}
+
+impl NetworkSettings {
+	pub fn keep_alive_interval(&self) -> Duration {
+		Duration::from_secs(self.keep_alive_interval_secs as u64)
+	}
+
+	pub fn timeout(&self) -> Duration {
+		Duration::from_secs(self.timeout_secs as u64)
+	}
+}
+
+/// How strictly player movement is checked
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovementSettings {
+	/// How far a client's inputs may run ahead of real time before they count as speeding, in milliseconds.
+	/// This covers inputs that were held up on the network and then arrive all at once.
+	pub jitter_tolerance_ms: u32,
+	/// Number of violations within `violation_window_secs` that gets a player kicked, `None` only logs them
+	pub kick_after: Option<u32>,
+	pub violation_window_secs: u32,
+}
+
+impl std::default::Default for MovementSettings {
+	fn default() -> Self {
+		MovementSettings {
+			jitter_tolerance_ms: 500,
+			kick_after: Some(10),
+			violation_window_secs: 30,
+		}
+	}
+}
+
+/// Limits on what players can send in chat
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatSettings {
+	/// Longest chat message in characters, longer ones are cut short
+	pub max_length: u32,
+	/// Most messages and commands a player may send within `rate_window_secs`, the rest are refused
+	pub rate_limit: u32,
+	pub rate_window_secs: u32,
+	/// Number of messages within the window, refused ones included, that gets a player kicked for spamming,
+	/// `None` never kicks
+	pub kick_after: Option<u32>,
+}
+
+impl std::default::Default for ChatSettings {
+	fn default() -> Self {
+		ChatSettings {
+			max_length: 256,
+			rate_limit: 5,
+			rate_window_secs: 5,
+			kick_after: Some(15),
 		}
 	}
 }
 
 impl Settings {
 	pub fn load() -> Settings {
-		let mut path = Self::settings_path();
+		Self::load_from(&Self::settings_path())
+	}
+
+	/// Loads the settings from a file of our choosing, creating it if it doesn't exist
+	pub fn load_from(path: &Path) -> Settings {
+		let mut settings = Self::read_or_create(path);
+		if let Some(dir) = path.parent() {
+			settings.config_dir = dir.to_owned();
+		}
+		settings
+	}
 
-		if let Ok(file) = fs::File::open(&path) {
+	fn read_or_create(path: &Path) -> Settings {
+		if let Ok(file) = fs::File::open(path) {
 			match ron::de::from_reader::<_, Self>(file) {
 				Ok(s) => s,
 				Err(e) => {
 					let template_settings = Self::default();
-					path.pop();
-					path.push("Server_settings.template.ron");
+					let path = path.with_extension("template.ron");
 					log::warn!(
 						"Failed to parse Server Settings, Falling back to default: {:?}",
 						e
@@ -52,7 +187,7 @@ impl Settings {
 			}
 		} else {
 			let default_settings = Self::default();
-			if let Err(e) = default_settings.save_to_file(&path) {
+			if let Err(e) = default_settings.save_to_file(path) {
 				log::warn!("Failed to create default server settings file: {:?}", e);
 			}
 			default_settings
@@ -74,9 +209,43 @@ impl Settings {
 		fs::write(path, stringify.as_bytes())
 	}
 
+	/// The directory the world is saved in
+	pub fn world_dir(&self) -> PathBuf {
+		self.config_dir.join(&self.world_path)
+	}
+
 	pub fn settings_path() -> PathBuf {
 		let mut path = config_root();
 		path.push("Server_settings.ron");
 		path
 	}
+
+	/// Where the server inside the client keeps its settings. It is a directory of its own so that
+	/// singleplayer doesn't share a world or access lists with a dedicated server run from the same place.
+	pub fn singleplayer_path() -> PathBuf {
+		let mut path = config_root();
+		path.push("singleplayer");
+		path.push("Server_settings.ron");
+		path
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn missing_settings_take_their_defaults() {
+		// A file from before most settings existed
+		let settings: Settings =
+			ron::de::from_str("(server_address: \"127.0.0.1:7000\", autosave: (backups: 5))").unwrap();
+		assert_eq!(settings.server_address, SocketAddr::from(([127, 0, 0, 1], 7000)));
+		assert_eq!(settings.autosave.backups, 5);
+		assert_eq!(
+			settings.autosave.interval_secs,
+			AutosaveSettings::default().interval_secs
+		);
+		assert_eq!(settings.view_distance, Settings::default().view_distance);
+		assert_eq!(settings.chat.max_length, ChatSettings::default().max_length);
+	}
 }
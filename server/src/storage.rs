@@ -0,0 +1,338 @@
+use common::{
+	ecsres::TimeOfDay,
+	net::{world::WorldData, VPosition},
+	world::terrain::Terrain,
+};
+
+use serde::{Deserialize, Serialize};
+use std::{
+	fs,
+	io::{self, Write},
+	path::{Path, PathBuf},
+	thread,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// What is kept about a world apart from its chunks
+#[derive(Serialize, Deserialize)]
+pub struct WorldInfo {
+	pub seed: u32,
+	/// Ticks of `TimeOfDay`
+	pub time: u64,
+}
+
+/// A chunk as it is saved, the same as `WorldData::ChunkData` without the position which is in the file name
+#[derive(Serialize, Deserialize)]
+struct ChunkFile {
+	palette: Vec<u32>,
+	voxels: Vec<u8>,
+}
+
+/// The parts of the world that need writing, taken on the tick thread so they can be written out elsewhere
+pub struct WorldSnapshot {
+	info: WorldInfo,
+	chunks: Vec<((i32, i32, i32), ChunkFile)>,
+}
+
+impl WorldSnapshot {
+	/// Copies out the chunks that changed since the last save, they count as saved from now on
+	pub fn take(terrain: &mut Terrain, time: TimeOfDay) -> WorldSnapshot {
+		let chunks = terrain
+			.take_dirty()
+			.into_iter()
+			.filter_map(|coord| match terrain.chunk_data(coord) {
+				Some(WorldData::ChunkData { palette, voxels, .. }) => {
+					Some((coord, ChunkFile { palette, voxels }))
+				}
+				_ => None,
+			})
+			.collect();
+		WorldSnapshot {
+			info: WorldInfo {
+				seed: terrain.seed,
+				time: time.0,
+			},
+			chunks,
+		}
+	}
+
+	/// Coordinates of the chunks in the snapshot
+	pub fn coords(&self) -> Vec<(i32, i32, i32)> {
+		self.chunks.iter().map(|(coord, _)| *coord).collect()
+	}
+}
+
+/// A save being written on another thread
+pub struct BackgroundSave {
+	handle: thread::JoinHandle<io::Result<usize>>,
+	/// Chunks being saved, to save again if writing them fails
+	coords: Vec<(i32, i32, i32)>,
+}
+
+impl BackgroundSave {
+	pub fn is_finished(&self) -> bool {
+		self.handle.is_finished()
+	}
+
+	/// Waits for the save to be written, returning the number of chunks saved. The chunks are marked as
+	/// needing to be saved again if it failed.
+	pub fn finish(self, terrain: &mut Terrain) -> io::Result<usize> {
+		let result = self
+			.handle
+			.join()
+			.unwrap_or_else(|_| Err(io::Error::other("the save thread panicked")));
+		if result.is_err() {
+			for coord in self.coords {
+				terrain.mark_dirty(coord);
+			}
+		}
+		result
+	}
+}
+
+/// A world saved on disk, a `world.ron` with its `WorldInfo` and a file for every chunk that has been
+/// changed or pregenerated
+#[derive(Clone)]
+pub struct WorldStorage {
+	path: PathBuf,
+}
+
+impl WorldStorage {
+	pub fn new(path: PathBuf) -> WorldStorage {
+		WorldStorage { path }
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Reads the world's info, `None` if the world hasn't been saved before
+	pub fn load_info(&self) -> Option<WorldInfo> {
+		let file = fs::File::open(self.path.join("world.ron")).ok()?;
+		match ron::de::from_reader(file) {
+			Ok(info) => Some(info),
+			Err(e) => {
+				log::warn!("Failed to read world info, starting it afresh: {:?}", e);
+				None
+			}
+		}
+	}
+
+	/// Loads the chunk at `coord` into `terrain` if it has been saved, returns whether it was
+	pub fn load_chunk(&self, terrain: &mut Terrain, coord: (i32, i32, i32)) -> bool {
+		let data = match fs::read(self.chunk_path(coord)) {
+			Ok(data) => data,
+			Err(_) => return false,
+		};
+		let chunk: ChunkFile = match bincode::deserialize(&data) {
+			Ok(chunk) => chunk,
+			Err(e) => {
+				log::warn!(
+					"Failed to read saved chunk {:?}, it will be generated again: {:?}",
+					coord,
+					e
+				);
+				return false;
+			}
+		};
+		let pos = VPosition::new(coord.0, coord.1, coord.2);
+		if terrain.insert_chunk_data(pos, &chunk.palette, chunk.voxels) {
+			true
+		} else {
+			log::warn!("Saved chunk {:?} is invalid, it will be generated again", coord);
+			false
+		}
+	}
+
+	/// Writes the world info and the chunks in the snapshot, returns the number of chunks saved
+	pub fn write(&self, snapshot: &WorldSnapshot) -> io::Result<usize> {
+		fs::create_dir_all(self.path.join("chunks"))?;
+		let info = ron::ser::to_string_pretty(
+			&snapshot.info,
+			ron::ser::PrettyConfig::default().with_indentor("\t".to_owned()),
+		)
+		.map_err(io::Error::other)?;
+		write_atomic(&self.path.join("world.ron"), info.as_bytes())?;
+
+		for (coord, chunk) in &snapshot.chunks {
+			let data = bincode::serialize(chunk).map_err(io::Error::other)?;
+			write_atomic(&self.chunk_path(*coord), &data)?;
+		}
+		Ok(snapshot.chunks.len())
+	}
+
+	/// Writes the snapshot on another thread, then backs the world up if `backups` is more than zero,
+	/// keeping that many of the newest backups
+	pub fn write_in_background(&self, snapshot: WorldSnapshot, backups: u32) -> io::Result<BackgroundSave> {
+		let storage = self.clone();
+		let coords = snapshot.coords();
+		let handle = thread::Builder::new()
+			.name("world-save".to_owned())
+			.spawn(move || {
+				let saved = storage.write(&snapshot)?;
+				if backups > 0 {
+					if let Err(e) = storage.backup(backups) {
+						log::warn!("Failed to back up the world: {:?}", e);
+					}
+				}
+				Ok(saved)
+			})?;
+		Ok(BackgroundSave { handle, coords })
+	}
+
+	/// Copies the saved world into its backup directory, then deletes the oldest backups past `keep`
+	fn backup(&self, keep: u32) -> io::Result<()> {
+		let dir = self.backup_dir();
+		let secs = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|t| t.as_secs())
+			.unwrap_or(0);
+		let backup = dir.join(format!("backup-{}", secs));
+		copy_dir(&self.path, &backup)?;
+		log::info!("Backed the world up to {}", backup.display());
+
+		let mut backups: Vec<(u64, PathBuf)> = fs::read_dir(&dir)?
+			.filter_map(|entry| {
+				let path = entry.ok()?.path();
+				let secs = path
+					.file_name()?
+					.to_str()?
+					.strip_prefix("backup-")?
+					.parse()
+					.ok()?;
+				Some((secs, path))
+			})
+			.collect();
+		backups.sort();
+		let old = backups.len().saturating_sub(keep as usize);
+		for (_, path) in backups.drain(..old) {
+			fs::remove_dir_all(path)?;
+		}
+		Ok(())
+	}
+
+	/// Backups go next to the world, in a directory named after it
+	fn backup_dir(&self) -> PathBuf {
+		let mut name = self.path.file_name().unwrap_or_default().to_owned();
+		name.push("-backups");
+		self.path.with_file_name(name)
+	}
+
+	fn chunk_path(&self, coord: (i32, i32, i32)) -> PathBuf {
+		self.path
+			.join("chunks")
+			.join(format!("{}_{}_{}.chunk", coord.0, coord.1, coord.2))
+	}
+}
+
+/// Writes a file by way of a temporary file next to it, so that stopping part way through leaves the old
+/// file whole rather than half written
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+	let mut temp = path.as_os_str().to_owned();
+	temp.push(".tmp");
+	let temp = PathBuf::from(temp);
+	{
+		let mut file = fs::File::create(&temp)?;
+		file.write_all(data)?;
+		file.sync_all()?;
+	}
+	fs::rename(&temp, path)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+	fs::create_dir_all(to)?;
+	for entry in fs::read_dir(from)? {
+		let entry = entry?;
+		let target = to.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			copy_dir(&entry.path(), &target)?;
+		} else {
+			fs::copy(entry.path(), target)?;
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing;
+
+	fn terrain() -> Terrain {
+		let mut palette = common::world::terrain::WorldPalette::new();
+		palette.add_voxel(common::world::voxel::Voxel::new_full());
+		Terrain::new(palette)
+	}
+
+	#[test]
+	fn only_changed_chunks_are_saved() {
+		let mut terrain = terrain();
+		terrain.get_or_generate((0, 0, 0));
+		terrain.get_or_generate((1, 0, 0));
+		assert!(WorldSnapshot::take(&mut terrain, TimeOfDay(0))
+			.coords()
+			.is_empty());
+
+		terrain.set_voxel(VPosition::new(40, 1, 1), 1);
+		assert_eq!(
+			WorldSnapshot::take(&mut terrain, TimeOfDay(0)).coords(),
+			vec![(1, 0, 0)]
+		);
+		// Taking a snapshot counts the chunks as saved
+		assert!(WorldSnapshot::take(&mut terrain, TimeOfDay(0))
+			.coords()
+			.is_empty());
+	}
+
+	#[test]
+	fn saved_chunks_load_as_they_were() {
+		let storage = WorldStorage::new(testing::temp_dir("storage-chunks").join("world"));
+		let mut terrain = terrain();
+		terrain.get_or_generate((0, 0, 0));
+		terrain.set_voxel(VPosition::new(3, 4, 5), 1);
+		terrain.set_voxel(VPosition::new(3, 5, 5), 0);
+		assert_eq!(
+			storage
+				.write(&WorldSnapshot::take(&mut terrain, TimeOfDay(42)))
+				.unwrap(),
+			1
+		);
+
+		let mut loaded = self::terrain();
+		assert!(storage.load_chunk(&mut loaded, (0, 0, 0)));
+		assert!(!storage.load_chunk(&mut loaded, (1, 0, 0)));
+		assert_eq!(loaded.voxel_id(VPosition::new(3, 4, 5)), Some(1));
+		assert_eq!(loaded.voxel_id(VPosition::new(3, 5, 5)), Some(0));
+		assert_eq!(storage.load_info().unwrap().time, 42);
+		// Nothing is left half written
+		let leftovers = fs::read_dir(storage.path().join("chunks"))
+			.unwrap()
+			.filter(|e| e.as_ref().unwrap().path().extension().is_some_and(|e| e == "tmp"))
+			.count();
+		assert_eq!(leftovers, 0);
+	}
+
+	#[test]
+	fn backups_past_the_limit_are_deleted_oldest_first() {
+		let storage = WorldStorage::new(testing::temp_dir("storage-backups").join("world"));
+		storage
+			.write(&WorldSnapshot::take(&mut terrain(), TimeOfDay(0)))
+			.unwrap();
+		let backups = storage.backup_dir();
+		for old in ["backup-1", "backup-2", "backup-3"].iter() {
+			fs::create_dir_all(backups.join(old)).unwrap();
+		}
+		fs::write(backups.join("notes.txt"), "not a backup").unwrap();
+
+		storage.backup(2).unwrap();
+		let left: Vec<String> = fs::read_dir(&backups)
+			.unwrap()
+			.map(|e| e.unwrap().file_name().into_string().unwrap())
+			.filter(|name| !["backup-3", "notes.txt"].contains(&name.as_str()))
+			.collect();
+		assert!(backups.join("backup-3").exists() && backups.join("notes.txt").exists());
+		// What is left is the backup just made
+		assert_eq!(left.len(), 1);
+		assert!(backups.join(&left[0]).join("world.ron").exists());
+	}
+}
@@ -0,0 +1,158 @@
+use crate::{client::Client, storage::WorldStorage};
+
+use common::{
+	components::Position,
+	net::{
+		server::{ClientBound, WorldUpdate},
+		world::WorldData,
+		VPosition,
+	},
+	world::terrain::Terrain,
+};
+
+use specs::{Join, World, WorldExt};
+use std::collections::HashSet;
+
+/// Most chunks sent to a single client in one tick, generation is slow so this keeps ticks short
+const CHUNKS_PER_TICK: usize = 8;
+
+/// Coordinate of the chunk that contains a world position
+pub fn chunk_at(pos: &Position) -> (i32, i32, i32) {
+	let voxel = VPosition::new(
+		pos.0.x.floor() as i32,
+		pos.0.y.floor() as i32,
+		pos.0.z.floor() as i32,
+	);
+	Terrain::split_pos(voxel).0
+}
+
+/// Coordinates of the chunks within `distance` voxels of the chunk `centre`
+pub fn chunks_around(centre: (i32, i32, i32), distance: u32) -> HashSet<(i32, i32, i32)> {
+	let (radius_h, radius_v) = Terrain::chunk_radius(distance);
+	let mut chunks = HashSet::new();
+	for x in -radius_h..=radius_h {
+		for z in -radius_h..=radius_h {
+			for y in -radius_v..=radius_v {
+				chunks.insert((centre.0 + x, centre.1 + y, centre.2 + z));
+			}
+		}
+	}
+	chunks
+}
+
+/// Makes sure the chunk at `coord` is loaded, reading it from the saved world or generating it. Returns
+/// whether it had to be generated.
+pub fn load_chunk(terrain: &mut Terrain, storage: &WorldStorage, coord: (i32, i32, i32)) -> bool {
+	if terrain.chunk(coord).is_some() || storage.load_chunk(terrain, coord) {
+		return false;
+	}
+	terrain.get_or_generate(coord);
+	true
+}
+
+/// Sends every client the chunks that have come into their view distance and tells them to unload the
+/// ones that have left it, loading or generating chunks on the way if needed. Clients that asked for less
+/// than `view_distance` get what they asked for.
+pub fn update_chunk_interest(ecs: &World, storage: &WorldStorage, view_distance: u32) {
+	let mut terrain = ecs.write_resource::<Terrain>();
+	let mut clients = ecs.write_storage::<Client>();
+	let positions = ecs.read_storage::<Position>();
+
+	for (client, pos) in (&mut clients, &positions).join() {
+		let centre = chunk_at(pos);
+		let distance = client
+			.view_distance
+			.map_or(view_distance, |d| d.min(view_distance));
+		let wanted = chunks_around(centre, distance);
+
+		// Unload anything that has fallen out of view
+		let unload: Vec<_> = client
+			.loaded_chunks
+			.iter()
+			.filter(|c| !wanted.contains(c))
+			.copied()
+			.collect();
+		for coord in unload {
+			client.loaded_chunks.remove(&coord);
+			client.send(ClientBound::Data(WorldData::UnloadChunk {
+				pos: VPosition::new(coord.0, coord.1, coord.2),
+			}));
+		}
+
+		// Send the closest missing chunks first
+		let mut wanted: Vec<_> = wanted.difference(&client.loaded_chunks).copied().collect();
+		wanted.sort_by_key(|c| (c.0 - centre.0).pow(2) + (c.1 - centre.1).pow(2) + (c.2 - centre.2).pow(2));
+		for coord in wanted.into_iter().take(CHUNKS_PER_TICK) {
+			load_chunk(&mut terrain, storage, coord);
+			if let Some(data) = terrain.chunk_data(coord) {
+				client.send(ClientBound::Data(data));
+				client.loaded_chunks.insert(coord);
+			}
+		}
+	}
+}
+
+/// Unloads the chunks that no client has loaded. Chunks with changes that haven't been saved yet are kept
+/// until they are.
+pub fn unload_unused_chunks(ecs: &World) {
+	let mut terrain = ecs.write_resource::<Terrain>();
+	let clients = ecs.read_storage::<Client>();
+	let used: HashSet<_> = clients
+		.join()
+		.flat_map(|c| c.loaded_chunks.iter().copied())
+		.collect();
+	let unused: Vec<_> = terrain
+		.chunk_coords()
+		.filter(|c| !used.contains(c) && !terrain.is_dirty(*c))
+		.collect();
+	for coord in unused {
+		terrain.remove_chunk(coord);
+	}
+}
+
+/// Sends a voxel change to every client that has the voxel's chunk loaded
+pub fn broadcast_voxel(ecs: &World, pos: VPosition, voxel_id: u32) {
+	let (coord, _) = Terrain::split_pos(pos);
+	for client in ecs.read_storage::<Client>().join() {
+		if client.loaded_chunks.contains(&coord) {
+			client.send(ClientBound::Update(WorldUpdate::BlockChange { pos, voxel_id }));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing;
+	use vek::Vec3;
+
+	#[test]
+	fn unloads_chunks_nobody_has_loaded() {
+		let mut state = testing::world();
+		let (entity, _receiver) = testing::join(&mut state, Vec3::new(4.5, 1.0, 4.5));
+		{
+			let mut terrain = state.ecs().write_resource::<Terrain>();
+			terrain.get_or_generate((5, 0, 0));
+			terrain.get_or_generate((6, 0, 0));
+			terrain.take_dirty();
+			// Changed since the last save
+			terrain.mark_dirty((6, 0, 0));
+		}
+
+		unload_unused_chunks(state.ecs());
+		let mut loaded: Vec<_> = state.ecs().read_resource::<Terrain>().chunk_coords().collect();
+		loaded.sort_unstable();
+		assert_eq!(loaded, vec![(0, 0, 0), (6, 0, 0)]);
+
+		state
+			.ecs()
+			.write_storage::<Client>()
+			.get_mut(entity)
+			.unwrap()
+			.loaded_chunks
+			.clear();
+		state.ecs().write_resource::<Terrain>().take_dirty();
+		unload_unused_chunks(state.ecs());
+		assert_eq!(state.ecs().read_resource::<Terrain>().chunk_coords().count(), 0);
+	}
+}
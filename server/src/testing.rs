@@ -0,0 +1,103 @@
+//! Worlds and players for the server's tests, set up the way the server does it
+
+use crate::client::Client;
+
+use common::{
+	components::{Last, NetworkId, OnGround, Orientation, Player, Position, Velocity},
+	net::{server::ClientBound, VPosition},
+	physics::Body,
+	state::State,
+	world::{
+		chunk::{Chunk, Palette},
+		terrain::{Terrain, WorldPalette},
+		voxel::Voxel,
+	},
+};
+
+use specs::{Builder, Entity, WorldExt};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{
+	atomic::{AtomicU32, Ordering},
+	Arc,
+};
+use std::task::{Context, Poll, Wake, Waker};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use vek::Vec3;
+
+/// World voxel id of the one solid voxel in the test world
+pub const STONE: u32 = 1;
+
+/// An empty directory for a test to keep its files in, left over files from earlier runs are removed
+pub fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("takh-test-{}-{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// A server world holding the chunk at the origin, which is air apart from a floor of stone at `y = 0`
+pub fn world() -> State {
+	let mut state = State::server();
+	state.ecs_mut().register::<Client>();
+	state.ecs_mut().register::<crate::replication::Moving>();
+
+	let mut palette = WorldPalette::new();
+	palette.add_voxel(Voxel::new_full());
+	let mut terrain = Terrain::new(palette);
+	terrain.insert_chunk(Chunk::new((0, 0, 0), Palette::new()));
+	for x in 0..Chunk::WIDTH as i32 {
+		for z in 0..Chunk::DEPTH as i32 {
+			terrain.set_voxel(VPosition::new(x, 0, z), STONE);
+		}
+	}
+	terrain.take_dirty();
+	state.ecs_mut().insert(terrain);
+	state
+}
+
+/// Logs a player in at `pos` with the chunk at the origin loaded, giving the messages sent to them
+pub fn join(state: &mut State, pos: Vec3<f64>) -> (Entity, UnboundedReceiver<ClientBound>) {
+	let (sender, receiver) = mpsc::unbounded_channel();
+	// The connection tasks are never run, the client only needs something to hold on to
+	let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+	let (writer, reader) = (runtime.spawn(async {}), runtime.spawn(async {}));
+	let mut client = Client::new(0, "Tester".to_owned(), None, sender, writer, reader);
+	client.loaded_chunks.insert((0, 0, 0));
+	client.last_valid = Body {
+		pos,
+		on_ground: true,
+		..Body::default()
+	};
+
+	static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+	let entity = state
+		.ecs_mut()
+		.create_entity()
+		.with(NetworkId(NEXT_ID.fetch_add(1, Ordering::Relaxed)))
+		.with(Position(pos))
+		.with(Last(Position(pos)))
+		.with(Orientation::default())
+		.with(Last(Orientation::default()))
+		.with(Velocity::default())
+		.with(OnGround(true))
+		.with(Player)
+		.with(client)
+		.build();
+	(entity, receiver)
+}
+
+/// Every message waiting in `receiver`
+pub fn received(receiver: &mut UnboundedReceiver<ClientBound>) -> Vec<ClientBound> {
+	struct Noop;
+	impl Wake for Noop {
+		fn wake(self: Arc<Self>) {}
+	}
+	let waker = Waker::from(Arc::new(Noop));
+	let mut context = Context::from_waker(&waker);
+	let mut messages = Vec::new();
+	while let Poll::Ready(Some(message)) = receiver.poll_recv(&mut context) {
+		messages.push(message);
+	}
+	messages
+}